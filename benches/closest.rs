@@ -0,0 +1,119 @@
+//! Benchmarks of building and querying trees, over synthetic data that is
+//! the same on every run.
+//!
+//! Run them with `cargo bench`. To compare against an earlier release, save
+//! a baseline on it with `cargo bench -- --save-baseline before`, and run
+//! `cargo bench -- --baseline before` on the new one: criterion reports the
+//! change of every scenario against the saved numbers.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use closest::{Data, KDTree, Point, SquaredEuclideanDistance};
+
+/// Uniform points in the unit cube, from a fixed seed.
+fn points(n: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed.max(1);
+    (0..n)
+        .map(|_| {
+            (0..dimension)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state >> 40) as f32 / (1u64 << 24) as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn records(n: usize, dimension: usize) -> Vec<Data<usize>> {
+    points(n, dimension, 42)
+        .into_iter()
+        .enumerate()
+        .map(|(i, p)| Data::new(i, p))
+        .collect()
+}
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+    for n in [1_000, 10_000, 100_000] {
+        let data = records(n, 3);
+        group.throughput(Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(n), &data, |b, data| {
+            b.iter(|| KDTree::from_vec(data.clone(), 16).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn query_k(c: &mut Criterion) {
+    let tree = KDTree::from_vec(records(100_000, 3), 16).unwrap();
+    let queries = points(1_000, 3, 7)
+        .into_iter()
+        .map(Point::new)
+        .collect::<Vec<_>>();
+    let metric = SquaredEuclideanDistance::default();
+    let mut group = c.benchmark_group("query_k");
+    for k in [1, 10, 100] {
+        group.throughput(Throughput::Elements(queries.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(k), &k, |b, k| {
+            b.iter(|| {
+                for query in &queries {
+                    black_box(tree.get_nearest_neighbors(query, *k, &metric));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn query_dimension(c: &mut Criterion) {
+    let metric = SquaredEuclideanDistance::default();
+    let mut group = c.benchmark_group("query_dimension");
+    for dimension in [2, 3, 8, 16] {
+        let tree = KDTree::from_vec(records(100_000, dimension), 16).unwrap();
+        let queries = points(1_000, dimension, 7)
+            .into_iter()
+            .map(Point::new)
+            .collect::<Vec<_>>();
+        group.throughput(Throughput::Elements(queries.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(dimension),
+            &queries,
+            |b, queries| {
+                b.iter(|| {
+                    for query in queries {
+                        black_box(tree.get_nearest_neighbors(query, 10, &metric));
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let tree = KDTree::from_vec(records(100_000, 3), 16).unwrap();
+    let queries = points(10_000, 3, 7)
+        .into_iter()
+        .map(Point::new)
+        .collect::<Vec<_>>();
+    let metric = SquaredEuclideanDistance::default();
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function("input_order", |b| {
+        b.iter(|| {
+            queries
+                .iter()
+                .map(|query| tree.get_nearest_neighbors(query, 10, &metric))
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("curve_order", |b| {
+        b.iter(|| tree.get_nearest_neighbors_all(&queries, 10, &metric))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, build, query_k, query_dimension, batch);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+//! An example looking for perceptually similar colors
+
+use std::error::Error;
+
+use closest::{srgb_to_lab, Data, DeltaE2000, KDTree, Point};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // RGB colors, converted to CIELAB coordinates
+    let colors = vec![
+        Data::new("blue", srgb_to_lab(0., 0., 255.)),
+        Data::new("red", srgb_to_lab(255., 0., 0.)),
+        Data::new("navy", srgb_to_lab(17., 4., 89.)),
+        Data::new("purple", srgb_to_lab(171., 3., 255.)),
+        Data::new("light-blue", srgb_to_lab(61., 118., 224.)),
+        Data::new("pink", srgb_to_lab(255., 3., 213.)),
+        Data::new("yellow", srgb_to_lab(255., 234., 0.)),
+        Data::new("green", srgb_to_lab(16., 145., 25.)),
+        Data::new("orange", srgb_to_lab(255., 106., 0.)),
+    ];
+    let tree = KDTree::from_vec(colors, 1)?;
+    let point = Point::new(srgb_to_lab(237., 139., 69.)); // Light Orange
+    let closest_colors = tree.get_nearest_neighbors(&point, 2, &DeltaE2000::default());
+    println!("The perceptually nearest colors to light orange.");
+    for color in closest_colors {
+        println!("color: {}, delta e 2000: {}", color.data, color.distance);
+    }
+    Ok(())
+}
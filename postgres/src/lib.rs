@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use closest::{Data, KDTree, Point, SpatialIndex, SquaredEuclideanDistance};
+use pgrx::prelude::*;
+
+pgrx::pg_module_magic!();
+
+thread_local! {
+    /// Trees built in this backend, by name. Each connection keeps its own,
+    /// and they are gone when it closes.
+    static TREES: RefCell<HashMap<String, KDTree<i64>>> = RefCell::new(HashMap::new());
+}
+
+/// Build a tree named `name` from a query returning an id and a point, as
+/// `bigint` and `real[]` columns, for example
+/// `SELECT id, ARRAY[x, y] FROM stores`. Rows with a null id or point are
+/// skipped. A tree with the same name is replaced. Returns the number of
+/// points indexed.
+#[pg_extern]
+fn closest_build(name: &str, query: &str, min_points: default!(i32, 16)) -> i64 {
+    let records = Spi::connect(|client| {
+        let mut records = Vec::new();
+        for row in client.select(query, None, None)? {
+            let id = row.get::<i64>(1)?;
+            let point = row.get::<Vec<f32>>(2)?;
+            if let (Some(id), Some(point)) = (id, point) {
+                records.push(Data::new(id, point));
+            }
+        }
+        Ok::<_, pgrx::spi::Error>(records)
+    })
+    .unwrap_or_else(|e| error!("closest_build query failed: {}", e));
+    if let Some(dimension) = records.first().map(|r| r.point().shape()) {
+        if records.iter().any(|r| r.point().shape() != dimension) {
+            error!(
+                "closest_build points must all have {} coordinates",
+                dimension
+            );
+        }
+    }
+    let count = records.len() as i64;
+    let tree = KDTree::from_vec(records, min_points.max(1) as usize)
+        .unwrap_or_else(|e| error!("closest_build failed: {}", e));
+    TREES.with(|trees| trees.borrow_mut().insert(name.to_string(), tree));
+    count
+}
+
+/// Drop the tree named `name`, returning whether it existed.
+#[pg_extern]
+fn closest_drop(name: &str) -> bool {
+    TREES.with(|trees| trees.borrow_mut().remove(name).is_some())
+}
+
+/// Run `query` against the tree named `name`, raising an error if there is
+/// none.
+fn with_tree<R>(name: &str, query: impl FnOnce(&KDTree<i64>) -> R) -> R {
+    TREES.with(|trees| match trees.borrow().get(name) {
+        Some(tree) => query(tree),
+        None => error!("no tree named {}, build it with closest_build", name),
+    })
+}
+
+/// The `k` points of tree `name` nearest to `point`, nearest first, with
+/// their euclidean distances.
+#[pg_extern]
+fn closest_knn(
+    name: &str,
+    point: Vec<f32>,
+    k: i32,
+) -> TableIterator<'static, (name!(id, i64), name!(distance, f32))> {
+    let found = with_tree(name, |tree| {
+        tree.get_nearest_neighbors(
+            &Point::new(point),
+            k.max(0) as usize,
+            &SquaredEuclideanDistance::default(),
+        )
+    });
+    TableIterator::new(found.into_iter().map(|n| (n.data, n.distance.sqrt())))
+}
+
+/// Every point of tree `name` within euclidean distance `radius` of `point`,
+/// nearest first.
+#[pg_extern]
+fn closest_within_radius(
+    name: &str,
+    point: Vec<f32>,
+    radius: f32,
+) -> TableIterator<'static, (name!(id, i64), name!(distance, f32))> {
+    let found = with_tree(name, |tree| {
+        tree.neighbors_within_radius(&Point::new(point), radius.powi(2))
+    });
+    TableIterator::new(found.into_iter().map(|n| (n.data, n.distance.sqrt())))
+}
+
+#[cfg(any(test, feature = "pg_test"))]
+#[pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn build_and_query() {
+        Spi::run("CREATE TABLE points (id bigint, x real, y real)").unwrap();
+        Spi::run("INSERT INTO points SELECT i, i % 10, i / 10 FROM generate_series(0, 99) i")
+            .unwrap();
+        let count = crate::closest_build("grid", "SELECT id, ARRAY[x, y] FROM points", 16);
+        assert_eq!(count, 100);
+        let nearest =
+            Spi::get_one::<i64>("SELECT id FROM closest_knn('grid', ARRAY[3.1, 4.2]::real[], 1)");
+        assert_eq!(nearest, Ok(Some(43)));
+        let within = Spi::get_one::<i64>(
+            "SELECT count(*) FROM closest_within_radius('grid', ARRAY[5, 5]::real[], 1)",
+        );
+        assert_eq!(within, Ok(Some(5)));
+        assert!(crate::closest_drop("grid"));
+    }
+}
+
+/// Required by `cargo pgrx test`.
+#[cfg(test)]
+pub mod pg_test {
+    pub fn setup(_options: Vec<&str>) {}
+
+    pub fn postgresql_conf_options() -> Vec<&'static str> {
+        vec![]
+    }
+}
@@ -1,5 +1,7 @@
 from __future__ import annotations
 
+from typing import Any, Literal
+
 class KDTree:
     """Simple KDTree Implementation"""
 
@@ -11,3 +13,54 @@ class KDTree:
     ) -> list[tuple[str | int | float, float]]:
         """Get k nearest neighbors."""
         ...
+    def save(self, path: str) -> None:
+        """Save the tree, to be opened with MappedKDTree."""
+        ...
+    def get_nearest_neighbors_batch(
+        self,
+        points: list[list[float]],
+        k: int = 1,
+        output: Literal["list", "columns", "dataframe"] = "list",
+    ) -> Any:
+        """Get k nearest neighbors to each point.
+
+        With output="columns" a dict with the columns query_idx,
+        neighbor_rank, distance and payload is returned, and with
+        output="dataframe" a pandas DataFrame of those columns.
+        """
+        ...
+
+class MappedKDTree:
+    """Read only KDTree queried from a memory mapped file written by
+    KDTree.save. Worker processes that open the same file share its memory."""
+
+    def __init__(self, path: str): ...
+    def get_nearest_neighbors(
+        self, point: list[float], k: int = 1
+    ) -> list[tuple[float, str | int | float]]:
+        """Get k nearest neighbors."""
+        ...
+    def __len__(self) -> int: ...
+
+class KNeighborsClassifier:
+    """K nearest neighbors classifier, with votes counted in Rust."""
+
+    classes_: list[str | int | float]
+
+    def __init__(
+        self,
+        n_neighbors: int = 5,
+        weights: Literal["uniform", "distance"] = "uniform",
+        min_points: int = 30,
+    ): ...
+    def fit(
+        self, x: list[list[float]], y: list[str | int | float]
+    ) -> KNeighborsClassifier:
+        """Build the tree from training points and their labels."""
+        ...
+    def predict(self, x: list[list[float]]) -> list[str | int | float]:
+        """Predict the label of each point."""
+        ...
+    def predict_proba(self, x: list[list[float]]) -> list[list[float]]:
+        """Share of the votes for each class, ordered as classes_."""
+        ...
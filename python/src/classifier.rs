@@ -0,0 +1,149 @@
+//! A k nearest neighbors classifier, with the interface of scikit-learn's
+//! `KNeighborsClassifier`. Votes are counted in Rust, so only the predicted
+//! labels or probabilities cross back into Python.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::{to_py_err, DataType};
+
+#[derive(Clone, Copy)]
+enum Weights {
+    Uniform,
+    Distance,
+}
+
+#[pyclass]
+pub struct KNeighborsClassifier {
+    n_neighbors: usize,
+    weights: Weights,
+    min_points: usize,
+    classes: Vec<DataType>,
+    /// Records hold the position of their label in `classes`.
+    tree: Option<closest_rust::KDTree<usize>>,
+}
+
+impl KNeighborsClassifier {
+    fn tree(&self) -> PyResult<&closest_rust::KDTree<usize>> {
+        self.tree
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("fit must be called before predicting"))
+    }
+
+    /// Weighted share of the votes for each class, among the nearest
+    /// neighbors of a point. Points without the training points' number of
+    /// coordinates, or with coordinates that are not finite, raise a
+    /// ValueError.
+    fn votes(&self, point: Vec<f32>) -> PyResult<Vec<f64>> {
+        let neighbors = self
+            .tree()?
+            .try_get_nearest_neighbors(
+                &closest_rust::Point::new(point),
+                self.n_neighbors,
+                &closest_rust::SquaredEuclideanDistance::default(),
+            )
+            .map_err(to_py_err)?;
+        let mut votes = vec![0.; self.classes.len()];
+        // Exact matches outweigh every other neighbor, as in scikit-learn.
+        let exact = neighbors.iter().any(|n| n.distance == 0.);
+        for n in &neighbors {
+            votes[n.data] += match self.weights {
+                Weights::Uniform => 1.,
+                Weights::Distance if exact => (n.distance == 0.) as u8 as f64,
+                Weights::Distance => 1. / (n.distance as f64).sqrt(),
+            };
+        }
+        let total = votes.iter().sum::<f64>();
+        if total > 0. {
+            votes.iter_mut().for_each(|v| *v /= total);
+        }
+        Ok(votes)
+    }
+}
+
+#[pymethods]
+impl KNeighborsClassifier {
+    /// Instantiate a new classifier. Weights is either "uniform", where every
+    /// neighbor has an equal vote, or "distance", where votes are weighted by
+    /// the inverse of the neighbor's euclidean distance.
+    #[new]
+    #[pyo3(signature = (n_neighbors=5, weights="uniform", min_points=30))]
+    fn new(n_neighbors: usize, weights: &str, min_points: usize) -> PyResult<Self> {
+        let weights = match weights {
+            "uniform" => Weights::Uniform,
+            "distance" => Weights::Distance,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "weights must be one of 'uniform' or 'distance', found '{}'",
+                    weights
+                )))
+            }
+        };
+        Ok(KNeighborsClassifier {
+            n_neighbors,
+            weights,
+            min_points,
+            classes: Vec::new(),
+            tree: None,
+        })
+    }
+
+    /// Build the tree from training points and their labels. Points must all
+    /// have the same number of coordinates.
+    pub fn fit(
+        mut slf: PyRefMut<Self>,
+        x: Vec<Vec<f32>>,
+        y: Vec<DataType>,
+    ) -> PyResult<PyRefMut<Self>> {
+        if x.len() != y.len() || x.is_empty() {
+            return Err(PyValueError::new_err(
+                "x and y must be non empty, and the same length",
+            ));
+        }
+        let mut classes = Vec::new();
+        let records = x
+            .into_iter()
+            .zip(y)
+            .map(|(point, label)| {
+                let class = match classes.iter().position(|c| *c == label) {
+                    Some(class) => class,
+                    None => {
+                        classes.push(label);
+                        classes.len() - 1
+                    }
+                };
+                closest_rust::Data::new(class, point)
+            })
+            .collect();
+        slf.tree =
+            Some(closest_rust::KDTree::from_vec(records, slf.min_points).map_err(to_py_err)?);
+        slf.classes = classes;
+        Ok(slf)
+    }
+
+    /// Labels seen by fit, in the order of the predict_proba columns.
+    #[getter]
+    fn classes_(&self, py: Python) -> Vec<PyObject> {
+        self.classes.iter().map(|c| c.to_object(py)).collect()
+    }
+
+    /// The label with the most votes for each point.
+    pub fn predict(&self, py: Python, x: Vec<Vec<f32>>) -> PyResult<Vec<PyObject>> {
+        x.into_iter()
+            .map(|point| {
+                let votes = self.votes(point)?;
+                // Ties go to the class seen first.
+                let best = votes
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(&a.0)))
+                    .map_or(0, |(class, _)| class);
+                Ok(self.classes[best].to_object(py))
+            })
+            .collect()
+    }
+
+    /// The share of votes for each class, for each point.
+    pub fn predict_proba(&self, x: Vec<Vec<f32>>) -> PyResult<Vec<Vec<f64>>> {
+        x.into_iter().map(|point| self.votes(point)).collect()
+    }
+}
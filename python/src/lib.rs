@@ -1,5 +1,11 @@
 extern crate closest as closest_rust;
+
+mod classifier;
+
+use closest_rust::{ClosestError, Encode};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 
 #[derive(FromPyObject, std::cmp::PartialEq, Clone)]
 pub enum DataType {
@@ -16,6 +22,66 @@ pub enum DataType {
 //     dt: nearest_rust::Data<DataType>
 // }
 
+impl DataType {
+    fn to_object(&self, py: Python) -> PyObject {
+        match self {
+            DataType::Str(v) => v.into_py(py),
+            DataType::Int(v) => v.into_py(py),
+            DataType::Flt(v) => v.into_py(py),
+        }
+    }
+}
+
+/// A kind byte, then the value, so saved trees can hold any mix of kinds.
+impl Encode for DataType {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            DataType::Str(v) => {
+                0u8.encode(out);
+                v.encode(out);
+            }
+            DataType::Int(v) => {
+                1u8.encode(out);
+                v.encode(out);
+            }
+            DataType::Flt(v) => {
+                2u8.encode(out);
+                v.encode(out);
+            }
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ClosestError> {
+        match u8::decode(input)? {
+            0 => Ok(DataType::Str(String::decode(input)?)),
+            1 => Ok(DataType::Int(i64::decode(input)?)),
+            2 => Ok(DataType::Flt(f64::decode(input)?)),
+            kind => Err(ClosestError::InvalidFormat(format!(
+                "unknown payload kind {}",
+                kind
+            ))),
+        }
+    }
+}
+
+/// File errors raise OSError, anything else ValueError.
+fn to_py_err(error: ClosestError) -> PyErr {
+    match error {
+        ClosestError::Io(e) => e.into(),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+fn check_dimension(expected: usize, point: &[f32]) -> PyResult<()> {
+    if point.len() != expected {
+        return Err(PyValueError::new_err(format!(
+            "expected a point with {} coordinates, found {}",
+            expected,
+            point.len()
+        )));
+    }
+    Ok(())
+}
+
 #[pyclass]
 pub struct KDTree {
     tree: closest_rust::KDTree<DataType>,
@@ -55,18 +121,136 @@ impl KDTree {
                 &closest_rust::SquaredEuclideanDistance::default(),
             )
             .iter()
-            .map(|n| match &n.data {
-                DataType::Str(v) => (n.distance, v.into_py(py)),
-                DataType::Int(v) => (n.distance, v.into_py(py)),
-                DataType::Flt(v) => (n.distance, v.into_py(py)),
-            })
+            .map(|n| (n.distance, n.data.to_object(py)))
             .collect::<Vec<(f32, PyObject)>>())
     }
+
+    /// Save the tree to a file, that can be opened by MappedKDTree in any
+    /// number of processes, without each one holding its own copy.
+    pub fn save(&self, path: &str) -> PyResult<()> {
+        self.tree.save_mapped(path).map_err(to_py_err)
+    }
+
+    /// Get the K nearest neighbors to each of several points.
+    ///
+    /// With output="list" a list of results per point is returned, in the
+    /// same shape as get_nearest_neighbors. With output="columns" a dict of
+    /// the columns query_idx, neighbor_rank, distance and payload is returned,
+    /// and output="dataframe" passes those columns to a pandas DataFrame.
+    #[pyo3(signature = (points, k=1, output="list"))]
+    pub fn get_nearest_neighbors_batch(
+        &self,
+        py: Python,
+        points: Vec<Vec<f32>>,
+        k: usize,
+        output: &str,
+    ) -> PyResult<PyObject> {
+        if let Some(first) = self.tree.records().first() {
+            for point in &points {
+                check_dimension(first.point().shape(), point)?;
+            }
+        }
+        let metric = closest_rust::SquaredEuclideanDistance::default();
+        let results = points.into_iter().map(|point| {
+            self.tree
+                .get_nearest_neighbors(&closest_rust::Point::new(point), k, &metric)
+        });
+        match output {
+            "list" => Ok(results
+                .map(|neighbors| {
+                    neighbors
+                        .iter()
+                        .map(|n| (n.distance, n.data.to_object(py)))
+                        .collect::<Vec<(f32, PyObject)>>()
+                })
+                .collect::<Vec<_>>()
+                .into_py(py)),
+            "columns" | "dataframe" => {
+                let mut query_idx = Vec::new();
+                let mut neighbor_rank = Vec::new();
+                let mut distance = Vec::new();
+                let mut payload = Vec::new();
+                for (i, neighbors) in results.enumerate() {
+                    for (rank, n) in neighbors.iter().enumerate() {
+                        query_idx.push(i);
+                        neighbor_rank.push(rank);
+                        distance.push(n.distance);
+                        payload.push(n.data.to_object(py));
+                    }
+                }
+                let columns = PyDict::new(py);
+                columns.set_item("query_idx", query_idx)?;
+                columns.set_item("neighbor_rank", neighbor_rank)?;
+                columns.set_item("distance", distance)?;
+                columns.set_item("payload", payload)?;
+                if output == "columns" {
+                    Ok(columns.into_py(py))
+                } else {
+                    let frame = py
+                        .import("pandas")?
+                        .getattr("DataFrame")?
+                        .call1((columns,))?;
+                    Ok(frame.into_py(py))
+                }
+            }
+            _ => Err(PyValueError::new_err(format!(
+                "output must be one of 'list', 'columns' or 'dataframe', found '{}'",
+                output
+            ))),
+        }
+    }
+}
+
+/// A tree saved with KDTree.save, queried straight from a memory map. Every
+/// process that opens the same file shares the operating system's page
+/// cache, rather than holding its own copy of the tree.
+#[pyclass]
+pub struct MappedKDTree {
+    tree: closest_rust::MappedKDTree<DataType>,
+}
+
+#[pymethods]
+impl MappedKDTree {
+    /// Open a tree saved with KDTree.save.
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        Ok(MappedKDTree {
+            tree: closest_rust::MappedKDTree::open(path).map_err(to_py_err)?,
+        })
+    }
+
+    /// Get the K nearest neighbors to a point.
+    #[pyo3(signature = (point, k=1))]
+    pub fn get_nearest_neighbors(
+        &self,
+        py: Python,
+        point: Vec<f32>,
+        k: usize,
+    ) -> PyResult<Vec<(f32, PyObject)>> {
+        check_dimension(self.tree.dimension(), &point)?;
+        Ok(self
+            .tree
+            .get_nearest_neighbors(
+                &closest_rust::Point::new(point),
+                k,
+                &closest_rust::SquaredEuclideanDistance::default(),
+            )
+            .map_err(to_py_err)?
+            .iter()
+            .map(|n| (n.distance, n.data.to_object(py)))
+            .collect())
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
 }
 
 /// A Python module implemented in Rust.
 #[pymodule]
 fn closest(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<KDTree>()?;
+    m.add_class::<MappedKDTree>()?;
+    m.add_class::<classifier::KNeighborsClassifier>()?;
     Ok(())
 }
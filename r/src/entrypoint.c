@@ -0,0 +1,8 @@
+// We need to forward routine registration from C to Rust
+// to avoid the linker removing the static library.
+
+void R_init_closest_extendr(void *dll);
+
+void R_init_closest(void *dll) {
+    R_init_closest_extendr(dll);
+}
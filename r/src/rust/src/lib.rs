@@ -0,0 +1,93 @@
+extern crate closest as closest_rust;
+use extendr_api::prelude::*;
+
+/// KD-tree over the rows of a numeric matrix. Each record's payload is its
+/// 1 based row number, so results index straight back into the R data.
+struct KDTree {
+    tree: closest_rust::KDTree<i32>,
+    dimension: usize,
+}
+
+/// Row `row` of a column major matrix.
+fn matrix_row(matrix: &RMatrix<f64>, row: usize) -> Vec<f32> {
+    let data = matrix.data();
+    (0..matrix.ncols())
+        .map(|col| data[col * matrix.nrows() + row] as f32)
+        .collect()
+}
+
+impl KDTree {
+    /// Raise an R error for queries without the tree's number of columns.
+    fn check_dimension(&self, found: usize) {
+        if found != self.dimension {
+            throw_r_error(format!(
+                "query must have {} coordinates, found {}",
+                self.dimension, found
+            ));
+        }
+    }
+}
+
+#[extendr]
+impl KDTree {
+    fn new(points: RMatrix<f64>, min_points: i32) -> Self {
+        if points.nrows() == 0 {
+            throw_r_error("points must have at least one row");
+        }
+        let records =
+            (0..points.nrows()).map(|row| closest_rust::Data::new(row as i32 + 1, matrix_row(&points, row)));
+        let tree = closest_rust::KDTree::from_iter(records, min_points.max(1) as usize)
+            .unwrap_or_else(|e| throw_r_error(e.to_string()));
+        KDTree {
+            tree,
+            dimension: points.ncols(),
+        }
+    }
+
+    fn dimension(&self) -> i32 {
+        self.dimension as i32
+    }
+
+    fn nearest(&self, queries: RMatrix<f64>, k: i32) -> List {
+        self.check_dimension(queries.ncols());
+        let k = k.max(0) as usize;
+        let metric = closest_rust::SquaredEuclideanDistance::default();
+        let results = (0..queries.nrows())
+            .map(|row| {
+                let point = closest_rust::Point::new(matrix_row(&queries, row));
+                self.tree.get_nearest_neighbors(&point, k, &metric)
+            })
+            .collect::<Vec<_>>();
+        // Queries can find fewer than k neighbors on small trees, those cells are NA.
+        let index = RMatrix::new_matrix(results.len(), k, |r, c| {
+            results[r].get(c).map_or(Rint::na(), |n| Rint::from(n.data))
+        });
+        let distance = RMatrix::new_matrix(results.len(), k, |r, c| {
+            results[r]
+                .get(c)
+                .map_or(Rfloat::na(), |n| Rfloat::from((n.distance as f64).sqrt()))
+        });
+        list!(index = index, distance = distance)
+    }
+
+    fn within_radius(&self, query: Vec<f64>, radius: f64) -> List {
+        self.check_dimension(query.len());
+        let point = closest_rust::Point::new(query.iter().map(|c| *c as f32).collect());
+        let found = closest_rust::SpatialIndex::neighbors_within_radius(
+            &self.tree,
+            &point,
+            radius.powi(2) as f32,
+        );
+        let index = found.iter().map(|n| n.data).collect::<Vec<i32>>();
+        let distance = found
+            .iter()
+            .map(|n| (n.distance as f64).sqrt())
+            .collect::<Vec<f64>>();
+        list!(index = index, distance = distance)
+    }
+}
+
+extendr_module! {
+    mod closest;
+    impl KDTree;
+}
@@ -0,0 +1,256 @@
+//! Aggregates of the records' data kept for every node of the tree, so that
+//! aggregate queries such as "how many records, and what total value, within
+//! this radius" take whole subtrees at once rather than visiting every
+//! record.
+use crate::bounds::BoundingBox;
+use crate::distance::DistanceMetric;
+use crate::tree::{KDTree, Point, TreeNode};
+
+/// A summary of records that can be combined with the summary of other
+/// records. `Default` is the summary of no records at all.
+pub trait Aggregate: Clone + Default {
+    fn merge(&mut self, other: &Self);
+}
+
+/// Count, sum, smallest and largest of a value of each record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    /// Summary of a single record's value.
+    pub fn of(value: f64) -> Self {
+        Summary {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+    /// Mean of the values, `None` without records.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Summary {
+            count: 0,
+            sum: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Aggregate for Summary {
+    fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+/// Node with the aggregate and bounding box of every record under it.
+struct AggregateNode<A> {
+    total: A,
+    /// Box enclosing the records under the node, `None` if all of them were
+    /// removed.
+    bounds: Option<BoundingBox>,
+    start: usize,
+    stop: usize,
+    split: Option<Split<A>>,
+}
+
+/// Position of the split record and the children of a split node.
+struct Split<A> {
+    record: usize,
+    left: Box<AggregateNode<A>>,
+    right: Box<AggregateNode<A>>,
+}
+
+/// A tree with an aggregate of every subtree, made with
+/// [`KDTree::aggregate`].
+pub struct AggregateTree<'a, T: Clone, A: Aggregate> {
+    tree: &'a KDTree<T>,
+    /// Aggregate of each record, in the order the tree stores them, and the
+    /// aggregate of no records for removed ones.
+    values: Vec<A>,
+    root: Option<AggregateNode<A>>,
+}
+
+fn build_node<T: Clone, A: Aggregate>(node: TreeNode<'_, T>, values: &[A]) -> AggregateNode<A> {
+    let range = node.range();
+    let mut total = A::default();
+    for value in &values[range.clone()] {
+        total.merge(value);
+    }
+    let split = node.children().map(|(left, right)| Split {
+        record: left.range().end,
+        left: Box::new(build_node(left, values)),
+        right: Box::new(build_node(right, values)),
+    });
+    AggregateNode {
+        total,
+        bounds: node.bounds(),
+        start: range.start,
+        stop: range.end,
+        split,
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Compute `value` for every record, and merge them for every subtree,
+    /// for aggregate queries. The tree must not change while the aggregates
+    /// are in use, which the borrow guarantees.
+    pub fn aggregate<A: Aggregate, F: Fn(&T) -> A>(&self, value: F) -> AggregateTree<'_, T, A> {
+        let values: Vec<A> = self
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                if self.is_removed(i) {
+                    A::default()
+                } else {
+                    value(&d.data)
+                }
+            })
+            .collect();
+        let root = (self.indexed > 0).then(|| build_node(self.root(), &values));
+        AggregateTree {
+            tree: self,
+            values,
+            root,
+        }
+    }
+}
+
+impl<T: Clone, A: Aggregate> AggregateTree<'_, T, A> {
+    /// Aggregate of every record within `radius` of `point`. Subtrees lying
+    /// entirely within the radius are taken as a whole for metrics that
+    /// [grow along the axes](DistanceMetric::grows_along_axes).
+    pub fn within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> A {
+        let mut total = A::default();
+        let within = |i: usize| {
+            distance_metric.distance(point.as_slice(), self.tree.data[i].point.as_slice()) <= radius
+        };
+        if let Some(root) = &self.root {
+            self.collect(root, point, radius, distance_metric, &within, &mut total);
+        }
+        for i in self.tree.indexed..self.tree.data.len() {
+            if within(i) {
+                total.merge(&self.values[i]);
+            }
+        }
+        total
+    }
+    fn collect<D: DistanceMetric, W: Fn(usize) -> bool>(
+        &self,
+        node: &AggregateNode<A>,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+        within: &W,
+        total: &mut A,
+    ) {
+        let Some(bounds) = &node.bounds else {
+            return;
+        };
+        if bounds.plane_bound(point, distance_metric) > radius {
+            return;
+        }
+        if distance_metric.grows_along_axes()
+            && bounds.max_distance(point, distance_metric) <= radius
+        {
+            total.merge(&node.total);
+            return;
+        }
+        match &node.split {
+            Some(split) => {
+                if within(split.record) {
+                    total.merge(&self.values[split.record]);
+                }
+                self.collect(&split.left, point, radius, distance_metric, within, total);
+                self.collect(&split.right, point, radius, distance_metric, within, total);
+            }
+            None => {
+                for i in node.start..node.stop {
+                    if within(i) {
+                        total.merge(&self.values[i]);
+                    }
+                }
+            }
+        }
+    }
+    /// Aggregate of every record.
+    pub fn total(&self) -> A {
+        let mut total = self
+            .root
+            .as_ref()
+            .map_or_else(A::default, |r| r.total.clone());
+        for value in &self.values[self.tree.indexed..] {
+            total.merge(value);
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::{PeriodicEuclideanDistance, SquaredEuclideanDistance};
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn radius_summaries() {
+        let data = (0..1000)
+            .map(|i| Data::new(i as f64, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        tree.extend((1000..1010).map(|i| Data::new(i as f64, vec![50., 20.])));
+        let summaries = tree.aggregate(|value: &f64| Summary::of(*value));
+        let metric = SquaredEuclideanDistance::default();
+        for (x, y, radius) in [
+            (50., 20., 100.),
+            (0., 0., 400.),
+            (100., 46., 10000.),
+            (20., 10., 0.),
+        ] {
+            let point = Point::from([x, y]);
+            let summary = summaries.within_radius(&point, radius, &metric);
+            let mut expected = Summary::default();
+            for record in tree.records() {
+                if metric.distance(point.as_slice(), record.point().as_slice()) <= radius {
+                    expected.merge(&Summary::of(*record.data()));
+                }
+            }
+            assert_eq!(summary, expected);
+        }
+        let periodic = PeriodicEuclideanDistance::new(vec![101., 47.]);
+        assert_eq!(
+            summaries
+                .within_radius(&Point::from([0., 0.]), 4., &periodic)
+                .count,
+            tree.get_neighbors_within_radius(&Point::from([0., 0.]), 4., &periodic)
+                .len()
+        );
+        assert_eq!(summaries.total().count, 1010);
+        assert_eq!(summaries.total().max, 1009.);
+        assert!(tree.delete(55));
+        let summaries = tree.aggregate(|value: &f64| Summary::of(*value));
+        assert_eq!(summaries.total().count, 1009);
+        let point = Point::from([(55 * 37 % 101) as f32, (55 * 13 % 47) as f32]);
+        assert_eq!(summaries.within_radius(&point, 0., &metric).count, 0);
+    }
+}
@@ -0,0 +1,62 @@
+//! Batches of queries run in space filling curve order, so consecutive
+//! queries walk mostly the same branches of the tree while they are still in
+//! the cache.
+use crate::distance::DistanceMetric;
+use crate::shard::{morton_bits, morton_code};
+use crate::tree::{KDTree, Neighbor, Point};
+
+impl<T: Clone> KDTree<T> {
+    /// Positions of `points` ordered along the Morton curve through the
+    /// tree's bounding box.
+    pub(crate) fn curve_order(&self, points: &[Point]) -> Vec<usize> {
+        let mut order = (0..points.len()).collect::<Vec<_>>();
+        if let Some(bounds) = self.bounds() {
+            let bits = morton_bits(self.dimension);
+            order.sort_by_cached_key(|i| morton_code(&points[*i], bounds, bits));
+        }
+        order
+    }
+    /// Get k nearest neighbors to each of the given points, results in the
+    /// same order as the points. The queries are run sorted along a space
+    /// filling curve, which on large batches of scattered points is
+    /// noticeably faster than running them in the order given.
+    pub fn get_nearest_neighbors_all<D: DistanceMetric>(
+        &self,
+        points: &[Point],
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Vec<Neighbor<T>>> {
+        let mut results = Vec::new();
+        results.resize_with(points.len(), Vec::new);
+        for i in self.curve_order(points) {
+            results[i] = self.get_nearest_neighbors(&points[i], k, distance_metric);
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn results_in_input_order() {
+        let data = (0..2000)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let points = (0..300)
+            .map(|i| Point::from([(i * 71 % 103) as f32 - 1., (i * 29 % 50) as f32]))
+            .collect::<Vec<_>>();
+        let order = tree.curve_order(&points);
+        assert_ne!(order, (0..300).collect::<Vec<_>>());
+        let metric = SquaredEuclideanDistance::default();
+        let batch = tree.get_nearest_neighbors_all(&points, 3, &metric);
+        for (point, found) in points.iter().zip(batch) {
+            let expected = tree.get_nearest_neighbors(point, 3, &metric);
+            assert_eq!(found, expected);
+        }
+    }
+}
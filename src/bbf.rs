@@ -0,0 +1,146 @@
+//! Best bin first search, which explores the branches closest to the query
+//! first and stops after a fixed number of distance computations, trading
+//! accuracy for a latency that does not depend on the data.
+use std::collections::VecDeque;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{at, Collector, KDTree, NearestCollector, Neighbor, NodeOrDataPointer, Point};
+
+impl<T: Clone> KDTree<T> {
+    /// Get approximately the k nearest neighbors to a given point, ordered
+    /// from nearest to farthest, computing the distance to at most about
+    /// `checks` records. Unexplored branches wait in a queue ordered by
+    /// their distance bound, holding at most `max_queue` of them, the
+    /// farthest being dropped when it is full. With enough checks and queue
+    /// room the search is exact. Records added since the tree was last
+    /// built are always checked.
+    pub fn get_nearest_neighbors_bbf<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        checks: usize,
+        max_queue: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        self.assert_searchable(point.as_slice(), distance_metric);
+        let mut collector = NearestCollector::new(k);
+        let mut checked = 0;
+        let offer = |collector: &mut NearestCollector, data_pointer: usize| {
+            if self.is_removed(data_pointer) {
+                return;
+            }
+            let distance = distance_metric.distance(
+                point.as_slice(),
+                self.get_data_point(data_pointer).as_slice(),
+            );
+            collector.offer(distance, data_pointer);
+        };
+        // Branches sorted by their distance bound, nearest first.
+        let mut queue: VecDeque<(f32, &NodeOrDataPointer, usize)> = VecDeque::new();
+        if self.indexed + self.inserted > 0 && k > 0 {
+            queue.push_back((0., &self.root_node, 0));
+        }
+        while let Some((bound, mut node, mut depth)) = queue.pop_front() {
+            if checked >= checks || bound > collector.bound() {
+                break;
+            }
+            loop {
+                match node {
+                    NodeOrDataPointer::Node(n) => {
+                        offer(&mut collector, n.data_pointer);
+                        checked += 1;
+                        let axis = depth % self.dimension;
+                        let split_value =
+                            *at(&self.get_data_point(n.data_pointer).coordinates, axis);
+                        let (near, far) = if at(&point.coordinates, axis) - split_value <= 0. {
+                            (n.left.as_ref(), n.right.as_ref())
+                        } else {
+                            (n.right.as_ref(), n.left.as_ref())
+                        };
+                        let far_bound = distance_metric.min_distance_to_plane(
+                            point.as_slice(),
+                            axis,
+                            split_value,
+                        );
+                        if max_queue > 0 && far_bound <= collector.bound() {
+                            let position = queue.partition_point(|(b, _, _)| *b <= far_bound);
+                            queue.insert(position, (far_bound, far, depth + 1));
+                            queue.truncate(max_queue);
+                        }
+                        node = near;
+                        depth += 1;
+                    }
+                    NodeOrDataPointer::Data((start, stop)) => {
+                        for data_pointer in *start..*stop {
+                            offer(&mut collector, data_pointer);
+                        }
+                        for data_pointer in self.leaf_overflow(*start) {
+                            offer(&mut collector, *data_pointer);
+                        }
+                        checked += stop - start + self.leaf_overflow(*start).len();
+                        break;
+                    }
+                }
+            }
+        }
+        for data_pointer in self.unindexed() {
+            offer(&mut collector, data_pointer);
+        }
+        collector.into_neighbors(&self.data)
+    }
+    /// Like [`KDTree::get_nearest_neighbors_bbf`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_get_nearest_neighbors_bbf<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        checks: usize,
+        max_queue: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_nearest_neighbors_bbf(point, k, checks, max_queue, distance_metric))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn limited_checks() {
+        let data = (0..5000)
+            .map(|i| {
+                Data::new(
+                    i,
+                    vec![
+                        (i * 37 % 101) as f32,
+                        (i * 13 % 47) as f32,
+                        (i * 7 % 29) as f32,
+                    ],
+                )
+            })
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        for query in [[50.5, 20.5, 3.5], [0., 0., 0.], [120., 60., 40.]] {
+            let point = Point::from(query);
+            let exact = distances(tree.get_nearest_neighbors(&point, 5, &metric));
+            let full = tree.get_nearest_neighbors_bbf(&point, 5, usize::MAX, usize::MAX, &metric);
+            assert_eq!(distances(full), exact);
+            let approximate = distances(tree.get_nearest_neighbors_bbf(&point, 5, 40, 8, &metric));
+            assert_eq!(approximate.len(), 5);
+            for (found, exact) in approximate.iter().zip(&exact) {
+                assert!(found >= exact);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::distance::DistanceMetric;
+use crate::tree::Point;
+
+/// Axis aligned box enclosing a set of points.
+///
+/// The distance bounds are exact for metrics that grow with the absolute
+/// difference along every axis, such as squared euclidean distance. For
+/// other metrics they are only as good as the metric's behavior allows.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingBox {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl BoundingBox {
+    /// Smallest box enclosing all the given points, or `None` if there are
+    /// no points.
+    pub fn from_points<'a, I: Iterator<Item = &'a Point>>(mut points: I) -> Option<Self> {
+        let first = points.next()?;
+        let mut min = first.coordinates.clone();
+        let mut max = first.coordinates.clone();
+        for point in points {
+            for (i, c) in point.coordinates.iter().enumerate() {
+                min[i] = min[i].min(*c);
+                max[i] = max[i].max(*c);
+            }
+        }
+        Some(BoundingBox {
+            min: Point { coordinates: min },
+            max: Point { coordinates: max },
+        })
+    }
+    /// Grow the box to enclose a point.
+    pub(crate) fn include(&mut self, point: &Point) {
+        for (i, c) in point.coordinates.iter().enumerate() {
+            self.min.coordinates[i] = self.min.coordinates[i].min(*c);
+            self.max.coordinates[i] = self.max.coordinates[i].max(*c);
+        }
+    }
+    /// Check if a point falls inside the box, boundaries included.
+    pub fn contains(&self, point: &Point) -> bool {
+        point
+            .coordinates
+            .iter()
+            .zip(self.min.coordinates.iter().zip(&self.max.coordinates))
+            .all(|(c, (lo, hi))| lo <= c && c <= hi)
+    }
+    /// Check if two boxes overlap, touching boundaries included.
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        (0..self.min.shape()).all(|i| {
+            self.min.point(i) <= other.max.point(i) && other.min.point(i) <= self.max.point(i)
+        })
+    }
+    /// Lower bound on the distance from `point` to anything in the box.
+    pub fn min_distance<D: DistanceMetric>(&self, point: &Point, distance_metric: &D) -> f32 {
+        let closest = point
+            .coordinates
+            .iter()
+            .zip(self.min.coordinates.iter().zip(&self.max.coordinates))
+            .map(|(c, (lo, hi))| c.max(*lo).min(*hi))
+            .collect::<Vec<f32>>();
+        distance_metric.distance(point.as_slice(), &closest)
+    }
+    /// Lower bound on the distance from `point` to anything in the box that
+    /// holds for every metric: the largest of the metric's bounds on the
+    /// distance across the faces `point` lies outside of, see
+    /// [`DistanceMetric::min_distance_to_plane`]. Looser than
+    /// [`BoundingBox::min_distance`] for euclidean distances.
+    pub(crate) fn plane_bound<D: DistanceMetric>(&self, point: &Point, distance_metric: &D) -> f32 {
+        let mut bound = 0f32;
+        for (axis, (c, (lo, hi))) in point
+            .coordinates
+            .iter()
+            .zip(self.min.coordinates.iter().zip(&self.max.coordinates))
+            .enumerate()
+        {
+            let face = if c < lo {
+                *lo
+            } else if c > hi {
+                *hi
+            } else {
+                continue;
+            };
+            bound = bound.max(distance_metric.min_distance_to_plane(point.as_slice(), axis, face));
+        }
+        bound
+    }
+    /// Upper bound on the distance from `point` to anything in the box.
+    pub fn max_distance<D: DistanceMetric>(&self, point: &Point, distance_metric: &D) -> f32 {
+        let farthest = point
+            .coordinates
+            .iter()
+            .zip(self.min.coordinates.iter().zip(&self.max.coordinates))
+            .map(|(c, (lo, hi))| {
+                if (c - lo).abs() > (hi - c).abs() {
+                    *lo
+                } else {
+                    *hi
+                }
+            })
+            .collect::<Vec<f32>>();
+        distance_metric.distance(point.as_slice(), &farthest)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+use crate::index::SpatialIndex;
+use crate::tree::{Collector, Data, NearestCollector, Neighbor, Point, RadiusCollector};
+
+/// Index that compares a query against every record. It has no build cost
+/// and beats trees for small datasets, or high dimensional data where trees
+/// can not prune anything.
+#[derive(Debug)]
+pub struct BruteForce<T: Clone> {
+    data: Vec<Data<T>>,
+}
+
+impl<T: Clone> BruteForce<T> {
+    pub fn from_vec(data: Vec<Data<T>>) -> Self {
+        BruteForce { data }
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// Add a record to the index.
+    pub fn push(&mut self, data: Data<T>) {
+        self.data.push(data);
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = NearestCollector::new(k);
+        self.scan(point, &mut collector, distance_metric);
+        collector.into_neighbors(&self.data)
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.scan(point, &mut collector, distance_metric);
+        collector.into_neighbors(&self.data)
+    }
+    fn scan<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        for (data_pointer, d) in self.data.iter().enumerate() {
+            collector.offer(
+                distance_metric.distance(point.as_slice(), d.point.as_slice()),
+                data_pointer,
+            );
+        }
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for BruteForce<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k, &SquaredEuclideanDistance::default())
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius, &SquaredEuclideanDistance::default())
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
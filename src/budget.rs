@@ -0,0 +1,114 @@
+//! Searches with a budget of nodes to visit, for callers that must answer in
+//! bounded time and prefer a limit that does not depend on the machine's
+//! load to a timer.
+use std::cell::Cell;
+
+use crate::bounds::BoundingBox;
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{Collector, KDTree, NearestCollector, Neighbor, Point};
+
+/// Stops the search from entering any branch once `max_nodes` nodes have
+/// been visited, and notes whether a branch was skipped for it.
+struct BudgetCollector<'a, C: Collector> {
+    inner: &'a mut C,
+    max_nodes: usize,
+    visited: usize,
+    truncated: Cell<bool>,
+}
+
+impl<C: Collector> Collector for BudgetCollector<'_, C> {
+    // The budget is checked before entering each branch.
+    const USES_REGIONS: bool = true;
+    fn bound(&self) -> f32 {
+        self.inner.bound()
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        self.inner.offer(distance, data_pointer);
+    }
+    fn accepts_region(&self, region: &BoundingBox) -> bool {
+        if !self.inner.accepts_region(region) {
+            return false;
+        }
+        if self.visited >= self.max_nodes {
+            self.truncated.set(true);
+            return false;
+        }
+        true
+    }
+    fn visit_node(&mut self) {
+        self.visited += 1;
+        self.inner.visit_node();
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Get the k nearest neighbors to a given point found by visiting at
+    /// most `max_nodes` nodes of the tree, ordered from nearest to farthest,
+    /// and whether the search ran out of nodes before it could finish. When
+    /// it did, closer records than the ones returned may exist. Records
+    /// added since the tree was last built are always checked.
+    pub fn get_nearest_neighbors_with_budget<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        max_nodes: usize,
+        distance_metric: &D,
+    ) -> (Vec<Neighbor<T>>, bool) {
+        let mut nearest = NearestCollector::new(k);
+        let mut collector = BudgetCollector {
+            inner: &mut nearest,
+            max_nodes,
+            visited: 0,
+            truncated: Cell::new(false),
+        };
+        self.search(point, &mut collector, distance_metric);
+        let truncated = collector.truncated.get();
+        (nearest.into_neighbors(&self.data), truncated)
+    }
+    /// Like [`KDTree::get_nearest_neighbors_with_budget`], with the checks
+    /// of [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_get_nearest_neighbors_with_budget<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        max_nodes: usize,
+        distance_metric: &D,
+    ) -> Result<(Vec<Neighbor<T>>, bool), ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_nearest_neighbors_with_budget(point, k, max_nodes, distance_metric))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn node_budget() {
+        let data = (0..2000)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50.5, 20.5]);
+        let exact = tree.get_nearest_neighbors(&point, 10, &metric);
+
+        let (found, truncated) =
+            tree.get_nearest_neighbors_with_budget(&point, 10, usize::MAX, &metric);
+        assert!(!truncated);
+        assert_eq!(found, exact);
+
+        let (found, truncated) = tree.get_nearest_neighbors_with_budget(&point, 10, 12, &metric);
+        assert!(truncated);
+        assert_eq!(found.len(), 10);
+        for (found, exact) in found.iter().zip(&exact) {
+            assert!(found.distance >= exact.distance);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Calibration of neighbor distances into match probabilities, as in record
+//! linkage, where a query's nearest records are candidate matches and a
+//! probability is more useful than a raw distance.
+//!
+//! The mapping is fit with isotonic regression (pool adjacent violators) on
+//! labeled pairs, the only assumption being that closer pairs are no less
+//! likely to match than farther ones.
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, Neighbor, Point};
+
+/// Non increasing mapping from distance to match probability, made with
+/// [`DistanceCalibration::fit`].
+///
+/// Probabilities are interpolated linearly between the fitted points, and
+/// held constant before the first and after the last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceCalibration {
+    /// Mean distance of every fitted block of pairs, increasing.
+    distances: Vec<f32>,
+    /// Share of matches in every block, non increasing.
+    probabilities: Vec<f32>,
+}
+
+impl DistanceCalibration {
+    /// Fit the mapping to pairs of a distance, in the units of the metric
+    /// queries will use, and whether the pair is a match. Pairs with a
+    /// distance that is not a number are ignored.
+    pub fn fit(pairs: &[(f32, bool)]) -> Result<Self, ClosestError> {
+        let mut pairs = pairs
+            .iter()
+            .filter(|(distance, _)| !distance.is_nan())
+            .copied()
+            .collect::<Vec<_>>();
+        if pairs.is_empty() {
+            return Err(ClosestError::NotEnoughSamples {
+                needed: 1,
+                found: 0,
+            });
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // Blocks of (distance sum, match count, pair count), each merged
+        // with the ones before it while it has the larger share of matches.
+        let mut blocks: Vec<(f64, f64, f64)> = Vec::new();
+        for (i, (distance, matched)) in pairs.iter().enumerate() {
+            let pair = (*distance as f64, *matched as u8 as f64, 1.);
+            // Equal distances cannot be told apart, and share a block.
+            let tied = i > 0 && pairs[i - 1].0 == *distance;
+            let mut block = if tied {
+                let last = blocks.pop().expect("tied pairs follow a block");
+                (last.0 + pair.0, last.1 + pair.1, last.2 + pair.2)
+            } else {
+                pair
+            };
+            while let Some(last) = blocks.last() {
+                if last.1 / last.2 >= block.1 / block.2 {
+                    break;
+                }
+                block = (last.0 + block.0, last.1 + block.1, last.2 + block.2);
+                blocks.pop();
+            }
+            blocks.push(block);
+        }
+        Ok(DistanceCalibration {
+            distances: blocks.iter().map(|b| (b.0 / b.2) as f32).collect(),
+            probabilities: blocks.iter().map(|b| (b.1 / b.2) as f32).collect(),
+        })
+    }
+    /// Probability that a pair at `distance` is a match.
+    pub fn probability(&self, distance: f32) -> f32 {
+        let after = self.distances.partition_point(|d| *d <= distance);
+        if after == 0 {
+            return self.probabilities[0];
+        }
+        if after == self.distances.len() {
+            return self.probabilities[after - 1];
+        }
+        let (d0, d1) = (self.distances[after - 1], self.distances[after]);
+        let (p0, p1) = (self.probabilities[after - 1], self.probabilities[after]);
+        p0 + (p1 - p0) * (distance - d0) / (d1 - d0)
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest, each with its match probability under `calibration`.
+    pub fn get_nearest_neighbors_calibrated<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        calibration: &DistanceCalibration,
+        distance_metric: &D,
+    ) -> Vec<(Neighbor<T>, f32)> {
+        self.get_nearest_neighbors(point, k, distance_metric)
+            .into_iter()
+            .map(|n| {
+                let probability = calibration.probability(n.distance);
+                (n, probability)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn isotonic_fit() {
+        // Matches get rarer with distance, with one out of order block.
+        let pairs = [
+            (0., true),
+            (1., true),
+            (1., false),
+            (2., false),
+            (3., true),
+            (4., false),
+            (f32::NAN, true),
+        ];
+        let calibration = DistanceCalibration::fit(&pairs).unwrap();
+        assert_eq!(calibration.distances, vec![0., 1., 2.5, 4.]);
+        assert_eq!(calibration.probabilities, vec![1., 0.5, 0.5, 0.]);
+        assert_eq!(calibration.probability(-1.), 1.);
+        assert_eq!(calibration.probability(0.5), 0.75);
+        assert_eq!(calibration.probability(2.), 0.5);
+        assert_eq!(calibration.probability(10.), 0.);
+        assert!(DistanceCalibration::fit(&[(f32::NAN, true)]).is_err());
+
+        let data = (0..10).map(|i| Data::new(i, vec![i as f32])).collect();
+        let tree = KDTree::from_vec(data, 2).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let found =
+            tree.get_nearest_neighbors_calibrated(&Point::from([0.]), 3, &calibration, &metric);
+        let probabilities = found.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+        assert_eq!(probabilities, vec![1., 0.5, 0.]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Building blocks for density based clustering, such as OPTICS and HDBSCAN,
+//! computed with the tree.
+use crate::distance::DistanceMetric;
+use crate::tree::{KDTree, NearestCollector, RawNeighbor};
+
+/// Mutual reachability distance between two records, the largest of their
+/// core distances and the distance between them.
+pub fn mutual_reachability(core_a: f32, core_b: f32, distance: f32) -> f32 {
+    core_a.max(core_b).max(distance)
+}
+
+impl<T: Clone> KDTree<T> {
+    /// The `min_samples` nearest neighbors of every stored record, each
+    /// record counting as its own nearest neighbor, by position in `data`.
+    fn all_nearest<D: DistanceMetric>(
+        &self,
+        min_samples: usize,
+        distance_metric: &D,
+    ) -> Vec<Vec<RawNeighbor>> {
+        self.data
+            .iter()
+            .map(|record| {
+                let mut collector = NearestCollector::new(min_samples);
+                self.search(&record.point, &mut collector, distance_metric);
+                collector.into_sorted_vec()
+            })
+            .collect()
+    }
+    /// Core distance of every record, the distance to its `min_samples`-th
+    /// nearest neighbor, counting the record itself. Records with fewer
+    /// neighbors have an infinite core distance. Distances are in the units
+    /// of the metric, squared for [`SquaredEuclideanDistance`], and ordered
+    /// by the records' positions in the data the tree was built from.
+    ///
+    /// [`SquaredEuclideanDistance`]: crate::SquaredEuclideanDistance
+    pub fn core_distances<D: DistanceMetric>(
+        &self,
+        min_samples: usize,
+        distance_metric: &D,
+    ) -> Vec<f32> {
+        let nearest = self.all_nearest(min_samples, distance_metric);
+        self.by_input_order(nearest.iter().map(|n| core_distance(n, min_samples)))
+    }
+    /// Edges from every record to its `min_samples` nearest neighbors, as
+    /// `(from, to, distance)`, weighted by mutual reachability distance.
+    /// Records are identified by their positions in the data the tree was
+    /// built from. This is the sparse graph approximate HDBSCAN runs on.
+    pub fn mutual_reachability_graph<D: DistanceMetric>(
+        &self,
+        min_samples: usize,
+        distance_metric: &D,
+    ) -> Vec<(usize, usize, f32)> {
+        let nearest = self.all_nearest(min_samples, distance_metric);
+        let core = nearest
+            .iter()
+            .map(|n| core_distance(n, min_samples))
+            .collect::<Vec<_>>();
+        nearest
+            .iter()
+            .enumerate()
+            .flat_map(|(from, neighbors)| {
+                let core = &core;
+                neighbors
+                    .iter()
+                    .filter(move |n| n.data_pointer != from)
+                    .map(move |n| {
+                        let weight =
+                            mutual_reachability(core[from], core[n.data_pointer], n.distance);
+                        (self.data[from].id, self.data[n.data_pointer].id, weight)
+                    })
+            })
+            .collect()
+    }
+    /// Values given per stored record, reordered by the records' positions
+    /// in the input.
+    pub(crate) fn by_input_order<V, I: Iterator<Item = V>>(&self, values: I) -> Vec<V> {
+        let mut values = self
+            .data
+            .iter()
+            .map(|d| d.id)
+            .zip(values)
+            .collect::<Vec<_>>();
+        values.sort_by_key(|(id, _)| *id);
+        values.into_iter().map(|(_, v)| v).collect()
+    }
+    /// Values given per record in the order of the input, reordered by the
+    /// records' storage positions. The reverse of
+    /// [`KDTree::by_input_order`].
+    pub(crate) fn by_storage_order<V: Clone>(&self, values: &[V]) -> Vec<V> {
+        let mut ranks = (0..self.data.len()).collect::<Vec<_>>();
+        ranks.sort_by_key(|position| self.data[*position].id);
+        let mut stored = values.to_vec();
+        for (value, position) in values.iter().zip(ranks) {
+            stored[position] = value.clone();
+        }
+        stored
+    }
+}
+
+fn core_distance(nearest: &[RawNeighbor], min_samples: usize) -> f32 {
+    if nearest.len() < min_samples || min_samples == 0 {
+        f32::INFINITY
+    } else {
+        nearest[min_samples - 1].distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn core_and_reachability() {
+        let points = [[0., 0.], [1., 0.], [0., 2.], [10., 10.]];
+        let data = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Data::new(i, p.to_vec()))
+            .collect();
+        let tree = KDTree::from_vec(data, 1).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        assert_eq!(tree.core_distances(2, &metric), vec![1., 1., 4., 164.]);
+        assert_eq!(tree.core_distances(5, &metric)[0], f32::INFINITY);
+        let graph = tree.mutual_reachability_graph(2, &metric);
+        assert_eq!(graph.len(), 4);
+        assert!(graph.contains(&(0, 1, 1.)));
+        assert!(graph.contains(&(2, 0, 4.)));
+        assert!(graph.contains(&(3, 2, 164.)));
+    }
+}
@@ -0,0 +1,168 @@
+//! Perceptual color distances.
+//!
+//! RGB coordinates are not perceptually uniform, so the closest color by
+//! euclidean distance in RGB space is often not the one a person would pick.
+//! Convert colors to CIELAB with [`srgb_to_lab`] before building the tree, and
+//! query it with [`DeltaE76`] or [`DeltaE2000`].
+use crate::distance::DistanceMetric;
+
+// D65 reference white.
+const WHITE_X: f64 = 0.95047;
+const WHITE_Y: f64 = 1.0;
+const WHITE_Z: f64 = 1.08883;
+
+fn linearize(channel: f32) -> f64 {
+    let c = channel as f64 / 255.;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f64) -> f64 {
+    let delta: f64 = 6. / 29.;
+    if t > delta.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3. * delta.powi(2)) + 4. / 29.
+    }
+}
+
+/// Convert an sRGB color, with channels in the range 0 to 255, to CIELAB
+/// coordinates `[L, a, b]` under the D65 illuminant.
+pub fn srgb_to_lab(r: f32, g: f32, b: f32) -> Vec<f32> {
+    let (r, g, b) = (linearize(r), linearize(g), linearize(b));
+    let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = 0.0193339 * r + 0.1191920 * g + 0.9503041 * b;
+    let (fx, fy, fz) = (lab_f(x / WHITE_X), lab_f(y / WHITE_Y), lab_f(z / WHITE_Z));
+    vec![
+        (116. * fy - 16.) as f32,
+        (500. * (fx - fy)) as f32,
+        (200. * (fy - fz)) as f32,
+    ]
+}
+
+/// CIE76 color difference, the euclidean distance between two CIELAB colors.
+#[derive(Debug, Default)]
+pub struct DeltaE76 {}
+
+impl DistanceMetric for DeltaE76 {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        p1.iter()
+            .zip(p2)
+            .map(|(s1, s2)| (s1 - s2).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).abs()
+    }
+    fn grows_along_axes(&self) -> bool {
+        true
+    }
+}
+
+/// CIEDE2000 color difference between two CIELAB colors.
+///
+/// CIEDE2000 is not a true metric, and gives no useful bound on the distance
+/// to a splitting plane, so the tree cannot prune any branches when it is
+/// used. Queries are exact, but visit every record.
+#[derive(Debug, Default)]
+pub struct DeltaE2000 {}
+
+impl DistanceMetric for DeltaE2000 {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let (l1, a1, b1) = (p1[0] as f64, p1[1] as f64, p1[2] as f64);
+        let (l2, a2, b2) = (p2[0] as f64, p2[1] as f64, p2[2] as f64);
+        let pow25_7 = 25f64.powi(7);
+
+        let c_bar = ((a1.hypot(b1)) + (a2.hypot(b2))) / 2.;
+        let g = 0.5 * (1. - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+        let (a1p, a2p) = ((1. + g) * a1, (1. + g) * a2);
+        let (c1p, c2p) = (a1p.hypot(b1), a2p.hypot(b2));
+        let hue = |b: f64, ap: f64| {
+            if b == 0. && ap == 0. {
+                0.
+            } else {
+                b.atan2(ap).to_degrees().rem_euclid(360.)
+            }
+        };
+        let (h1p, h2p) = (hue(b1, a1p), hue(b2, a2p));
+
+        let delta_lp = l2 - l1;
+        let delta_cp = c2p - c1p;
+        let delta_hp = if c1p * c2p == 0. {
+            0.
+        } else if (h2p - h1p).abs() <= 180. {
+            h2p - h1p
+        } else if h2p - h1p > 180. {
+            h2p - h1p - 360.
+        } else {
+            h2p - h1p + 360.
+        };
+        let delta_big_hp = 2. * (c1p * c2p).sqrt() * (delta_hp / 2.).to_radians().sin();
+
+        let l_bar_p = (l1 + l2) / 2.;
+        let c_bar_p = (c1p + c2p) / 2.;
+        let h_bar_p = if c1p * c2p == 0. {
+            h1p + h2p
+        } else if (h1p - h2p).abs() <= 180. {
+            (h1p + h2p) / 2.
+        } else if h1p + h2p < 360. {
+            (h1p + h2p + 360.) / 2.
+        } else {
+            (h1p + h2p - 360.) / 2.
+        };
+
+        let t = 1. - 0.17 * (h_bar_p - 30.).to_radians().cos()
+            + 0.24 * (2. * h_bar_p).to_radians().cos()
+            + 0.32 * (3. * h_bar_p + 6.).to_radians().cos()
+            - 0.20 * (4. * h_bar_p - 63.).to_radians().cos();
+        let delta_theta = 30. * (-((h_bar_p - 275.) / 25.).powi(2)).exp();
+        let r_c = 2. * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+        let s_l = 1. + (0.015 * (l_bar_p - 50.).powi(2)) / (20. + (l_bar_p - 50.).powi(2)).sqrt();
+        let s_c = 1. + 0.045 * c_bar_p;
+        let s_h = 1. + 0.015 * c_bar_p * t;
+        let r_t = -(2. * delta_theta).to_radians().sin() * r_c;
+
+        let (l_term, c_term, h_term) = (delta_lp / s_l, delta_cp / s_c, delta_big_hp / s_h);
+        (l_term.powi(2) + c_term.powi(2) + h_term.powi(2) + r_t * c_term * h_term).sqrt() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lab_conversion() {
+        let white = srgb_to_lab(255., 255., 255.);
+        assert!((white[0] - 100.).abs() < 1e-3);
+        assert!(white[1].abs() < 1e-3 && white[2].abs() < 1e-3);
+        let red = srgb_to_lab(255., 0., 0.);
+        assert!((red[0] - 53.24).abs() < 1e-2);
+        assert!((red[1] - 80.09).abs() < 1e-2);
+        assert!((red[2] - 67.20).abs() < 1e-2);
+    }
+
+    #[test]
+    fn delta_e_2000_reference_pairs() {
+        // Pairs from Sharma, Wu & Dalal's CIEDE2000 test data.
+        let pairs = [
+            ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+            ([50., 2.5, 0.], [50., 0., -2.5], 4.3065),
+            ([50., 2.5, 0.], [73., 25., -18.], 27.1492),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            let d = DeltaE2000::default().distance(&lab1, &lab2);
+            assert!((d - expected).abs() < 1e-3, "{} != {}", d, expected);
+        }
+    }
+}
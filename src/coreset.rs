@@ -0,0 +1,111 @@
+//! Representative subsets of the records, for summarizing large datasets
+//! before further modeling.
+//!
+//! Centers are picked greedily, each one the record farthest from all
+//! centers so far (Gonzalez's algorithm), which covers the records within
+//! twice the smallest possible radius for that many centers. After a center
+//! is added, only records closer to it than the current covering radius can
+//! move closer to a center, and those are found with a radius search.
+use crate::distance::DistanceMetric;
+use crate::tree::{KDTree, RadiusCollector};
+
+impl<T: Clone> KDTree<T> {
+    /// Pick centers until `stop` holds for the number of centers and the
+    /// largest distance from any record to its nearest center.
+    fn farthest_point_centers<D: DistanceMetric, F: Fn(usize, f32) -> bool>(
+        &self,
+        distance_metric: &D,
+        stop: F,
+    ) -> Vec<usize> {
+        let mut centers = Vec::new();
+        // Start from the first record added, so results do not depend on
+        // the order the tree stores records in.
+        let Some(mut next) = (0..self.data.len()).min_by_key(|i| self.data[*i].id) else {
+            return centers;
+        };
+        let mut nearest_center = vec![f32::INFINITY; self.data.len()];
+        let mut radius = f32::INFINITY;
+        while !stop(centers.len(), radius) {
+            centers.push(next);
+            let mut collector = RadiusCollector::new(radius);
+            self.search(&self.data[next].point, &mut collector, distance_metric);
+            for found in collector.into_sorted_vec() {
+                let current = &mut nearest_center[found.data_pointer];
+                *current = current.min(found.distance);
+            }
+            // Each center is at no distance from itself, so ties go to
+            // records that are not centers yet.
+            (next, radius) = nearest_center.iter().enumerate().fold(
+                (next, f32::NEG_INFINITY),
+                |farthest, (i, d)| {
+                    if *d > farthest.1 {
+                        (i, *d)
+                    } else {
+                        farthest
+                    }
+                },
+            );
+        }
+        centers.into_iter().map(|i| self.data[i].id).collect()
+    }
+    /// Greedy k-center subset, `k` records spread over the data so that every
+    /// record is close to one of them, identified by their positions in the
+    /// data the tree was built from, in the order they were picked. Every
+    /// record is within twice the optimal covering radius of a center.
+    pub fn k_center<D: DistanceMetric>(&self, k: usize, distance_metric: &D) -> Vec<usize> {
+        self.farthest_point_centers(distance_metric, |count, radius| count >= k || radius <= 0.)
+    }
+    /// Epsilon net of the records, a subset such that every record is within
+    /// `epsilon` of a record in it, and records in it are more than `epsilon`
+    /// apart. Distances are in the units of the metric, squared for
+    /// [`SquaredEuclideanDistance`].
+    ///
+    /// [`SquaredEuclideanDistance`]: crate::SquaredEuclideanDistance
+    pub fn epsilon_net<D: DistanceMetric>(&self, epsilon: f32, distance_metric: &D) -> Vec<usize> {
+        self.farthest_point_centers(distance_metric, |_, radius| radius <= epsilon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data, KDTree};
+
+    #[test]
+    fn centers_cover_records() {
+        let points = (0..500).map(scattered_point).collect::<Vec<_>>();
+        let data = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Data::new(i, p.clone()))
+            .collect();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let covering = |centers: &[usize]| {
+            points
+                .iter()
+                .map(|p| {
+                    centers
+                        .iter()
+                        .map(|c| (p[0] - points[*c][0]).powi(2) + (p[1] - points[*c][1]).powi(2))
+                        .fold(f32::INFINITY, f32::min)
+                })
+                .fold(0., f32::max)
+        };
+        let centers = tree.k_center(10, &metric);
+        assert_eq!(centers.len(), 10);
+        assert_eq!(centers[0], 0);
+        assert!(covering(&centers) < covering(&centers[..5]));
+
+        let net = tree.epsilon_net(100., &metric);
+        assert!(covering(&net) <= 100.);
+        for (i, a) in net.iter().enumerate() {
+            for b in &net[i + 1..] {
+                let (a, b) = (&points[*a], &points[*b]);
+                assert!((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) > 100.);
+            }
+        }
+        // Asking for more centers than records gives every distinct record.
+        assert_eq!(tree.k_center(1000, &metric).len(), 500);
+    }
+}
@@ -0,0 +1,103 @@
+//! Nearest neighbor joins between Polars data frames, enabled with the
+//! `polars` feature.
+use polars::prelude::*;
+
+use crate::distance::SquaredEuclideanDistance;
+use crate::tree::{Data, KDTree, Point};
+
+/// Coordinate columns of a frame as f32, one vector per row, `None` for rows
+/// with a null coordinate.
+fn coordinate_rows(frame: &DataFrame, columns: &[&str]) -> PolarsResult<Vec<Option<Vec<f32>>>> {
+    let columns = columns
+        .iter()
+        .map(|name| frame.column(name)?.cast(&DataType::Float32))
+        .collect::<PolarsResult<Vec<_>>>()?;
+    let values = columns
+        .iter()
+        .map(|c| Ok(c.f32()?.iter().collect::<Vec<_>>()))
+        .collect::<PolarsResult<Vec<_>>>()?;
+    Ok((0..frame.height())
+        .map(|row| values.iter().map(|column| column[row]).collect())
+        .collect())
+}
+
+/// For every row of `left`, attach the columns of the nearest row of
+/// `right`, comparing the coordinates in `left_on` with those in `right_on`,
+/// and a `distance` column with the euclidean distance between them. Columns
+/// of `right` whose names are already used in `left` get `suffix` appended.
+/// Rows of `left` with a null coordinate get nulls, and rows of `right` with
+/// a null coordinate are never matched.
+pub fn nearest_join(
+    left: &DataFrame,
+    left_on: &[&str],
+    right: &DataFrame,
+    right_on: &[&str],
+    suffix: &str,
+) -> PolarsResult<DataFrame> {
+    polars_ensure!(
+        left_on.len() == right_on.len(),
+        ShapeMismatch: "nearest_join needs as many left_on as right_on columns, found {} and {}",
+        left_on.len(), right_on.len()
+    );
+    let records = coordinate_rows(right, right_on)?
+        .into_iter()
+        .enumerate()
+        .filter_map(|(row, point)| Some(Data::new(row as IdxSize, point?)))
+        .collect::<Vec<_>>();
+    let tree = KDTree::from_vec(records, 16)
+        .map_err(|e| polars_err!(ComputeError: "unable to build tree: {}", e))?;
+    let metric = SquaredEuclideanDistance::default();
+    let (rows, distances): (Vec<_>, Vec<_>) = coordinate_rows(left, left_on)?
+        .into_iter()
+        .map(|point| {
+            let nearest =
+                point.and_then(|p| tree.get_nearest_neighbors(&Point::new(p), 1, &metric).pop());
+            (
+                nearest.as_ref().map(|n| n.data),
+                nearest.map(|n| n.distance.sqrt()),
+            )
+        })
+        .unzip();
+    let rows = IdxCa::from_iter_options("row".into(), rows.into_iter());
+    let mut matched = right.take(&rows)?;
+    let left_names = left.get_column_names_owned();
+    for name in right.get_column_names_owned() {
+        if left_names.contains(&name) {
+            matched.rename(&name, format!("{}{}", name, suffix).into())?;
+        }
+    }
+    let distance = Column::new("distance".into(), distances);
+    let mut joined = left.hstack(matched.get_columns())?;
+    joined.with_column(distance)?;
+    Ok(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_nearest_rows() {
+        let stores = df!(
+            "name" => ["north", "south", "east"],
+            "x" => [0., 0., 10.],
+            "y" => [10., -10., 0.],
+        )
+        .unwrap();
+        let customers = df!(
+            "name" => ["a", "b", "c"],
+            "x" => [Some(1.), Some(9.), None],
+            "y" => [Some(8.), Some(1.), Some(0.)],
+        )
+        .unwrap();
+        let joined = nearest_join(&customers, &["x", "y"], &stores, &["x", "y"], "_store").unwrap();
+        assert_eq!(joined.height(), 3);
+        let store = joined.column("name_store").unwrap().str().unwrap();
+        assert_eq!(store.get(0), Some("north"));
+        assert_eq!(store.get(1), Some("east"));
+        assert_eq!(store.get(2), None);
+        let distance = joined.column("distance").unwrap().f32().unwrap();
+        assert!((distance.get(0).unwrap() - 5f32.sqrt()).abs() < 1e-6);
+        assert!(distance.get(2).is_none());
+    }
+}
@@ -1,17 +1,829 @@
-use crate::tree::Point;
+use smallvec::SmallVec;
+
+use crate::error::ClosestError;
+use crate::tree::{Point, INLINE_DIMENSIONS};
+
+/// Distance between two points, given as coordinate slices so metrics can
+/// also be used outside of the tree, on any row of coordinates.
 pub trait DistanceMetric {
-    fn distance(&self, p1: &Point, p2: &Point) -> f32;
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32;
+    /// Lower bound on the distance between `point` and any point lying on
+    /// the far side of the splitting plane where coordinate `axis` equals
+    /// `value`. The tree only descends into the far branch when this is
+    /// smaller than the current worst neighbor distance, so returning a value
+    /// that is too large silently drops true neighbors, while returning 0
+    /// searches every branch. Defaults to 0, always correct but never
+    /// pruning, so metrics override it with a bound in their own units to
+    /// make searches fast. The squared difference along the axis is only
+    /// right for squared euclidean distance.
+    fn min_distance_to_plane(&self, _point: &[f32], _axis: usize, _value: f32) -> f32 {
+        0.
+    }
+    /// Whether the distance never shrinks as a coordinate of the second point
+    /// moves away from the first point's along its axis, so the farthest
+    /// corner of a box is as far as any point in it. Aggregate queries only
+    /// take whole subtrees within a radius at once for such metrics.
+    /// Defaults to `false`, which is always correct.
+    fn grows_along_axes(&self) -> bool {
+        false
+    }
+    /// Number of coordinates the metric is made for, when it holds a
+    /// parameter for every axis. Trees check it against the dimension of
+    /// their records before searching. Defaults to `None`, any number.
+    fn dimension(&self) -> Option<usize> {
+        None
+    }
+    /// The true distance for a distance returned by [`DistanceMetric::distance`].
+    /// Metrics may return a reduced distance, cheaper to compute and ordering
+    /// points the same way, such as the squared euclidean distance, and undo
+    /// the reduction here. Defaults to the distance as is.
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced
+    }
+    /// The reduced distance for a true distance, the inverse of
+    /// [`DistanceMetric::to_true_distance`], to turn a radius given as a true
+    /// distance into the units the tree searches in.
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance
+    }
+}
+
+/// Shrink a pruning bound by a relative margin of 1e-4, so that rounding in
+/// a bound computed through divisions, roots or trigonometry never lifts it
+/// above the distance it stands for. The few operations such bounds take
+/// round by well under a thousandth of that, and the margin only costs the
+/// odd branch visited in vain.
+pub(crate) fn shave(bound: f32) -> f32 {
+    bound * (1. - 1e-4)
+}
+
+/// How the per axis terms of a distance are added up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Summation {
+    /// Plain f32 addition, the fastest.
+    #[default]
+    Naive,
+    /// Kahan compensated summation, which carries the rounding error of each
+    /// addition into the next. With hundreds of dimensions, naive sums lose
+    /// enough precision to reorder close neighbors.
+    Kahan,
+    /// Each term is added to an f64 total, rounded to f32 at the end. Slower
+    /// than naive addition, but coordinates stay stored as f32.
+    F64,
+}
+
+impl Summation {
+    /// Add up terms, for use in custom metrics.
+    pub fn sum<I: Iterator<Item = f32>>(self, terms: I) -> f32 {
+        match self {
+            Summation::Naive => terms.sum(),
+            Summation::Kahan => {
+                let (mut sum, mut compensation) = (0f32, 0f32);
+                for term in terms {
+                    let y = term - compensation;
+                    let t = sum + y;
+                    compensation = (t - sum) - y;
+                    sum = t;
+                }
+                sum
+            }
+            Summation::F64 => terms.map(|t| t as f64).sum::<f64>() as f32,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
-pub struct SquaredEuclideanDistance {}
+pub struct SquaredEuclideanDistance {
+    pub summation: Summation,
+}
 
 impl DistanceMetric for SquaredEuclideanDistance {
-    fn distance(&self, p1: &Point, p2: &Point) -> f32 {
-        p1.coordinates
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        self.summation
+            .sum(p1.iter().zip(p2).map(|(s1, s2)| (s1 - s2).powi(2)))
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).powi(2)
+    }
+    fn grows_along_axes(&self) -> bool {
+        true
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced.sqrt()
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance * distance
+    }
+}
+
+/// Manhattan (L1) distance, the sum of the absolute differences along
+/// every axis.
+#[derive(Debug, Default)]
+pub struct ManhattanDistance {
+    pub summation: Summation,
+}
+
+impl DistanceMetric for ManhattanDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        self.summation
+            .sum(p1.iter().zip(p2).map(|(s1, s2)| (s1 - s2).abs()))
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).abs()
+    }
+    fn grows_along_axes(&self) -> bool {
+        true
+    }
+}
+
+/// Squared euclidean distance with a weight on every axis, the sum of the
+/// squared differences along each axis times that axis' weight. Weights
+/// must not be negative, and there must be one per axis.
+#[derive(Debug, Clone)]
+pub struct WeightedEuclideanDistance {
+    pub weights: Vec<f32>,
+    pub summation: Summation,
+}
+
+impl WeightedEuclideanDistance {
+    pub fn new(weights: Vec<f32>) -> Self {
+        WeightedEuclideanDistance {
+            weights,
+            summation: Summation::default(),
+        }
+    }
+}
+
+impl DistanceMetric for WeightedEuclideanDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        self.summation.sum(
+            p1.iter()
+                .zip(p2)
+                .zip(&self.weights)
+                .map(|((s1, s2), w)| w * (s1 - s2).powi(2)),
+        )
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        self.weights[axis] * (point[axis] - value).powi(2)
+    }
+    fn grows_along_axes(&self) -> bool {
+        true
+    }
+    fn dimension(&self) -> Option<usize> {
+        Some(self.weights.len())
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced.sqrt()
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance * distance
+    }
+}
+
+/// Squared euclidean distance in a periodic box, where leaving one side
+/// re-enters from the opposite one, as in molecular dynamics simulations or
+/// wrap-around game worlds. Along every axis the difference is the shortest
+/// one over all periodic images, at most half the box length. An infinite
+/// length leaves that axis unwrapped.
+///
+/// Pruning relies on every coordinate of the records and queries lying
+/// within `[0, length)`, bring them into the box with
+/// [`PeriodicEuclideanDistance::wrap`] first.
+#[derive(Debug, Clone)]
+pub struct PeriodicEuclideanDistance {
+    pub lengths: Vec<f32>,
+    pub summation: Summation,
+}
+
+impl PeriodicEuclideanDistance {
+    pub fn new(lengths: Vec<f32>) -> Self {
+        PeriodicEuclideanDistance {
+            lengths,
+            summation: Summation::default(),
+        }
+    }
+    /// The image of a point within the box.
+    pub fn wrap(&self, point: &[f32]) -> Point {
+        point
+            .iter()
+            .zip(&self.lengths)
+            .map(|(c, length)| {
+                if length.is_finite() {
+                    c.rem_euclid(*length)
+                } else {
+                    *c
+                }
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl DistanceMetric for PeriodicEuclideanDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        self.summation.sum(
+            p1.iter()
+                .zip(p2)
+                .zip(&self.lengths)
+                .map(|((s1, s2), length)| {
+                    let difference = (s1 - s2).abs().rem_euclid(*length);
+                    difference.min(length - difference).powi(2)
+                }),
+        )
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        let (c, length) = (point[axis], self.lengths[axis]);
+        let direct = (c - value).abs();
+        if !length.is_finite() {
+            return direct.powi(2);
+        }
+        // The far side reaches the nearest edge of the box behind the plane,
+        // and wraps around from there to the point.
+        let around = if c <= value { c } else { length - c };
+        direct.min(around.max(0.)).powi(2)
+    }
+    fn dimension(&self) -> Option<usize> {
+        Some(self.lengths.len())
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced.sqrt()
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance * distance
+    }
+}
+
+/// Minkowski (Lp) distance raised to the power p, the sum of the absolute
+/// differences along every axis raised to the power p. Leaving out the final
+/// root keeps the order of neighbors while saving its cost, so p = 1 is
+/// [`ManhattanDistance`] and p = 2 is [`SquaredEuclideanDistance`]. An
+/// infinite p gives the largest absolute difference, the Chebyshev distance.
+/// p must be positive, and below 1 this is not a metric, but the tree still
+/// prunes correctly.
+#[derive(Debug)]
+pub struct MinkowskiDistance {
+    p: f32,
+    pub summation: Summation,
+}
+
+impl MinkowskiDistance {
+    /// Minkowski distance for a positive, possibly infinite, `p`. Anything
+    /// else, including NaN, is rejected.
+    pub fn new(p: f32) -> Result<Self, ClosestError> {
+        if p.is_nan() || p <= 0. {
+            return Err(ClosestError::InvalidParameter {
+                name: "p",
+                value: p,
+            });
+        }
+        Ok(MinkowskiDistance {
+            p,
+            summation: Summation::default(),
+        })
+    }
+    pub fn p(&self) -> f32 {
+        self.p
+    }
+    fn term(&self, difference: f32) -> f32 {
+        let difference = difference.abs();
+        if self.p == 1. {
+            difference
+        } else if self.p == 2. {
+            difference * difference
+        } else {
+            difference.powf(self.p)
+        }
+    }
+}
+
+impl DistanceMetric for MinkowskiDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        if self.p == f32::INFINITY {
+            return p1
+                .iter()
+                .zip(p2)
+                .map(|(s1, s2)| (s1 - s2).abs())
+                .fold(0., f32::max);
+        }
+        self.summation
+            .sum(p1.iter().zip(p2).map(|(s1, s2)| self.term(s1 - s2)))
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        let difference = point[axis] - value;
+        if self.p == f32::INFINITY {
+            difference.abs()
+        } else {
+            self.term(difference)
+        }
+    }
+    fn grows_along_axes(&self) -> bool {
+        true
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        if self.p == f32::INFINITY {
+            reduced
+        } else {
+            reduced.powf(1. / self.p)
+        }
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        if self.p == f32::INFINITY {
+            distance
+        } else {
+            distance.powf(self.p)
+        }
+    }
+}
+
+/// Squared Mahalanobis distance, `(p1 - p2)ᵀ S⁻¹ (p1 - p2)` for the inverse
+/// `S⁻¹` of the covariance matrix of the features, which accounts for their
+/// scales and correlations. With the identity it is squared euclidean
+/// distance.
+///
+/// Points past a splitting plane at a distance `t` along an axis are at
+/// least `t² / S_aa` away, where `S_aa` is the variance along that axis, the
+/// smallest value the quadratic form takes when only that coordinate is
+/// fixed. The variances are worked out once, when the metric is made.
+#[derive(Debug, Clone)]
+pub struct MahalanobisDistance {
+    inv_covariance: Vec<f64>,
+    variances: Vec<f32>,
+}
+
+impl MahalanobisDistance {
+    /// Make the metric from the rows of the inverse covariance matrix, which
+    /// must be square, symmetric and positive definite.
+    pub fn new(inv_covariance: Vec<Vec<f32>>) -> Result<Self, ClosestError> {
+        let n = inv_covariance.len();
+        if let Some(row) = inv_covariance.iter().find(|row| row.len() != n) {
+            return Err(ClosestError::DimensionMismatch {
+                expected: n,
+                found: row.len(),
+            });
+        }
+        let m = inv_covariance
+            .iter()
+            .flatten()
+            .map(|v| *v as f64)
+            .collect::<Vec<_>>();
+        for i in 0..n {
+            for j in 0..i {
+                if (m[i * n + j] - m[j * n + i]).abs() > 1e-6 * (m[i * n + j].abs() + 1.) {
+                    return Err(ClosestError::NotPositiveDefinite);
+                }
+            }
+        }
+        // Cholesky factor, lower triangular, m = l lᵀ.
+        let mut l = vec![0f64; n * n];
+        for i in 0..n {
+            for j in 0..=i {
+                let sum = m[i * n + j] - (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f64>();
+                if i == j {
+                    if sum <= 0. || !sum.is_finite() {
+                        return Err(ClosestError::NotPositiveDefinite);
+                    }
+                    l[i * n + i] = sum.sqrt();
+                } else {
+                    l[i * n + j] = sum / l[j * n + j];
+                }
+            }
+        }
+        // The covariance's diagonal is the squared norm of each column of
+        // l⁻¹, found by forward substitution against the unit vectors.
+        let variances = (0..n)
+            .map(|axis| {
+                let mut y = vec![0f64; n];
+                for i in axis..n {
+                    let e = if i == axis { 1. } else { 0. };
+                    let sum = e - (axis..i).map(|k| l[i * n + k] * y[k]).sum::<f64>();
+                    y[i] = sum / l[i * n + i];
+                }
+                y.iter().map(|v| v * v).sum::<f64>() as f32
+            })
+            .collect();
+        Ok(MahalanobisDistance {
+            inv_covariance: m,
+            variances,
+        })
+    }
+}
+
+impl DistanceMetric for MahalanobisDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let n = self.variances.len();
+        let delta = p1
+            .iter()
+            .zip(p2)
+            .map(|(s1, s2)| (s1 - s2) as f64)
+            .collect::<SmallVec<[f64; INLINE_DIMENSIONS]>>();
+        let mut sum = 0.;
+        for (i, di) in delta.iter().enumerate() {
+            let row = &self.inv_covariance[i * n..(i + 1) * n];
+            sum += di * row.iter().zip(&delta).map(|(m, dj)| m * dj).sum::<f64>();
+        }
+        sum.max(0.) as f32
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        shave((point[axis] - value).powi(2) / self.variances[axis])
+    }
+    fn dimension(&self) -> Option<usize> {
+        Some(self.variances.len())
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced.sqrt()
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance * distance
+    }
+}
+
+/// Squared euclidean distance that skips coordinates missing, as NaN, from
+/// either point, scaled up by the fraction of coordinates present, so that
+/// points missing some values stay comparable to complete ones. Points with
+/// no coordinates present in both are infinitely far apart.
+///
+/// Only query points may miss values in tree searches. The pruning bound
+/// assumes every record has the coordinate along the splitting axis, so
+/// records with missing values must be completed first, for instance with
+/// [`impute_knn`](crate::impute_knn).
+#[derive(Debug, Default)]
+pub struct NanEuclideanDistance {}
+
+impl DistanceMetric for NanEuclideanDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let (sum, present) = p1
+            .iter()
+            .zip(p2)
+            .filter(|(s1, s2)| !s1.is_nan() && !s2.is_nan())
+            .fold((0., 0), |(sum, present), (s1, s2)| {
+                (sum + (s1 - s2).powi(2), present + 1)
+            });
+        if present == 0 {
+            f32::INFINITY
+        } else {
+            sum * p1.len() as f32 / present as f32
+        }
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        // Only holds for records without missing values, see above.
+        let coordinate = point[axis];
+        if coordinate.is_nan() {
+            0.
+        } else {
+            (coordinate - value).powi(2)
+        }
+    }
+    fn to_true_distance(&self, reduced: f32) -> f32 {
+        reduced.sqrt()
+    }
+    fn to_reduced_distance(&self, distance: f32) -> f32 {
+        distance * distance
+    }
+}
+
+/// Earth mover's distance between one dimensional histograms with the same
+/// fixed bins, the total mass that must move times the number of bins it
+/// moves across, which is the sum of the absolute differences of the
+/// cumulative histograms. Unlike bin by bin distances, mass shifted to a
+/// neighboring bin counts for less than mass shifted far away. Histograms
+/// are compared as given, normalize them first to compare shapes only.
+///
+/// The difference in any one bin is at most the sum of the differences of
+/// the two cumulative sums around it, which gives the pruning bound.
+#[derive(Debug, Default)]
+pub struct EarthMoversDistance {}
+
+impl DistanceMetric for EarthMoversDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let mut carried = 0f32;
+        p1.iter()
+            .zip(p2)
+            .map(|(s1, s2)| {
+                carried += s1 - s2;
+                carried.abs()
+            })
+            .sum()
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).abs()
+    }
+}
+
+/// Bound [`FnMetric`] prunes with until given another, which never rules out
+/// a branch.
+fn no_pruning(_point: &[f32], _axis: usize, _value: f32) -> f32 {
+    0.
+}
+
+/// Distance metric made from a closure, for trying out a distance without
+/// writing a type for it.
+///
+/// Without a bound on the distance to splitting planes the tree can not
+/// skip any branch, and every query checks every record. Give one with
+/// [`FnMetric::with_plane_bound`] to prune, it must never be larger than the
+/// distance to any point on the far side of the plane.
+#[derive(Clone, Copy)]
+pub struct FnMetric<F, B = fn(&[f32], usize, f32) -> f32> {
+    distance: F,
+    plane_bound: B,
+}
+
+impl<F: Fn(&[f32], &[f32]) -> f32> FnMetric<F> {
+    pub fn new(distance: F) -> Self {
+        FnMetric {
+            distance,
+            plane_bound: no_pruning,
+        }
+    }
+}
+
+impl<F, B> FnMetric<F, B> {
+    /// Prune with `plane_bound(point, axis, value)`, a lower bound on the
+    /// distance from `point` to any point where coordinate `axis` is on the
+    /// other side of `value`, see [`DistanceMetric::min_distance_to_plane`].
+    pub fn with_plane_bound<G: Fn(&[f32], usize, f32) -> f32>(
+        self,
+        plane_bound: G,
+    ) -> FnMetric<F, G> {
+        FnMetric {
+            distance: self.distance,
+            plane_bound,
+        }
+    }
+}
+
+impl<F, B> DistanceMetric for FnMetric<F, B>
+where
+    F: Fn(&[f32], &[f32]) -> f32,
+    B: Fn(&[f32], usize, f32) -> f32,
+{
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        (self.distance)(p1, p2)
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (self.plane_bound)(point, axis, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{scattered_point, Data, KDTree, Neighbor, Point};
+
+    #[test]
+    fn compensated_summation() {
+        // One large term followed by many small ones, which naive f32
+        // addition drops entirely.
+        let terms = || std::iter::once(1e8f32).chain(std::iter::repeat_n(1f32, 1000));
+        assert_eq!(Summation::Naive.sum(terms()), 1e8);
+        assert_eq!(Summation::Kahan.sum(terms()), 1e8 + 1000.);
+        assert_eq!(Summation::F64.sum(terms()), 1e8 + 1000.);
+        let metric = SquaredEuclideanDistance {
+            summation: Summation::Kahan,
+        };
+        let p1 = vec![0.; 1001];
+        let mut p2 = vec![1.; 1001];
+        p2[0] = 1e4;
+        assert_eq!(metric.distance(&p1, &p2), 1e8 + 1000.);
+    }
+
+    #[test]
+    fn nan_euclidean() {
+        let metric = NanEuclideanDistance {};
+        assert_eq!(metric.distance(&[0., f32::NAN, 0.], &[1., 5., 1.]), 3.);
+        assert_eq!(metric.distance(&[0., 0., 0.], &[1., 1., 1.]), 3.);
+        assert_eq!(metric.distance(&[f32::NAN], &[1.]), f32::INFINITY);
+        assert_eq!(metric.min_distance_to_plane(&[f32::NAN, 0.], 0, 3.), 0.);
+    }
+
+    #[test]
+    fn earth_movers() {
+        let metric = EarthMoversDistance {};
+        // Moving all mass one bin costs less than moving it three bins.
+        assert_eq!(metric.distance(&[1., 0., 0., 0.], &[0., 1., 0., 0.]), 1.);
+        assert_eq!(metric.distance(&[1., 0., 0., 0.], &[0., 0., 0., 1.]), 3.);
+        assert_eq!(metric.distance(&[0.5, 0.5, 0.], &[0., 0.5, 0.5]), 1.);
+
+        let data = (0..300)
+            .map(|i| {
+                let bins = (0..6)
+                    .map(|b| ((i * 7 + b * 13) % 11) as f32)
+                    .collect::<Vec<_>>();
+                Data::new(i, bins)
+            })
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let query = Point::from([3., 1., 4., 1., 5., 9.]);
+        let nearest = tree.get_nearest_neighbors(&query, 5, &metric);
+        let mut expected = data
             .iter()
-            .zip(&p2.coordinates)
-            .map(|(s1, s2)| (s1 - s2).powi(2))
-            .sum::<f32>()
+            .map(|d| metric.distance(query.as_slice(), d.point.as_slice()))
+            .collect::<Vec<_>>();
+        expected.sort_by(f32::total_cmp);
+        assert_eq!(
+            nearest.iter().map(|n| n.distance).collect::<Vec<_>>(),
+            expected[..5]
+        );
+    }
+
+    #[test]
+    fn manhattan() {
+        let metric = ManhattanDistance::default();
+        assert_eq!(metric.distance(&[0., 0.], &[3., -4.]), 7.);
+        let data = (0..400)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        for query in [[50., 20.], [0., 46.], [-10., 200.]] {
+            let point = Point::from(query);
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(point.as_slice(), d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let nearest = tree.get_nearest_neighbors(&point, 10, &metric);
+            assert_eq!(
+                nearest.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..10]
+            );
+            let within = tree.get_neighbors_within_radius(&point, 12., &metric);
+            assert_eq!(within.len(), expected.iter().filter(|d| **d <= 12.).count());
+        }
+    }
+
+    #[test]
+    fn minkowski() {
+        let (p1, p2) = ([0., 1., -2.], [3., -1., 2.]);
+        assert_eq!(
+            MinkowskiDistance::new(1.).unwrap().distance(&p1, &p2),
+            ManhattanDistance::default().distance(&p1, &p2)
+        );
+        assert_eq!(
+            MinkowskiDistance::new(2.).unwrap().distance(&p1, &p2),
+            SquaredEuclideanDistance::default().distance(&p1, &p2)
+        );
+        assert_eq!(
+            MinkowskiDistance::new(f32::INFINITY)
+                .unwrap()
+                .distance(&p1, &p2),
+            4.
+        );
+        assert_eq!(MinkowskiDistance::new(3.).unwrap().distance(&p1, &p2), 99.);
+        for p in [0., -1., f32::NAN] {
+            assert!(matches!(
+                MinkowskiDistance::new(p),
+                Err(ClosestError::InvalidParameter { name: "p", .. })
+            ));
+        }
+
+        let data = (0..400)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let point = Point::from([50.5, 20.5]);
+        for p in [0.5, 1.5, 3., f32::INFINITY] {
+            let metric = MinkowskiDistance::new(p).unwrap();
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(point.as_slice(), d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let nearest = tree.get_nearest_neighbors(&point, 8, &metric);
+            assert_eq!(
+                nearest.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..8]
+            );
+        }
+    }
+
+    #[test]
+    fn mahalanobis() {
+        let identity = MahalanobisDistance::new(vec![vec![1., 0.], vec![0., 1.]]).unwrap();
+        assert_eq!(identity.distance(&[0., 0.], &[3., 4.]), 25.);
+        // Covariance [[4, 2], [2, 3]], whose inverse is [[3, 2], [2, 4]] / 8.
+        let inv_covariance = vec![vec![0.375, -0.25], vec![-0.25, 0.5]];
+        let metric = MahalanobisDistance::new(inv_covariance).unwrap();
+        assert!((metric.variances[0] - 4.).abs() < 1e-5);
+        assert!((metric.variances[1] - 3.).abs() < 1e-5);
+        assert!((metric.distance(&[0., 0.], &[2., 1.]) - 1.).abs() < 1e-6);
+        assert!(MahalanobisDistance::new(vec![vec![1., 2.], vec![2., 1.]]).is_err());
+        assert!(MahalanobisDistance::new(vec![vec![1., 0.]]).is_err());
+
+        let data = (0..800)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        for query in [[50.5, 20.5], [0., 46.], [-30., 80.]] {
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(&query, d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let found = tree.get_nearest_neighbors(&Point::from(query), 8, &metric);
+            assert_eq!(
+                found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..8]
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_euclidean() {
+        let metric = WeightedEuclideanDistance::new(vec![2., 0.5]);
+        assert_eq!(metric.distance(&[0., 0.], &[1., 2.]), 4.);
+        let data = (0..800)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        for metric in [
+            WeightedEuclideanDistance::new(vec![2., 1.]),
+            WeightedEuclideanDistance::new(vec![0.01, 10.]),
+            WeightedEuclideanDistance::new(vec![0., 1.]),
+        ] {
+            let query = [50.5, 20.5];
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(&query, d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let found = tree.get_nearest_neighbors(&Point::from(query), 8, &metric);
+            assert_eq!(
+                found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..8]
+            );
+        }
+    }
+
+    #[test]
+    fn closure_metric() {
+        let data = (0..500)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let chebyshev = |p1: &[f32], p2: &[f32]| {
+            p1.iter()
+                .zip(p2)
+                .map(|(s1, s2)| (s1 - s2).abs())
+                .fold(0., f32::max)
+        };
+        let query = Point::from([40.5, 12.25]);
+        let mut expected = data
+            .iter()
+            .map(|d| chebyshev(query.as_slice(), d.point.as_slice()))
+            .collect::<Vec<_>>();
+        expected.sort_by(f32::total_cmp);
+        expected.truncate(6);
+        let unpruned = FnMetric::new(chebyshev);
+        let pruned = unpruned.with_plane_bound(|point, axis, value| (point[axis] - value).abs());
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        assert_eq!(
+            distances(tree.get_nearest_neighbors(&query, 6, &unpruned)),
+            expected
+        );
+        assert_eq!(
+            distances(tree.get_nearest_neighbors(&query, 6, &pruned)),
+            expected
+        );
+        // Metrics without a plane bound of their own search every branch.
+        struct Chebyshev;
+        impl DistanceMetric for Chebyshev {
+            fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+                p1.iter()
+                    .zip(p2)
+                    .map(|(s1, s2)| (s1 - s2).abs())
+                    .fold(0., f32::max)
+            }
+        }
+        assert_eq!(
+            distances(tree.get_nearest_neighbors(&query, 6, &Chebyshev)),
+            expected
+        );
+    }
+
+    #[test]
+    fn periodic_euclidean() {
+        let metric = PeriodicEuclideanDistance::new(vec![10., f32::INFINITY]);
+        assert_eq!(metric.distance(&[0.5, 0.], &[9.5, 0.]), 1.);
+        assert_eq!(metric.distance(&[0.5, 0.], &[0.5, 9.]), 81.);
+        assert_eq!(metric.wrap(&[-1., -1.]), Point::from([9., -1.]));
+
+        let data = (0..800)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let metric = PeriodicEuclideanDistance::new(vec![101., 47.]);
+        for query in [[100.5, 46.5], [0.2, 23.], [50.5, 0.1], [30.25, 20.5]] {
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(&query, d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let found = tree.get_nearest_neighbors(&Point::from(query), 8, &metric);
+            assert_eq!(
+                found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..8]
+            );
+        }
     }
 }
@@ -0,0 +1,74 @@
+use crate::distance::DistanceMetric;
+
+/// Dynamic time warping distance between two sequences, which may differ in
+/// length. Each step costs the absolute difference of the aligned values.
+///
+/// With a `window`, only alignments within that many steps of the diagonal
+/// are considered (a Sakoe-Chiba band), which is faster and stops one value
+/// from matching a whole stretch of the other sequence. The window is widened
+/// to the difference in length when needed, so any two sequences can be
+/// aligned.
+///
+/// Dynamic time warping is not a true metric, it does not satisfy the
+/// triangle inequality, and gives no bound on the distance to a splitting
+/// plane. A [`KDTree`](crate::KDTree) can only hold sequences of one length,
+/// and visits every record when queried with it. Use a
+/// [`BruteForce`](crate::BruteForce) index for exact results over sequences
+/// of any length, or a [`VPTree`](crate::VPTree) for faster, approximate ones.
+#[derive(Debug, Default)]
+pub struct DynamicTimeWarping {
+    pub window: Option<usize>,
+}
+
+impl DistanceMetric for DynamicTimeWarping {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        if p1.is_empty() || p2.is_empty() {
+            return if p1.len() == p2.len() {
+                0.
+            } else {
+                f32::INFINITY
+            };
+        }
+        let window = self
+            .window
+            .map_or(p2.len(), |w| w.max(p1.len().abs_diff(p2.len())));
+        // Cost of the best alignment ending at each position of p2, for the
+        // previous and current position of p1.
+        let mut previous = vec![f32::INFINITY; p2.len() + 1];
+        let mut current = vec![f32::INFINITY; p2.len() + 1];
+        previous[0] = 0.;
+        for (i, a) in p1.iter().enumerate() {
+            current.fill(f32::INFINITY);
+            let lo = (i + 1).saturating_sub(window).max(1);
+            let hi = (i + 1 + window).min(p2.len());
+            for j in lo..=hi {
+                let best = previous[j - 1].min(previous[j]).min(current[j - 1]);
+                current[j] = (a - p2[j - 1]).abs() + best;
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        previous[p2.len()]
+    }
+    fn min_distance_to_plane(&self, _point: &[f32], _axis: usize, _value: f32) -> f32 {
+        0.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warped_sequences() {
+        let metric = DynamicTimeWarping::default();
+        let a = [0., 1., 2., 3., 2., 1., 0.];
+        let stretched = [0., 0., 1., 1., 2., 3., 3., 2., 1., 0.];
+        assert_eq!(metric.distance(&a, &stretched), 0.);
+        assert_eq!(metric.distance(&a, &[0., 1., 2., 4., 2., 1., 0.]), 1.);
+        // Without warping, the distance is the sum of differences.
+        let banded = DynamicTimeWarping { window: Some(0) };
+        let shifted = [1., 2., 3., 2., 1., 0., 0.];
+        assert_eq!(banded.distance(&a, &shifted), 6.);
+        assert_eq!(metric.distance(&a, &shifted), 1.);
+    }
+}
@@ -8,4 +8,46 @@ pub enum ClosestError {
     UnableToBuildTree,
     #[error("Root node is data.")]
     RootNodeIsData,
+    #[error("Expected points with {expected} dimensions, found {found}.")]
+    DimensionMismatch { expected: usize, found: usize },
+    #[error("Expected {expected} coordinates, found {found}.")]
+    CoordinateCountMismatch { expected: usize, found: usize },
+    #[error("Coordinate reference system mismatch, expected {expected} found {found}.")]
+    CrsMismatch { expected: String, found: String },
+    #[error("Unable to transform coordinates: {0}")]
+    TransformFailed(String),
+    #[error("Unable to create thread pool: {0}")]
+    ThreadPoolFailed(String),
+    #[error("The tree has no records.")]
+    EmptyTree,
+    #[error("At least one neighbor must be requested.")]
+    ZeroNeighbors,
+    #[error("Coordinate {axis} of the query point is not finite.")]
+    NonFiniteCoordinate { axis: usize },
+    #[error("Expected at least {needed} samples, found {found}.")]
+    NotEnoughSamples { needed: usize, found: usize },
+    #[error("Expected {expected} values, one per record, found {found}.")]
+    LengthMismatch { expected: usize, found: usize },
+    #[error("No record has the id {id}.")]
+    UnknownRecord { id: usize },
+    #[error("Unable to fetch the payload of record {id}: {reason}")]
+    PayloadUnavailable { id: usize, reason: String },
+    #[error("Invalid value {value} for the parameter {name}.")]
+    InvalidParameter { name: &'static str, value: f32 },
+    #[error("The matrix is not symmetric positive definite.")]
+    NotPositiveDefinite,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid stored tree: {0}")]
+    InvalidFormat(String),
+    #[error(
+        "Stored tree has format version {found}, this release only reads version {supported}."
+    )]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+    #[cfg(feature = "arrow")]
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[cfg(feature = "json")]
+    #[error("Unable to write JSON: {0}")]
+    Json(#[from] serde_json::Error),
 }
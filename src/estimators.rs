@@ -0,0 +1,248 @@
+//! Information theoretic estimators built on k-th nearest neighbor
+//! distances. Samples are rows of coordinates, all results are in nats.
+//!
+//! These estimators take logarithms of neighbor distances, so samples should
+//! not contain duplicate points, add a little noise to discrete data first.
+use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+use crate::error::ClosestError;
+use crate::tree::{Collector, KDTree, NearestCollector, Point};
+
+/// Largest absolute difference along any axis.
+struct ChebyshevDistance {}
+
+impl DistanceMetric for ChebyshevDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        p1.iter()
+            .zip(p2)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0., f32::max)
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).abs()
+    }
+}
+
+/// Counts the records strictly closer than a distance.
+struct CountCollector {
+    radius: f32,
+    count: usize,
+}
+
+impl Collector for CountCollector {
+    fn bound(&self) -> f32 {
+        self.radius
+    }
+    fn offer(&mut self, distance: f32, _data_pointer: usize) {
+        if distance < self.radius {
+            self.count += 1;
+        }
+    }
+}
+
+/// Digamma function, by recurrence up to 10 and then its asymptotic series.
+fn digamma(mut x: f64) -> f64 {
+    let mut result = 0.;
+    while x < 10. {
+        result -= 1. / x;
+        x += 1.;
+    }
+    let f = 1. / (x * x);
+    result + x.ln() - 0.5 / x - f * (1. / 12. - f * (1. / 120. - f * (1. / 252. - f / 240.)))
+}
+
+/// Log of the volume of the unit ball in `dimension` dimensions.
+fn ln_unit_ball_volume(dimension: usize) -> f64 {
+    // Gamma(d / 2 + 1), stepping down by one to Gamma(1) or Gamma(1 / 2).
+    let mut ln_gamma = if dimension.is_multiple_of(2) {
+        0.
+    } else {
+        std::f64::consts::PI.sqrt().ln()
+    };
+    let mut x = dimension as f64 / 2.;
+    while x > 0. {
+        ln_gamma += x.ln();
+        x -= 1.;
+    }
+    dimension as f64 / 2. * std::f64::consts::PI.ln() - ln_gamma
+}
+
+/// Check the samples share a dimension, and there are more than `k`.
+fn check_samples(samples: &[Vec<f32>], k: usize) -> Result<usize, ClosestError> {
+    if samples.len() <= k {
+        return Err(ClosestError::NotEnoughSamples {
+            needed: k + 1,
+            found: samples.len(),
+        });
+    }
+    let dimension = samples[0].len();
+    match samples.iter().find(|s| s.len() != dimension) {
+        Some(s) => Err(ClosestError::DimensionMismatch {
+            expected: dimension,
+            found: s.len(),
+        }),
+        None => Ok(dimension),
+    }
+}
+
+/// Distance to the k-th nearest neighbor in `tree`, skipping `skip`
+/// neighbors, which are the point itself when querying its own sample.
+fn kth_distance<D: DistanceMetric>(
+    tree: &KDTree<()>,
+    point: &[f32],
+    k: usize,
+    skip: usize,
+    distance_metric: &D,
+) -> f32 {
+    let mut collector = NearestCollector::new(k + skip);
+    tree.search(&Point::from_slice(point), &mut collector, distance_metric);
+    collector.bound()
+}
+
+/// Kozachenko-Leonenko estimate of the differential entropy of the
+/// distribution the samples are drawn from, using the distance of each
+/// sample to its k-th nearest neighbor.
+pub fn entropy_knn(samples: &[Vec<f32>], k: usize) -> Result<f64, ClosestError> {
+    let dimension = check_samples(samples, k)?;
+    let tree = KDTree::from_points(samples.iter().cloned(), 16)?;
+    let metric = SquaredEuclideanDistance::default();
+    let n = samples.len() as f64;
+    let mean_ln_distance = samples
+        .iter()
+        .map(|s| (kth_distance(&tree, s, k, 1, &metric) as f64).sqrt().ln())
+        .sum::<f64>()
+        / n;
+    Ok(digamma(n) - digamma(k as f64)
+        + ln_unit_ball_volume(dimension)
+        + dimension as f64 * mean_ln_distance)
+}
+
+/// Estimate of the Kullback-Leibler divergence D(P || Q), from samples of P
+/// and Q, comparing each sample of P's k-th nearest neighbor distance among
+/// the other samples of P and among the samples of Q (Wang, Kulkarni and
+/// Verdú, 2009).
+pub fn kl_divergence_knn(p: &[Vec<f32>], q: &[Vec<f32>], k: usize) -> Result<f64, ClosestError> {
+    let dimension = check_samples(p, k)?;
+    let q_dimension = check_samples(q, k.saturating_sub(1))?;
+    if q_dimension != dimension {
+        return Err(ClosestError::DimensionMismatch {
+            expected: dimension,
+            found: q_dimension,
+        });
+    }
+    let p_tree = KDTree::from_points(p.iter().cloned(), 16)?;
+    let q_tree = KDTree::from_points(q.iter().cloned(), 16)?;
+    let metric = SquaredEuclideanDistance::default();
+    let (n, m) = (p.len() as f64, q.len() as f64);
+    let sum_ln_ratio = p
+        .iter()
+        .map(|s| {
+            let within = kth_distance(&p_tree, s, k, 1, &metric) as f64;
+            let across = kth_distance(&q_tree, s, k, 0, &metric) as f64;
+            // Distances are squared, halve the log of their ratio.
+            (across / within).ln() / 2.
+        })
+        .sum::<f64>();
+    Ok(dimension as f64 / n * sum_ln_ratio + (m / (n - 1.)).ln())
+}
+
+/// Kraskov, Stögbauer and Grassberger's estimate of the mutual information
+/// between paired samples of X and Y, from the k-th nearest neighbor of each
+/// pair in the joint space, under the max norm.
+pub fn mutual_information_knn(
+    x: &[Vec<f32>],
+    y: &[Vec<f32>],
+    k: usize,
+) -> Result<f64, ClosestError> {
+    check_samples(x, k)?;
+    check_samples(y, k)?;
+    if x.len() != y.len() {
+        return Err(ClosestError::DifferingPositionLength);
+    }
+    let joint = x
+        .iter()
+        .zip(y)
+        .map(|(a, b)| a.iter().chain(b).copied().collect::<Vec<f32>>())
+        .collect::<Vec<_>>();
+    let joint_tree = KDTree::from_points(joint.iter().cloned(), 16)?;
+    let x_tree = KDTree::from_points(x.iter().cloned(), 16)?;
+    let y_tree = KDTree::from_points(y.iter().cloned(), 16)?;
+    let metric = ChebyshevDistance {};
+    // Neighbors strictly closer than `radius`, not counting the point itself.
+    let count = |tree: &KDTree<()>, point: &[f32], radius: f32| {
+        let mut collector = CountCollector { radius, count: 0 };
+        tree.search(&Point::from_slice(point), &mut collector, &metric);
+        collector.count - 1
+    };
+    let n = x.len() as f64;
+    let mean_digamma = joint
+        .iter()
+        .zip(x.iter().zip(y))
+        .map(|(point, (a, b))| {
+            let radius = kth_distance(&joint_tree, point, k, 1, &metric);
+            digamma(count(&x_tree, a, radius) as f64 + 1.)
+                + digamma(count(&y_tree, b, radius) as f64 + 1.)
+        })
+        .sum::<f64>()
+        / n;
+    Ok(digamma(k as f64) + digamma(n) - mean_digamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Uniform random points in the unit cube, from a fixed seed.
+    fn uniform(n: usize, dimension: usize, mut seed: u64) -> Vec<Vec<f32>> {
+        let mut next = move || {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 40) as f32 / (1u64 << 24) as f32
+        };
+        (0..n)
+            .map(|_| (0..dimension).map(|_| next()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn digamma_values() {
+        let euler_mascheroni = 0.5772156649015329;
+        assert!((digamma(1.) + euler_mascheroni).abs() < 1e-10);
+        assert!((digamma(0.5) + euler_mascheroni + 2. * 2f64.ln()).abs() < 1e-10);
+        assert!((ln_unit_ball_volume(2) - std::f64::consts::PI.ln()).abs() < 1e-12);
+        assert!((ln_unit_ball_volume(3) - (4. / 3. * std::f64::consts::PI).ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn uniform_estimates() {
+        // The uniform distribution on the unit square has zero entropy.
+        let square = uniform(2000, 2, 42);
+        assert!(entropy_knn(&square, 3).unwrap().abs() < 0.1);
+        // Stretched to twice the width, entropy is ln(2) higher.
+        let wide = square
+            .iter()
+            .map(|p| vec![p[0] * 2., p[1]])
+            .collect::<Vec<_>>();
+        let difference = entropy_knn(&wide, 3).unwrap() - entropy_knn(&square, 3).unwrap();
+        assert!((difference - 2f64.ln()).abs() < 0.05);
+        // Samples of the wide rectangle that fall in the square have a
+        // density half as large under the square's distribution.
+        let other = uniform(3000, 2, 7);
+        let left = other
+            .iter()
+            .map(|p| vec![p[0] * 2., p[1]])
+            .filter(|p| p[0] < 1.)
+            .collect::<Vec<_>>();
+        assert!((kl_divergence_knn(&left, &wide, 3).unwrap() - 2f64.ln()).abs() < 0.1);
+
+        let x = square.iter().map(|p| vec![p[0]]).collect::<Vec<_>>();
+        let y = square.iter().map(|p| vec![p[1]]).collect::<Vec<_>>();
+        assert!(mutual_information_knn(&x, &y, 3).unwrap().abs() < 0.05);
+        let noisy = square
+            .iter()
+            .map(|p| vec![p[0] + 0.01 * p[1]])
+            .collect::<Vec<_>>();
+        assert!(mutual_information_knn(&x, &noisy, 3).unwrap() > 2.);
+    }
+}
@@ -0,0 +1,70 @@
+//! Exporting records as newline delimited JSON, to audit what an index holds
+//! or diff the contents of two.
+use std::io::Write;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::ClosestError;
+use crate::tree::{Data, KDTree};
+
+/// One exported line.
+struct Line<'a, T: Clone>(&'a Data<T>);
+
+impl<T: Clone + Serialize> Serialize for Line<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut line = serializer.serialize_struct("Record", 3)?;
+        line.serialize_field("id", &self.0.id)?;
+        line.serialize_field("coordinates", self.0.point.as_slice())?;
+        line.serialize_field("data", &self.0.data)?;
+        line.end()
+    }
+}
+
+impl<T: Clone + Serialize> KDTree<T> {
+    /// Write every record as one line of JSON, with its id, coordinates and
+    /// data, as `{"id":0,"coordinates":[1.0,2.0],"data":...}`. Records are
+    /// written in the order they were added rather than the order the tree
+    /// stores them, so two trees holding the same records export the same
+    /// lines. Removed records are left out. Non finite coordinates are
+    /// written as `null`.
+    pub fn export_records_json<W: Write>(&self, mut writer: W) -> Result<(), ClosestError> {
+        let mut records = (0..self.data.len())
+            .filter(|i| !self.is_removed(*i))
+            .map(|i| &self.data[i])
+            .collect::<Vec<_>>();
+        records.sort_by_key(|d| d.id);
+        for record in records {
+            serde_json::to_writer(&mut writer, &Line(record))?;
+            writer.write_all(b"\n").map_err(serde_json::Error::io)?;
+        }
+        writer.flush().map_err(serde_json::Error::io)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_lines() {
+        let records = vec![
+            Data::new("b", vec![3., 1.]),
+            Data::new("a", vec![1., 2.]),
+            Data::new("c", vec![0.5, f32::NAN]),
+        ];
+        let mut tree = KDTree::from_vec(records.clone(), 1).unwrap();
+        tree.extend([Data::new("d", vec![2., 2.])]);
+        tree.delete(0);
+        let mut out = Vec::new();
+        tree.export_records_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "{\"id\":1,\"coordinates\":[1.0,2.0],\"data\":\"a\"}\n",
+                "{\"id\":2,\"coordinates\":[0.5,null],\"data\":\"c\"}\n",
+                "{\"id\":3,\"coordinates\":[2.0,2.0],\"data\":\"d\"}\n",
+            )
+        );
+    }
+}
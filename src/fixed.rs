@@ -0,0 +1,166 @@
+//! Nearest neighbor queries that never allocate, writing into a buffer the
+//! caller provides and walking the tree with a fixed size stack, for targets
+//! where allocation is unavailable or too costly at query time.
+use std::cmp::Ordering;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{at, KDTree, NodeOrDataPointer};
+
+/// Capacity of the search stack. At most one branch per level of the tree
+/// waits on the stack, and median splits halve the records at every level,
+/// so no tree addressable with a usize is deeper than this.
+const STACK_DEPTH: usize = 64;
+
+/// Insert a candidate into the sorted first `found` entries of `out`,
+/// dropping the farthest when full. Returns the new number of entries.
+fn offer(out: &mut [(f32, usize)], found: usize, distance: f32, data_pointer: usize) -> usize {
+    let k = out.len();
+    if found == k && distance.partial_cmp(&out[k - 1].0) != Some(Ordering::Less) {
+        return found;
+    }
+    let mut i = found.min(k - 1);
+    while i > 0 && out[i - 1].0 > distance {
+        out[i] = out[i - 1];
+        i -= 1;
+    }
+    out[i] = (distance, data_pointer);
+    (found + 1).min(k)
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Find the `out.len()` nearest neighbors to `point` without allocating,
+    /// writing `(distance, position)` pairs into `out` from nearest to
+    /// farthest, where the position is that of the record in
+    /// [`KDTree::records`]. Returns how many entries were written, fewer
+    /// than `out.len()` only when the tree holds fewer records.
+    pub fn k_nearest_into<D: DistanceMetric>(
+        &self,
+        point: &[f32],
+        distance_metric: &D,
+        out: &mut [(f32, usize)],
+    ) -> usize {
+        if out.is_empty() || self.data.is_empty() {
+            return 0;
+        }
+        self.assert_searchable(point, distance_metric);
+        let k = out.len();
+        let mut found = 0;
+        let bound = |out: &[(f32, usize)], found: usize| {
+            if found < k {
+                f32::INFINITY
+            } else {
+                out[k - 1].0
+            }
+        };
+        let mut stack: [(Option<&NodeOrDataPointer>, usize, f32); STACK_DEPTH] =
+            [(None, 0, 0.); STACK_DEPTH];
+        let mut pending = 0;
+        if self.indexed + self.inserted > 0 {
+            stack[0] = (Some(&self.root_node), 0, 0.);
+            pending = 1;
+        }
+        while pending > 0 {
+            pending -= 1;
+            let (node, mut depth, plane_distance) = stack[pending];
+            if plane_distance > bound(out, found) {
+                continue;
+            }
+            let mut node = node.expect("pending entries hold a node");
+            loop {
+                match node {
+                    NodeOrDataPointer::Node(n) => {
+                        let split = self.get_data_point(n.data_pointer).as_slice();
+                        if !self.is_removed(n.data_pointer) {
+                            let distance = distance_metric.distance(point, split);
+                            found = offer(out, found, distance, n.data_pointer);
+                        }
+                        let axis = depth % self.dimension;
+                        let split_value = *at(split, axis);
+                        let (near, far) = if at(point, axis) - split_value <= 0. {
+                            (n.left.as_ref(), n.right.as_ref())
+                        } else {
+                            (n.right.as_ref(), n.left.as_ref())
+                        };
+                        let far_distance =
+                            distance_metric.min_distance_to_plane(point, axis, split_value);
+                        stack[pending] = (Some(far), depth + 1, far_distance);
+                        pending += 1;
+                        node = near;
+                        depth += 1;
+                    }
+                    NodeOrDataPointer::Data((start, stop)) => {
+                        let overflow = self.leaf_overflow(*start).iter().copied();
+                        for data_pointer in (*start..*stop).chain(overflow) {
+                            if self.is_removed(data_pointer) {
+                                continue;
+                            }
+                            let distance = distance_metric
+                                .distance(point, self.get_data_point(data_pointer).as_slice());
+                            found = offer(out, found, distance, data_pointer);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        for data_pointer in self.unindexed() {
+            if self.is_removed(data_pointer) {
+                continue;
+            }
+            let distance =
+                distance_metric.distance(point, self.get_data_point(data_pointer).as_slice());
+            found = offer(out, found, distance, data_pointer);
+        }
+        found
+    }
+    /// Like [`KDTree::k_nearest_into`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`], `out` being empty counting as
+    /// asking for no neighbors.
+    pub fn try_k_nearest_into<D: DistanceMetric>(
+        &self,
+        point: &[f32],
+        distance_metric: &D,
+        out: &mut [(f32, usize)],
+    ) -> Result<usize, ClosestError> {
+        if out.is_empty() {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point, distance_metric)?;
+        Ok(self.k_nearest_into(point, distance_metric, out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data, Point};
+
+    #[test]
+    fn fixed_buffer_queries() {
+        let data = (0..3000)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        tree.extend((0..5).map(|i| Data::new(i, vec![50.2, 20.1 + i as f32])));
+        let metric = SquaredEuclideanDistance::default();
+        let mut out = [(0., 0); 6];
+        for query in [[50.2, 20.1], [0., 0.], [-30., 80.]] {
+            let found = tree.k_nearest_into(&query, &metric, &mut out);
+            assert_eq!(found, 6);
+            let expected = tree.get_nearest_neighbors(&Point::from(query), 6, &metric);
+            for ((distance, position), neighbor) in out.iter().zip(&expected) {
+                assert_eq!(*distance, neighbor.distance);
+                let record = &tree.records()[*position];
+                assert_eq!(
+                    metric.distance(&query, record.point().as_slice()),
+                    *distance
+                );
+            }
+        }
+        let small = KDTree::from_vec(vec![Data::new(0, vec![1., 1.])], 4).unwrap();
+        assert_eq!(small.k_nearest_into(&[0., 0.], &metric, &mut out), 1);
+        assert_eq!(out[0], (2., 0));
+    }
+}
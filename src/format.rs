@@ -0,0 +1,684 @@
+//! Binary format of trees, so they can be stored and loaded again without
+//! rebuilding, and checked to stay loadable across releases.
+//!
+//! All numbers are little endian. After an 8 byte magic string and the
+//! format version come the dimension, leaf size, counts and then every
+//! record in tree order: its id, its coordinates and its encoded data. The
+//! tree's structure is not stored, it follows from the record order and the
+//! leaf size alone. A CRC-32 of every byte before it ends the data, so
+//! corrupted files are rejected rather than loaded.
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::ClosestError;
+use crate::tree::{is_leaf_size, Data, KDTree, Node, NodeOrDataPointer, Point};
+
+/// First bytes of every stored tree.
+const MAGIC: [u8; 8] = *b"CLOSEST\0";
+
+/// Version of the format written by this release. Every earlier version
+/// stays loadable.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// CRC-32 (IEEE) lookup table, one entry per byte value.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+#[cfg(any(test, feature = "mmap"))]
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+/// Continue a CRC-32 over more bytes, from the state before its final
+/// inversion.
+pub(crate) fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, b| {
+        CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Binary encoding of the data of records, for storing trees.
+pub trait Encode: Sized {
+    /// Append the encoding of the value to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Read a value back from the start of `input`, advancing past it.
+    fn decode(input: &mut &[u8]) -> Result<Self, ClosestError>;
+}
+
+/// Bytes encoded into a buffer and written out in chunks, with a CRC-32 of
+/// every byte written, so that a stored tree is never held in memory whole
+/// besides the tree itself.
+pub(crate) struct ChecksumOutput<W: Write> {
+    writer: W,
+    pub(crate) buffer: Vec<u8>,
+    crc: u32,
+}
+
+impl<W: Write> ChecksumOutput<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        ChecksumOutput {
+            writer,
+            buffer: Vec::new(),
+            crc: !0,
+        }
+    }
+    /// Write out the buffered bytes once there are enough of them.
+    pub(crate) fn write_if_full(&mut self) -> Result<(), ClosestError> {
+        if self.buffer.len() >= 1 << 16 {
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+    fn write_buffer(&mut self) -> Result<(), ClosestError> {
+        self.crc = crc32_update(self.crc, &self.buffer);
+        self.writer.write_all(&self.buffer)?;
+        self.buffer.clear();
+        Ok(())
+    }
+    /// Write out what is left, and return the writer with the CRC-32 of
+    /// everything written through it.
+    pub(crate) fn finish(mut self) -> Result<(W, u32), ClosestError> {
+        self.write_buffer()?;
+        Ok((self.writer, !self.crc))
+    }
+}
+
+/// First bytes of data compressed with zstd.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Bytes read from a stream as they are decoded, so that a stored tree is
+/// never held in memory whole besides the tree itself.
+struct StreamInput<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    /// Start of the buffered bytes not decoded yet.
+    start: usize,
+    /// CRC-32 state over every decoded byte.
+    crc: u32,
+}
+
+impl<R: Read> StreamInput<R> {
+    fn new(reader: R) -> Self {
+        StreamInput {
+            reader,
+            buffer: Vec::new(),
+            start: 0,
+            crc: !0,
+        }
+    }
+    /// Read more bytes, at least as many as are buffered, and return whether
+    /// there were any left.
+    fn fill(&mut self) -> Result<bool, ClosestError> {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        let wanted = self.buffer.len().max(1 << 16) as u64;
+        let read = (&mut self.reader)
+            .take(wanted)
+            .read_to_end(&mut self.buffer)?;
+        Ok(read > 0)
+    }
+    /// Decode a value, reading more of the stream until there is enough of
+    /// it to decode.
+    fn decode<V, F: Fn(&mut &[u8]) -> Result<V, ClosestError>>(
+        &mut self,
+        decode: F,
+    ) -> Result<V, ClosestError> {
+        loop {
+            let mut input = &self.buffer[self.start..];
+            match decode(&mut input) {
+                Ok(value) => {
+                    let stop = self.buffer.len() - input.len();
+                    self.crc = crc32_update(self.crc, &self.buffer[self.start..stop]);
+                    self.start = stop;
+                    return Ok(value);
+                }
+                Err(error) => {
+                    if !self.fill()? {
+                        return Err(error);
+                    }
+                }
+            }
+        }
+    }
+    /// Every byte of the stream not decoded yet.
+    fn rest(&mut self) -> Result<&[u8], ClosestError> {
+        while self.fill()? {}
+        Ok(&self.buffer[self.start..])
+    }
+}
+
+fn truncated() -> ClosestError {
+    ClosestError::InvalidFormat("unexpected end of data".to_string())
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], ClosestError> {
+    if input.len() < len {
+        return Err(truncated());
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+macro_rules! encode_number {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self, ClosestError> {
+                let bytes = take(input, std::mem::size_of::<$t>())?;
+                Ok(<$t>::from_le_bytes(bytes.try_into().expect("sized slice")))
+            }
+        }
+    )*};
+}
+
+encode_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+/// Stored as 64 bits, so files do not depend on the platform's word size.
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ClosestError> {
+        usize::try_from(u64::decode(input)?)
+            .map_err(|_| ClosestError::InvalidFormat("value does not fit a usize".to_string()))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ClosestError> {
+        Ok(u8::decode(input)? != 0)
+    }
+}
+
+impl Encode for () {
+    fn encode(&self, _out: &mut Vec<u8>) {}
+    fn decode(_input: &mut &[u8]) -> Result<Self, ClosestError> {
+        Ok(())
+    }
+}
+
+/// Length in bytes, then UTF-8.
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, ClosestError> {
+        let len = usize::decode(input)?;
+        String::from_utf8(take(input, len)?.to_vec())
+            .map_err(|_| ClosestError::InvalidFormat("string is not UTF-8".to_string()))
+    }
+}
+
+/// Structure of the tree over records `start..stop` already in tree order.
+fn structure(start: usize, stop: usize, min_points: usize) -> NodeOrDataPointer {
+    let len = stop - start;
+    if is_leaf_size(len, min_points) {
+        return NodeOrDataPointer::Data((start, stop));
+    }
+    let median = start + (len >> 1);
+    NodeOrDataPointer::Node(Node {
+        data_pointer: median,
+        left: Box::new(structure(start, median, min_points)),
+        right: Box::new(structure(median + 1, stop, min_points)),
+    })
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Encode the coordinates and structure of the tree without the data of
+    /// the records, for data stored elsewhere under the records' ids. Load
+    /// it as a `KDTree<()>` with [`KDTree::from_bytes`], whose queries
+    /// return the ids, with [`KDTree::k_nearest`] for example, and attach
+    /// data to it again with [`KDTree::attach_payloads`].
+    pub fn geometry_to_bytes(&self) -> Vec<u8> {
+        self.write_with(Vec::new(), |_, _| {})
+            .expect("writing to memory does not fail")
+    }
+    /// Write the binary format, with the data of every record written by
+    /// `encode_data`, and return the writer. A tree with records inserted or
+    /// removed is written as it would be rebuilt, without rebuilding it.
+    fn write_with<W: Write, F: Fn(&T, &mut Vec<u8>)>(
+        &self,
+        writer: W,
+        encode_data: F,
+    ) -> Result<W, ClosestError> {
+        let order = (self.inserted > 0 || self.tombstones > 0).then(|| self.rebuilt_order());
+        let (len, indexed) = match &order {
+            Some(order) => (order.len(), order.len()),
+            None => (self.data.len(), self.indexed),
+        };
+        write_records(
+            writer,
+            self.records_dimension(),
+            self.min_points,
+            len,
+            indexed,
+            self.next_id,
+            |i, out| {
+                let record = &self.data[order.as_ref().map_or(i, |order| order[i])];
+                record.id.encode(out);
+                for c in record.point.as_slice() {
+                    c.encode(out);
+                }
+                encode_data(&record.data, out);
+            },
+        )
+    }
+}
+
+/// Write the header of the binary format, then `len` records, each encoded
+/// by `encode_record` from its position in tree order, then the checksum,
+/// and return the writer.
+pub(crate) fn write_records<W: Write, F: Fn(usize, &mut Vec<u8>)>(
+    writer: W,
+    dimension: usize,
+    min_points: usize,
+    len: usize,
+    indexed: usize,
+    next_id: usize,
+    encode_record: F,
+) -> Result<W, ClosestError> {
+    let mut out = ChecksumOutput::new(writer);
+    out.buffer.extend_from_slice(&MAGIC);
+    FORMAT_VERSION.encode(&mut out.buffer);
+    dimension.encode(&mut out.buffer);
+    min_points.encode(&mut out.buffer);
+    len.encode(&mut out.buffer);
+    indexed.encode(&mut out.buffer);
+    next_id.encode(&mut out.buffer);
+    // Removed records are never written.
+    0usize.encode(&mut out.buffer);
+    for i in 0..len {
+        encode_record(i, &mut out.buffer);
+        out.write_if_full()?;
+    }
+    let (mut writer, checksum) = out.finish()?;
+    writer.write_all(&checksum.to_le_bytes())?;
+    Ok(writer)
+}
+
+impl KDTree<()> {
+    /// Give every record the data `payload` returns for its id, as when
+    /// loading data stored apart from a tree saved with
+    /// [`KDTree::geometry_to_bytes`].
+    pub fn attach_payloads<T: Clone, F: FnMut(usize) -> T>(self, mut payload: F) -> KDTree<T> {
+        let data = self
+            .data
+            .into_iter()
+            .map(|record| Data {
+                data: payload(record.id),
+                point: record.point,
+                id: record.id,
+            })
+            .collect();
+        KDTree {
+            root_node: self.root_node,
+            data,
+            dimension: self.dimension,
+            min_points: self.min_points,
+            indexed: self.indexed,
+            tombstones: self.tombstones,
+            removed: self.removed,
+            largest_leaf: self.largest_leaf,
+            inserted: self.inserted,
+            overflow: self.overflow,
+            next_id: self.next_id,
+            positions: self.positions,
+            extent: self.extent,
+            policy: self.policy,
+            order: self.order,
+            recorder: self.recorder,
+        }
+    }
+}
+
+impl<T: Clone + Encode> KDTree<T> {
+    /// Encode the tree in the binary format, records added since it was
+    /// last built included. A tree with records placed by
+    /// [`KDTree::insert`] or removed with [`KDTree::delete`] is stored as it
+    /// would be rebuilt, since the format only stores the structure that
+    /// follows from the record order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.write_with(Vec::new(), T::encode)
+            .expect("writing to memory does not fail")
+    }
+    /// Write the tree to a writer in the format of [`KDTree::to_bytes`],
+    /// encoding records as they are written.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), ClosestError> {
+        self.write_with(writer, T::encode)?.flush()?;
+        Ok(())
+    }
+    /// Load a tree encoded with [`KDTree::to_bytes`], by this or any earlier
+    /// release. Data that is not a stored tree, is cut short, is corrupted
+    /// or was written by a newer release is rejected.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ClosestError> {
+        KDTree::read_from(bytes)
+    }
+    /// Load a tree encoded with [`KDTree::to_bytes`] from a reader, as
+    /// [`KDTree::from_bytes`] does, decoding records as they are read.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, ClosestError> {
+        let mut input = StreamInput::new(reader);
+        if !matches!(
+            input.decode(|i| take(i, MAGIC.len()).map(|magic| magic == MAGIC)),
+            Ok(true)
+        ) {
+            return Err(ClosestError::InvalidFormat("not a stored tree".to_string()));
+        }
+        let version = input.decode(u32::decode)?;
+        if version != FORMAT_VERSION {
+            return Err(ClosestError::UnsupportedFormatVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        let dimension = input.decode(usize::decode)?;
+        let min_points = input.decode(usize::decode)?;
+        let len = input.decode(usize::decode)?;
+        let indexed = input.decode(usize::decode)?;
+        let next_id = input.decode(usize::decode)?;
+        let tombstones = input.decode(usize::decode)?;
+        if indexed > len {
+            return Err(ClosestError::InvalidFormat(format!(
+                "{} indexed records out of {}",
+                indexed, len
+            )));
+        }
+        if dimension == 0 && len > 0 {
+            return Err(ClosestError::InvalidFormat(
+                "records without coordinates".to_string(),
+            ));
+        }
+        // Removed records are never written, so their count is always 0.
+        if tombstones != 0 {
+            return Err(ClosestError::InvalidFormat(format!(
+                "{} removed records in a stored tree",
+                tombstones
+            )));
+        }
+        // The counts are not trusted before the records are read.
+        let mut data = Vec::with_capacity(len.min(1 << 16));
+        for _ in 0..len {
+            let record = input.decode(|i| {
+                let id = usize::decode(i)?;
+                let coordinates = (0..dimension)
+                    .map(|_| f32::decode(i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Data {
+                    data: T::decode(i)?,
+                    point: Point::new(coordinates),
+                    id,
+                })
+            })?;
+            data.push(record);
+        }
+        let checksum = !input.crc;
+        let rest = input.rest()?;
+        if rest.len() < 4 {
+            return Err(truncated());
+        }
+        if rest.len() > 4 {
+            return Err(ClosestError::InvalidFormat(format!(
+                "{} bytes left after the last record",
+                rest.len() - 4
+            )));
+        }
+        if u32::from_le_bytes(rest.try_into().expect("sized slice")) != checksum {
+            return Err(ClosestError::InvalidFormat(
+                "checksum mismatch, the data is corrupted".to_string(),
+            ));
+        }
+        let root_node = structure(0, indexed, min_points);
+        let mut tree = KDTree::from_built(data, root_node, dimension, min_points);
+        tree.indexed = indexed;
+        tree.next_id = next_id;
+        Ok(tree)
+    }
+    /// Store the tree in a file, in the format of [`KDTree::to_bytes`].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ClosestError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+    /// Store the tree in a file compressed with zstd at the given level,
+    /// from 1 to 22, where 0 picks the default level, compressing records
+    /// as they are encoded. Load it with [`KDTree::load`].
+    #[cfg(feature = "zstd")]
+    pub fn save_compressed<P: AsRef<Path>>(&self, path: P, level: i32) -> Result<(), ClosestError> {
+        let encoder = zstd::Encoder::new(File::create(path)?, level)?;
+        self.write_with(encoder, T::encode)?.finish()?;
+        Ok(())
+    }
+    /// Load a tree stored with [`KDTree::save`], or with
+    /// [`KDTree::save_compressed`] when the `zstd` feature is enabled,
+    /// rejecting files as [`KDTree::from_bytes`] does. The file is decoded,
+    /// and decompressed, as it is read.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ClosestError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if !reader.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            return KDTree::read_from(reader);
+        }
+        #[cfg(feature = "zstd")]
+        let tree = KDTree::read_from(zstd::Decoder::with_buffer(reader)?);
+        #[cfg(not(feature = "zstd"))]
+        let tree = Err(ClosestError::InvalidFormat(
+            "compressed with zstd, loading it needs the `zstd` feature".to_string(),
+        ));
+        tree
+    }
+    /// Check that `bytes` hold a tree this release can load and query
+    /// correctly: besides loading it, every split record must still
+    /// separate the records on either side of it. Run this over stored
+    /// trees in a test to learn about incompatibilities before upgrading.
+    pub fn verify_compatibility(bytes: &[u8]) -> Result<(), ClosestError> {
+        let tree = KDTree::<T>::from_bytes(bytes)?;
+        for node in tree.nodes() {
+            let (Some((axis, value)), Some((left, right))) = (node.split(), node.children()) else {
+                continue;
+            };
+            let misplaced = left.records().any(|d| d.point.point(axis) > value)
+                || right.records().any(|d| d.point.point(axis) < value);
+            if misplaced {
+                return Err(ClosestError::InvalidFormat(format!(
+                    "records out of order around the split at depth {}",
+                    node.depth()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::grid_point;
+
+    fn colors() -> KDTree<String> {
+        let data = [
+            ("blue", [0., 0., 255.]),
+            ("red", [255., 0., 0.]),
+            ("navy", [17., 4., 89.]),
+            ("purple", [171., 3., 255.]),
+            ("light-blue", [61., 118., 224.]),
+            ("pink", [255., 3., 213.]),
+            ("yellow", [255., 234., 0.]),
+            ("green", [16., 145., 25.]),
+            ("orange", [255., 106., 0.]),
+        ]
+        .into_iter()
+        .map(|(name, rgb)| Data::new(name.to_string(), rgb.to_vec()))
+        .collect();
+        KDTree::from_vec(data, 2).unwrap()
+    }
+
+    fn grid() -> KDTree<u64> {
+        let data = (0..200u64)
+            .map(|i| Data::new(i, grid_point(i as usize)))
+            .collect();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        tree.extend((200..203u64).map(|i| Data::new(i, vec![0.5, i as f32 / 10.])));
+        tree
+    }
+
+    /// Trees stored by earlier releases, with the tree each was made from.
+    /// A new file is added whenever the format changes, and the old ones
+    /// are kept, so every stored version stays loadable.
+    #[test]
+    fn golden_files() {
+        let metric = SquaredEuclideanDistance::default();
+        let stored = include_bytes!("../tests/golden/v1_colors.bin");
+        KDTree::<String>::verify_compatibility(stored).unwrap();
+        let loaded = KDTree::<String>::from_bytes(stored).unwrap();
+        assert_eq!(loaded, colors());
+        let nearest = loaded.get_nearest_neighbors(&Point::from([237., 139., 69.]), 2, &metric);
+        let names = nearest.iter().map(|n| n.data.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["orange", "yellow"]);
+
+        let stored = include_bytes!("../tests/golden/v1_grid.bin");
+        KDTree::<u64>::verify_compatibility(stored).unwrap();
+        let loaded = KDTree::<u64>::from_bytes(stored).unwrap();
+        assert_eq!(loaded, grid());
+        let nearest = loaded.get_nearest_neighbors(&Point::from([0.5, 20.1]), 1, &metric);
+        assert_eq!(nearest[0].data, 201);
+
+        // Trees written now are byte for byte the latest golden files.
+        assert_eq!(
+            colors().to_bytes(),
+            include_bytes!("../tests/golden/v1_colors.bin")
+        );
+        assert_eq!(grid().to_bytes(), stored);
+    }
+
+    #[test]
+    fn rejects_invalid_bytes() {
+        let bytes = grid().to_bytes();
+        let error = |bytes: &[u8]| KDTree::<u64>::from_bytes(bytes).unwrap_err();
+        assert!(matches!(
+            error(b"not a tree"),
+            ClosestError::InvalidFormat(_)
+        ));
+        assert!(matches!(
+            error(&bytes[..bytes.len() - 3]),
+            ClosestError::InvalidFormat(_)
+        ));
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            error(&newer),
+            ClosestError::UnsupportedFormatVersion { .. }
+        ));
+        let header = |offset: usize, value: u64| {
+            let mut changed = bytes.clone();
+            changed[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            error(&changed)
+        };
+        assert!(matches!(
+            header(12, 0),
+            ClosestError::InvalidFormat(message) if message.contains("coordinates")
+        ));
+        assert!(matches!(
+            header(52, 3),
+            ClosestError::InvalidFormat(message) if message.contains("removed")
+        ));
+        let mut corrupted = bytes.clone();
+        corrupted[100] ^= 1;
+        assert!(matches!(
+            error(&corrupted),
+            ClosestError::InvalidFormat(message) if message.contains("checksum")
+        ));
+        let mut shuffled = bytes;
+        // Swap the x coordinates of the first and last indexed records, and
+        // checksum the result, as a release storing records in another
+        // order would.
+        let (first, last) = (60 + 8, 60 + 199 * 24 + 8);
+        let x = shuffled[first..first + 4].to_vec();
+        shuffled.copy_within(last..last + 4, first);
+        shuffled[last..last + 4].copy_from_slice(&x);
+        let body = shuffled.len() - 4;
+        let checksum = crc32(&shuffled[..body]);
+        shuffled[body..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(KDTree::<u64>::from_bytes(&shuffled).is_ok());
+        assert!(KDTree::<u64>::verify_compatibility(&shuffled).is_err());
+    }
+
+    #[test]
+    fn geometry_apart_from_data() {
+        let tree = colors();
+        let names = tree
+            .records()
+            .iter()
+            .map(|d| (d.id, d.data.clone()))
+            .collect::<std::collections::HashMap<_, _>>();
+        let bytes = tree.geometry_to_bytes();
+        assert!(bytes.len() < tree.to_bytes().len());
+        let geometry = KDTree::<()>::from_bytes(&bytes).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let ids = geometry.k_nearest(&Point::from([237., 139., 69.]), 2, &metric);
+        let found = ids
+            .iter()
+            .map(|(id, _)| names[id].as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec!["orange", "yellow"]);
+        assert_eq!(geometry.attach_payloads(|id| names[&id].clone()), tree);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("closest-{}.bin", std::process::id()));
+        let tree = grid();
+        tree.save(&path).unwrap();
+        let loaded = KDTree::<u64>::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), tree);
+        assert!(matches!(
+            KDTree::<u64>::load(&path),
+            Err(ClosestError::Io(_))
+        ));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        // Inserted and removed records are written as a rebuild stores them.
+        let mut changed = grid();
+        changed.insert(Data::new(300, vec![4.5, 4.5])).unwrap();
+        changed.delete(12);
+        let mut rebuilt = changed.clone();
+        rebuilt.rebuild();
+        assert_eq!(changed.to_bytes(), rebuilt.to_bytes());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_files() {
+        let path = std::env::temp_dir().join(format!("closest-{}.zst", std::process::id()));
+        let tree = grid();
+        tree.save_compressed(&path, 0).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len() as usize;
+        let loaded = KDTree::<u64>::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), tree);
+        // The grid's coordinates and ids repeat a lot.
+        assert!(size < tree.to_bytes().len() / 2);
+    }
+}
@@ -0,0 +1,605 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+use crate::error::ClosestError;
+use crate::format::{write_records, Encode};
+use crate::index::SpatialIndex;
+use crate::tree::{
+    assert_point, at, check_point, is_leaf_size, Collector, KDTree, NearestCollector, Neighbor,
+    Point, RadiusCollector, RawNeighbor,
+};
+use crate::warm::touch_pages;
+
+/// Coordinates stored one axis after another, so that all values of an axis
+/// are contiguous.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+enum Coordinates {
+    Full(Vec<f32>),
+    /// Each value is mapped onto 65536 evenly spaced steps between the
+    /// smallest and largest value of its axis.
+    Quantized {
+        values: Vec<u16>,
+        min: Vec<f32>,
+        step: Vec<f32>,
+    },
+}
+
+/// Read only KDTree, made with [`KDTree::freeze`].
+///
+/// Nodes are not stored at all, the tree's structure follows from the record
+/// order and leaf size alone, and coordinates are stored per axis rather than
+/// per record, optionally quantized to 16 bits. This is the form to keep
+/// trees in once they are built, and to share between threads, it is `Send`
+/// and `Sync` whenever the records' data is.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "StoredFrozenKDTree<T>",
+        bound(deserialize = "T: serde::Deserialize<'de>")
+    )
+)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrozenKDTree<T: Clone> {
+    coordinates: Coordinates,
+    pub(crate) data: Vec<T>,
+    pub(crate) ids: Vec<usize>,
+    dimension: usize,
+    min_points: usize,
+    /// Id the tree would give the next record, kept to store the tree.
+    next_id: usize,
+}
+
+/// A deserialized frozen tree, whose coordinates are checked to cover
+/// every record before it is used.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredFrozenKDTree<T: Clone> {
+    coordinates: Coordinates,
+    data: Vec<T>,
+    ids: Vec<usize>,
+    dimension: usize,
+    min_points: usize,
+    next_id: usize,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Clone> TryFrom<StoredFrozenKDTree<T>> for FrozenKDTree<T> {
+    type Error = ClosestError;
+    fn try_from(stored: StoredFrozenKDTree<T>) -> Result<Self, ClosestError> {
+        let values = stored.data.len() * stored.dimension;
+        let complete = stored.ids.len() == stored.data.len()
+            && stored.ids.iter().all(|id| *id < stored.next_id)
+            && match &stored.coordinates {
+                Coordinates::Full(full) => full.len() == values,
+                Coordinates::Quantized {
+                    values: v,
+                    min,
+                    step,
+                } => {
+                    v.len() == values
+                        && min.len() == stored.dimension
+                        && step.len() == stored.dimension
+                }
+            };
+        if !complete {
+            return Err(ClosestError::InvalidFormat(
+                "coordinates or ids missing for some records".to_string(),
+            ));
+        }
+        Ok(FrozenKDTree {
+            coordinates: stored.coordinates,
+            data: stored.data,
+            ids: stored.ids,
+            dimension: stored.dimension,
+            min_points: stored.min_points,
+            next_id: stored.next_id,
+        })
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Freeze the tree into a compact read only form, indexing any records
+    /// that were added since it was last built.
+    pub fn freeze(self) -> FrozenKDTree<T> {
+        let (tree, coordinates) = self.freeze_parts();
+        let len = tree.len();
+        let values = (0..tree.dimension)
+            .flat_map(|axis| (0..len).map(move |record| (axis, record)))
+            .map(|(axis, record)| coordinates[record * tree.dimension + axis])
+            .collect();
+        FrozenKDTree {
+            coordinates: Coordinates::Full(values),
+            ..tree
+        }
+    }
+    /// Freeze the tree, storing each coordinate in 16 bits. Queries are
+    /// approximate, found records and distances are those of the quantized
+    /// coordinates, which are within half a step of the originals, where a
+    /// step is 1/65535 of the axis' range.
+    pub fn freeze_quantized(self) -> FrozenKDTree<T> {
+        let (tree, coordinates) = self.freeze_parts();
+        let len = tree.len();
+        let (mut min, mut step) = (Vec::new(), Vec::new());
+        let mut values = Vec::with_capacity(coordinates.len());
+        for axis in 0..tree.dimension {
+            let axis_values = || (0..len).map(|record| coordinates[record * tree.dimension + axis]);
+            let lo = axis_values().fold(f32::INFINITY, f32::min);
+            let hi = axis_values().fold(f32::NEG_INFINITY, f32::max);
+            let axis_step = if hi > lo {
+                (hi - lo) / u16::MAX as f32
+            } else {
+                1.
+            };
+            values.extend(axis_values().map(|c| ((c - lo) / axis_step).round() as u16));
+            min.push(lo);
+            step.push(axis_step);
+        }
+        FrozenKDTree {
+            coordinates: Coordinates::Quantized { values, min, step },
+            ..tree
+        }
+    }
+    /// The frozen tree with no coordinates yet, and the coordinates of every
+    /// record in tree order.
+    fn freeze_parts(mut self) -> (FrozenKDTree<T>, Vec<f32>) {
+        if self.indexed < self.data.len() || self.inserted > 0 || self.tombstones > 0 {
+            self.rebuild();
+        }
+        let mut coordinates = Vec::with_capacity(self.data.len() * self.dimension);
+        let mut data = Vec::with_capacity(self.data.len());
+        let mut ids = Vec::with_capacity(self.data.len());
+        for record in self.data {
+            coordinates.extend(record.point.coordinates);
+            data.push(record.data);
+            ids.push(record.id);
+        }
+        let tree = FrozenKDTree {
+            coordinates: Coordinates::Full(Vec::new()),
+            data,
+            ids,
+            dimension: self.dimension,
+            min_points: self.min_points,
+            next_id: self.next_id,
+        };
+        (tree, coordinates)
+    }
+}
+
+impl<T: Clone> FrozenKDTree<T> {
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+    pub fn is_quantized(&self) -> bool {
+        matches!(self.coordinates, Coordinates::Quantized { .. })
+    }
+    fn coordinate(&self, record: usize, axis: usize) -> f32 {
+        let offset = axis * self.data.len() + record;
+        match &self.coordinates {
+            Coordinates::Full(values) => *at(values, offset),
+            Coordinates::Quantized { values, min, step } => {
+                at(min, axis) + *at(values, offset) as f32 * at(step, axis)
+            }
+        }
+    }
+    /// Touch every page of the coordinates, so they are in memory before the
+    /// first query.
+    pub fn warm(&self) {
+        match &self.coordinates {
+            Coordinates::Full(values) => touch_pages(values),
+            Coordinates::Quantized { values, min, step } => {
+                touch_pages(values);
+                touch_pages(min);
+                touch_pages(step);
+            }
+        }
+        touch_pages(&self.ids);
+    }
+    /// Coordinates of a record, in the position the tree stores it at.
+    pub fn point(&self, record: usize) -> Point {
+        Point::new(
+            (0..self.dimension)
+                .map(|axis| self.coordinate(record, axis))
+                .collect(),
+        )
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        self.neighbors_from(collector.into_sorted_vec())
+    }
+    /// Positions, in the data the tree was built from, of the k nearest
+    /// neighbors of a given point, ordered from nearest to farthest.
+    pub fn k_nearest_indices<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<usize> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| self.ids[r.data_pointer])
+            .collect()
+    }
+    /// Like [`FrozenKDTree::k_nearest_indices`], gathering the coordinates
+    /// of records into `buffer` rather than a buffer of its own, so queries
+    /// run one after another can share it.
+    pub fn k_nearest_indices_with_buffer<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+        buffer: &mut Vec<f32>,
+    ) -> Vec<usize> {
+        let mut collector = NearestCollector::new(k);
+        self.search_with(point, buffer, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| self.ids[r.data_pointer])
+            .collect()
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        self.neighbors_from(collector.into_sorted_vec())
+    }
+    /// Like [`FrozenKDTree::get_nearest_neighbors`], but returns an error
+    /// instead of an empty or meaningless result, with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point, distance_metric)?;
+        Ok(self.get_nearest_neighbors(point, k, distance_metric))
+    }
+    /// Like [`FrozenKDTree::get_neighbors_within_radius`], with the checks
+    /// of [`FrozenKDTree::try_get_nearest_neighbors`].
+    pub fn try_get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        self.check_query(point, distance_metric)?;
+        Ok(self.get_neighbors_within_radius(point, radius, distance_metric))
+    }
+    fn check_query<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        distance_metric: &D,
+    ) -> Result<(), ClosestError> {
+        if self.is_empty() {
+            return Err(ClosestError::EmptyTree);
+        }
+        check_point(point.as_slice(), self.dimension, distance_metric)
+    }
+    fn neighbors_from(&self, found: Vec<RawNeighbor>) -> Vec<Neighbor<T>> {
+        found
+            .into_iter()
+            .map(|r| Neighbor {
+                distance: r.distance,
+                data: self.data[r.data_pointer].clone(),
+            })
+            .collect()
+    }
+}
+
+impl<T: Clone + Encode> FrozenKDTree<T> {
+    /// Write the tree to a writer in the binary format of
+    /// [`KDTree::to_bytes`], quantized coordinates as the values they stand
+    /// for.
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), ClosestError> {
+        let len = self.len();
+        write_records(
+            writer,
+            self.dimension,
+            self.min_points,
+            len,
+            len,
+            self.next_id,
+            |record, out| {
+                self.ids[record].encode(out);
+                for axis in 0..self.dimension {
+                    self.coordinate(record, axis).encode(out);
+                }
+                self.data[record].encode(out);
+            },
+        )?
+        .flush()?;
+        Ok(())
+    }
+    /// Store the tree in a file, in the format of [`KDTree::save`].
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ClosestError> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+    /// Load a tree stored with [`FrozenKDTree::save`] or [`KDTree::save`],
+    /// with full coordinates. Freeze a tree loaded with [`KDTree::load`]
+    /// with [`KDTree::freeze_quantized`] to quantize it again.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ClosestError> {
+        Ok(KDTree::load(path)?.freeze())
+    }
+}
+
+impl<T: Clone> ImplicitTree for FrozenKDTree<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+    fn min_points(&self) -> usize {
+        self.min_points
+    }
+    fn coordinate(&self, record: usize, axis: usize) -> f32 {
+        FrozenKDTree::coordinate(self, record, axis)
+    }
+}
+
+/// A tree stored without nodes, whose structure follows from the record
+/// order and leaf size alone, as in a freshly built [`KDTree`].
+pub(crate) trait ImplicitTree {
+    fn len(&self) -> usize;
+    fn dimension(&self) -> usize;
+    fn min_points(&self) -> usize;
+    /// Coordinate of a record, by its position in tree order.
+    fn coordinate(&self, record: usize, axis: usize) -> f32;
+    fn search<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        self.search_with(point, &mut Vec::new(), collector, distance_metric);
+    }
+    /// Search with `buffer` to gather records' coordinates into, one at a
+    /// time, so its allocation can be reused across queries. Panics unless
+    /// `point` and `distance_metric` have the tree's dimension.
+    fn search_with<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        buffer: &mut Vec<f32>,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        if self.len() > 0 {
+            assert_point(point.as_slice(), self.dimension(), distance_metric);
+        }
+        buffer.clear();
+        buffer.resize(self.dimension(), 0.);
+        self.search_range(
+            point.as_slice(),
+            0,
+            self.len(),
+            0,
+            buffer,
+            collector,
+            distance_metric,
+        );
+    }
+    fn offer<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &[f32],
+        record: usize,
+        buffer: &mut [f32],
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        for (axis, c) in buffer.iter_mut().enumerate() {
+            *c = self.coordinate(record, axis);
+        }
+        collector.offer(distance_metric.distance(point, buffer), record);
+    }
+    #[allow(clippy::too_many_arguments)]
+    fn search_range<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &[f32],
+        start: usize,
+        stop: usize,
+        depth: usize,
+        buffer: &mut [f32],
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        let len = stop - start;
+        if is_leaf_size(len, self.min_points()) {
+            for record in start..stop {
+                self.offer(point, record, buffer, collector, distance_metric);
+            }
+            return;
+        }
+        let median = start + (len >> 1);
+        self.offer(point, median, buffer, collector, distance_metric);
+        let axis = depth % self.dimension();
+        let split_value = self.coordinate(median, axis);
+        let (close, away) = if at(point, axis) - split_value <= 0. {
+            ((start, median), (median + 1, stop))
+        } else {
+            ((median + 1, stop), (start, median))
+        };
+        self.search_range(
+            point,
+            close.0,
+            close.1,
+            depth + 1,
+            buffer,
+            collector,
+            distance_metric,
+        );
+        if distance_metric.min_distance_to_plane(point, axis, split_value) <= collector.bound() {
+            self.search_range(
+                point,
+                away.0,
+                away.1,
+                depth + 1,
+                buffer,
+                collector,
+                distance_metric,
+            );
+        }
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for FrozenKDTree<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k, &SquaredEuclideanDistance::default())
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius, &SquaredEuclideanDistance::default())
+    }
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn frozen_matches_tree() {
+        let data = (0..300)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 8).unwrap();
+        tree.extend((300..310).map(|i| Data::new(i, vec![i as f32, 0.5])));
+        let frozen = tree.clone().freeze();
+        let quantized = tree.clone().freeze_quantized();
+        assert_eq!(frozen.len(), 310);
+        let last = frozen.k_nearest_indices(
+            &Point::new(vec![309., 0.5]),
+            1,
+            &SquaredEuclideanDistance::default(),
+        );
+        assert_eq!(last, vec![309]);
+        assert!(quantized.is_quantized());
+        let metric = SquaredEuclideanDistance::default();
+        for i in 0..40 {
+            let point = Point::new(vec![i as f32 * 7.5, i as f32 * 1.1]);
+            let expected = tree.get_nearest_neighbors(&point, 5, &metric);
+            let found = frozen.get_nearest_neighbors(&point, 5, &metric);
+            let distances =
+                |n: &[Neighbor<usize>]| n.iter().map(|n| n.distance).collect::<Vec<_>>();
+            assert_eq!(distances(&expected), distances(&found));
+            // Coordinates are within 0 to 310, a quantization step is well
+            // under a hundredth.
+            let nearest = quantized.get_nearest_neighbors(&point, 1, &metric);
+            assert!((nearest[0].distance - expected[0].distance).abs() < 0.5);
+        }
+        let mut buffer = Vec::new();
+        for i in 0..10 {
+            let point = Point::new(vec![i as f32 * 31., i as f32 * 4.]);
+            assert_eq!(
+                frozen.k_nearest_indices_with_buffer(&point, 3, &metric, &mut buffer),
+                frozen.k_nearest_indices(&point, 3, &metric)
+            );
+        }
+        let short = Point::new(vec![1.]);
+        assert!(matches!(
+            frozen.try_get_nearest_neighbors(&short, 1, &metric),
+            Err(ClosestError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(frozen
+            .try_get_neighbors_within_radius(&Point::new(vec![1., 2.]), 4., &metric)
+            .is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "query points must have 2 coordinates, found 3")]
+    fn long_query() {
+        let data = (0..100)
+            .map(|i| Data::new(i, vec![i as f32, 0.]))
+            .collect::<Vec<_>>();
+        let frozen = KDTree::from_vec(data, 8).unwrap().freeze();
+        frozen.get_nearest_neighbors(
+            &Point::new(vec![1., 2., 3.]),
+            1,
+            &SquaredEuclideanDistance::default(),
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let data = (0..300)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let path = std::env::temp_dir().join(format!("closest-frozen-{}.bin", std::process::id()));
+        let metric = SquaredEuclideanDistance::default();
+        for frozen in [tree.clone().freeze(), tree.clone().freeze_quantized()] {
+            frozen.save(&path).unwrap();
+            let loaded = FrozenKDTree::<usize>::load(&path).unwrap();
+            assert!(!loaded.is_quantized());
+            for i in 0..20 {
+                let point = Point::new(vec![i as f32 * 5., i as f32 * 2.]);
+                let found = |tree: &FrozenKDTree<usize>| {
+                    tree.get_nearest_neighbors(&point, 5, &metric)
+                        .iter()
+                        .map(|n| (n.data, n.distance))
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(found(&loaded), found(&frozen));
+            }
+        }
+        let mut bytes = Vec::new();
+        tree.clone().freeze().write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, tree.to_bytes());
+        // The next id is kept when the records with the largest ids are gone.
+        let mut tree = tree;
+        assert!(tree.delete(299));
+        bytes.clear();
+        tree.clone().freeze().write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, tree.to_bytes());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let data = (0..100)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        for frozen in [tree.clone().freeze(), tree.freeze_quantized()] {
+            let json = serde_json::to_string(&frozen).unwrap();
+            let loaded: FrozenKDTree<usize> = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded, frozen);
+        }
+    }
+}
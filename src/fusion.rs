@@ -0,0 +1,155 @@
+//! Hybrid search, fusing the ranked results of several searches, over
+//! different indexes or with different metrics, into one ranking.
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::tree::Neighbor;
+
+/// A search run by [`Fusion`], given the number of neighbors to find.
+type Search<'a, T> = dyn Fn(usize) -> Vec<Neighbor<T>> + 'a;
+
+/// How the results of the searches are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion, every record scores its weight divided by
+    /// `constant` plus its rank in every result, counting from 1. Ranks do
+    /// not depend on the scale of the distances, so results of any metric
+    /// combine. 60 is the usual constant.
+    ReciprocalRank { constant: f32 },
+    /// Every record scores its weight times its similarity in every result,
+    /// the distance rescaled to 1 for the nearest record of that result and
+    /// 0 for the farthest.
+    Score,
+}
+
+impl Default for FusionMethod {
+    fn default() -> Self {
+        FusionMethod::ReciprocalRank { constant: 60. }
+    }
+}
+
+/// Searches whose results are fused into one ranking. Records are matched
+/// across the results by their data, so every search must return the same
+/// data for the same record, such as an id.
+pub struct Fusion<'a, T: Clone> {
+    pub method: FusionMethod,
+    /// Number of neighbors every search is asked for, `k` when not set.
+    /// Deeper results let records that rank fair in every search rise.
+    pub candidates: Option<usize>,
+    searches: Vec<(f32, Box<Search<'a, T>>)>,
+}
+
+impl<'a, T: Clone + Eq + Hash> Fusion<'a, T> {
+    pub fn new(method: FusionMethod) -> Self {
+        Fusion {
+            method,
+            candidates: None,
+            searches: Vec::new(),
+        }
+    }
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = Some(candidates);
+        self
+    }
+    /// Add a search, given the number of neighbors to find, such as
+    /// `|k| tree.get_nearest_neighbors(&point, k, &metric)`, with the weight
+    /// of its results.
+    pub fn search<F: Fn(usize) -> Vec<Neighbor<T>> + 'a>(mut self, weight: f32, search: F) -> Self {
+        self.searches.push((weight, Box::new(search)));
+        self
+    }
+    /// Run every search and return the k records with the highest fused
+    /// scores, highest first, with their scores.
+    pub fn top(&self, k: usize) -> Vec<(T, f32)> {
+        let candidates = self.candidates.unwrap_or(k);
+        let mut scores: HashMap<T, f32> = HashMap::new();
+        let mut order = Vec::new();
+        for (weight, search) in &self.searches {
+            let found = search(candidates);
+            let nearest = found.first().map_or(0., |n| n.distance);
+            let farthest = found.last().map_or(0., |n| n.distance);
+            for (rank, neighbor) in found.into_iter().enumerate() {
+                let score = match self.method {
+                    FusionMethod::ReciprocalRank { constant } => {
+                        weight / (constant + rank as f32 + 1.)
+                    }
+                    FusionMethod::Score if farthest > nearest => {
+                        weight * (farthest - neighbor.distance) / (farthest - nearest)
+                    }
+                    FusionMethod::Score => *weight,
+                };
+                match scores.get_mut(&neighbor.data) {
+                    Some(total) => *total += score,
+                    None => {
+                        order.push(neighbor.data.clone());
+                        scores.insert(neighbor.data, score);
+                    }
+                }
+            }
+        }
+        // Ties keep the order records were first found in.
+        let mut fused = order
+            .into_iter()
+            .map(|data| {
+                let score = scores[&data];
+                (data, score)
+            })
+            .collect::<Vec<_>>();
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+        fused.truncate(k);
+        fused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::{ManhattanDistance, SquaredEuclideanDistance};
+    use crate::tree::{grid_point, Data, KDTree, Point};
+
+    #[test]
+    fn fused_rankings() {
+        // Every record has an embedding and a location, indexed apart.
+        let embeddings =
+            KDTree::from_vec((0..100).map(|i| Data::new(i, grid_point(i))).collect(), 4).unwrap();
+        let locations = KDTree::from_vec(
+            (0..100)
+                .map(|i| Data::new(i, vec![(i * 37 % 101) as f32]))
+                .collect(),
+            4,
+        )
+        .unwrap();
+        let (embedding, location) = (Point::from([2., 3.]), Point::from([50.]));
+        let euclidean = SquaredEuclideanDistance::default();
+        let manhattan = ManhattanDistance::default();
+        let by_embedding = |k| embeddings.get_nearest_neighbors(&embedding, k, &euclidean);
+        let by_location = |k| locations.get_nearest_neighbors(&location, k, &manhattan);
+
+        // A single search keeps its own order.
+        let alone = Fusion::new(FusionMethod::default())
+            .search(1., by_embedding)
+            .top(5);
+        let expected = by_embedding(5)
+            .into_iter()
+            .map(|n| n.data)
+            .collect::<Vec<_>>();
+        assert_eq!(alone.iter().map(|f| f.0).collect::<Vec<_>>(), expected);
+
+        for method in [FusionMethod::default(), FusionMethod::Score] {
+            let fused = Fusion::new(method)
+                .candidates(100)
+                .search(1., by_embedding)
+                .search(1., by_location)
+                .top(3);
+            assert_eq!(fused.len(), 3);
+            assert!(fused.windows(2).all(|w| w[0].1 >= w[1].1));
+            // The top record does well in both rankings.
+            let rank = |found: Vec<Neighbor<usize>>, data| {
+                found.iter().position(|n| n.data == data).unwrap()
+            };
+            let best = fused[0].0;
+            assert!(rank(by_embedding(100), best) < 30);
+            assert!(rank(by_location(100), best) < 30);
+        }
+    }
+}
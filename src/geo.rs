@@ -0,0 +1,503 @@
+//! Geographic queries, enabled with the `geo` feature.
+//!
+//! Polygons are defined over the first two coordinates of the tree's points,
+//! in the same order the points store them. Great circle queries expect the
+//! tree to be built from [`lat_lng_to_ecef`] coordinates. Being on the
+//! sphere, those coordinates have no seam at the antimeridian: points on
+//! either side of ±180° longitude are as close in the tree as they are on
+//! the ground, so neither construction nor pruning needs special cases.
+use crate::bounds::BoundingBox;
+use crate::distance::{shave, DistanceMetric, SquaredEuclideanDistance};
+use crate::error::ClosestError;
+use crate::tree::{Collector, Data, KDTree, NearestCollector, Neighbor, Point, RadiusCollector};
+
+/// Mean radius of the earth.
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Convert a latitude and longitude in degrees to earth centered, earth
+/// fixed coordinates on the unit sphere. Squared euclidean distance between
+/// these coordinates grows with great circle distance, so a tree built from
+/// them answers geographic queries without any distortion near the poles.
+pub fn lat_lng_to_ecef(lat: f32, lng: f32) -> Vec<f32> {
+    let (lat, lng) = ((lat as f64).to_radians(), (lng as f64).to_radians());
+    vec![
+        (lat.cos() * lng.cos()) as f32,
+        (lat.cos() * lng.sin()) as f32,
+        lat.sin() as f32,
+    ]
+}
+
+/// Squared chord length on the unit sphere spanning a great circle distance.
+fn meters_to_squared_chord(meters: f64) -> f32 {
+    let angle = (meters / EARTH_RADIUS_METERS).min(std::f64::consts::PI);
+    (2. * (angle / 2.).sin()).powi(2) as f32
+}
+
+/// Great circle distance spanned by a squared chord length on the unit sphere.
+fn squared_chord_to_meters(squared_chord: f32) -> f32 {
+    let half_chord = ((squared_chord.max(0.) as f64).sqrt() / 2.).min(1.);
+    (2. * half_chord.asin() * EARTH_RADIUS_METERS) as f32
+}
+
+/// Great circle distance between `[latitude, longitude]` points in degrees,
+/// by the haversine formula, in the units of `radius`, meters on the earth
+/// by default.
+///
+/// Pruning needs no preprocessing of the coordinates: any point past a
+/// latitude split is at least the difference in latitude away, and any point
+/// past a longitude split lies between that meridian and the antimeridian,
+/// so is at least as far as the nearer of the two great circles through
+/// them.
+#[derive(Debug)]
+pub struct HaversineDistance {
+    pub radius: f64,
+}
+
+impl Default for HaversineDistance {
+    fn default() -> Self {
+        HaversineDistance {
+            radius: EARTH_RADIUS_METERS,
+        }
+    }
+}
+
+impl DistanceMetric for HaversineDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let (lat1, lng1) = ((p1[0] as f64).to_radians(), (p1[1] as f64).to_radians());
+        let (lat2, lng2) = ((p2[0] as f64).to_radians(), (p2[1] as f64).to_radians());
+        let h = ((lat2 - lat1) / 2.).sin().powi(2)
+            + lat1.cos() * lat2.cos() * ((lng2 - lng1) / 2.).sin().powi(2);
+        (2. * h.sqrt().min(1.).asin() * self.radius) as f32
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        let angle = if axis == 0 {
+            ((point[0] - value).abs() as f64).to_radians()
+        } else {
+            let (lat, lng) = (
+                (point[0] as f64).to_radians(),
+                (point[1] as f64).to_radians(),
+            );
+            let across =
+                ((lng - (value as f64).to_radians()).sin().abs()).min(lng.sin().abs()) * lat.cos();
+            across.min(1.).asin()
+        };
+        shave((angle * self.radius) as f32)
+    }
+}
+
+/// Simple polygon, defined by the vertices of its exterior ring. The ring is
+/// closed automatically, repeating the first vertex is not needed.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    vertices: Vec<[f32; 2]>,
+    min: [f32; 2],
+    max: [f32; 2],
+}
+
+/// Orientation of the triangle a, b, c, positive if counter clockwise.
+fn orientation(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Check if c lies within the bounding box of segment a, b.
+fn on_segment(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool {
+    a[0].min(b[0]) <= c[0]
+        && c[0] <= a[0].max(b[0])
+        && a[1].min(b[1]) <= c[1]
+        && c[1] <= a[1].max(b[1])
+}
+
+fn segments_intersect(p1: [f32; 2], p2: [f32; 2], q1: [f32; 2], q2: [f32; 2]) -> bool {
+    let d1 = orientation(q1, q2, p1);
+    let d2 = orientation(q1, q2, p2);
+    let d3 = orientation(p1, p2, q1);
+    let d4 = orientation(p1, p2, q2);
+    if ((d1 > 0. && d2 < 0.) || (d1 < 0. && d2 > 0.))
+        && ((d3 > 0. && d4 < 0.) || (d3 < 0. && d4 > 0.))
+    {
+        return true;
+    }
+    (d1 == 0. && on_segment(q1, q2, p1))
+        || (d2 == 0. && on_segment(q1, q2, p2))
+        || (d3 == 0. && on_segment(p1, p2, q1))
+        || (d4 == 0. && on_segment(p1, p2, q2))
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<[f32; 2]>) -> Self {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for v in &vertices {
+            for i in 0..2 {
+                min[i] = min[i].min(v[i]);
+                max[i] = max[i].max(v[i]);
+            }
+        }
+        Polygon { vertices, min, max }
+    }
+    fn edges(&self) -> impl Iterator<Item = ([f32; 2], [f32; 2])> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+    }
+    /// Check if a location falls inside the polygon.
+    pub fn contains(&self, location: [f32; 2]) -> bool {
+        let [x, y] = location;
+        let mut inside = false;
+        for (a, b) in self.edges() {
+            if (a[1] > y) != (b[1] > y) && x < (b[0] - a[0]) * (y - a[1]) / (b[1] - a[1]) + a[0] {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+    /// Check if the polygon overlaps the first two axes of a region.
+    fn intersects(&self, region: &BoundingBox) -> bool {
+        // Clip the region to the polygon's extent, regions in the tree can be unbounded.
+        let min = [
+            region.min.point(0).max(self.min[0]),
+            region.min.point(1).max(self.min[1]),
+        ];
+        let max = [
+            region.max.point(0).min(self.max[0]),
+            region.max.point(1).min(self.max[1]),
+        ];
+        if min[0] > max[0] || min[1] > max[1] {
+            return false;
+        }
+        let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+        let in_rect =
+            |v: &[f32; 2]| min[0] <= v[0] && v[0] <= max[0] && min[1] <= v[1] && v[1] <= max[1];
+        self.vertices.iter().any(in_rect)
+            || corners.iter().any(|c| self.contains(*c))
+            || self.edges().any(|(a, b)| {
+                (0..4).any(|i| segments_intersect(a, b, corners[i], corners[(i + 1) % 4]))
+            })
+    }
+}
+
+/// Keeps the k closest records that fall inside a polygon.
+struct PolygonCollector<'a, T: Clone> {
+    nearest: NearestCollector,
+    tree: &'a KDTree<T>,
+    polygon: &'a Polygon,
+}
+
+impl<T: Clone> Collector for PolygonCollector<'_, T> {
+    const USES_REGIONS: bool = true;
+    fn bound(&self) -> f32 {
+        self.nearest.bound()
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        let point = self.tree.get_data_point(data_pointer);
+        if self.polygon.contains([point.point(0), point.point(1)]) {
+            self.nearest.offer(distance, data_pointer);
+        }
+    }
+    fn accepts_region(&self, region: &BoundingBox) -> bool {
+        self.polygon.intersects(region)
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Get the k nearest neighbors to a given point, among the records that
+    /// fall inside `polygon`, ordered from nearest to farthest. Branches of
+    /// the tree that do not overlap the polygon are never visited.
+    pub fn get_nearest_neighbors_in_polygon<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+        polygon: &Polygon,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = PolygonCollector {
+            nearest: NearestCollector::new(k),
+            tree: self,
+            polygon,
+        };
+        self.search(point, &mut collector, distance_metric);
+        collector.nearest.into_neighbors(&self.data)
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Get the k nearest neighbors to a latitude and longitude by great
+    /// circle distance, ordered from nearest to farthest, with the neighbor
+    /// distances in meters. The tree must be built from [`lat_lng_to_ecef`]
+    /// coordinates.
+    pub fn get_nearest_neighbors_geo(&self, lat: f32, lng: f32, k: usize) -> Vec<Neighbor<T>> {
+        let point = Point::new(lat_lng_to_ecef(lat, lng));
+        let mut neighbors =
+            self.get_nearest_neighbors(&point, k, &SquaredEuclideanDistance::default());
+        neighbors
+            .iter_mut()
+            .for_each(|n| n.distance = squared_chord_to_meters(n.distance));
+        neighbors
+    }
+    /// Get every record within `radius_meters` great circle distance of a
+    /// latitude and longitude, ordered from nearest to farthest, with the
+    /// neighbor distances in meters. The tree must be built from
+    /// [`lat_lng_to_ecef`] coordinates.
+    pub fn query_radius_geo(&self, lat: f32, lng: f32, radius_meters: f64) -> Vec<Neighbor<T>> {
+        let point = Point::new(lat_lng_to_ecef(lat, lng));
+        let mut collector = RadiusCollector::new(meters_to_squared_chord(radius_meters));
+        self.search(&point, &mut collector, &SquaredEuclideanDistance::default());
+        let mut neighbors = collector.into_neighbors(&self.data);
+        neighbors
+            .iter_mut()
+            .for_each(|n| n.distance = squared_chord_to_meters(n.distance));
+        neighbors
+    }
+}
+
+/// Transformation of coordinates between two coordinate reference systems.
+///
+/// Implement this for a wrapper around a projection library, such as a
+/// `proj::Proj`, to build and query trees from data in any CRS.
+pub trait CrsTransform {
+    /// Identifier of the CRS coordinates are transformed from, such as
+    /// `"EPSG:3857"`.
+    fn source(&self) -> &str;
+    /// Identifier of the CRS coordinates are transformed to.
+    fn target(&self) -> &str;
+    fn transform(&self, coordinates: &[f32]) -> Result<Vec<f32>, ClosestError>;
+}
+
+/// Web mercator `[x, y]` meters to WGS 84 `[lat, lng]` degrees.
+#[derive(Debug, Default)]
+pub struct WebMercatorToWgs84 {}
+
+impl CrsTransform for WebMercatorToWgs84 {
+    fn source(&self) -> &str {
+        "EPSG:3857"
+    }
+    fn target(&self) -> &str {
+        "EPSG:4326"
+    }
+    fn transform(&self, coordinates: &[f32]) -> Result<Vec<f32>, ClosestError> {
+        match coordinates {
+            [x, y] => {
+                // Web mercator uses a spherical earth with the equatorial radius.
+                let radius = 6_378_137.;
+                let lng = (*x as f64 / radius).to_degrees();
+                let lat = (*y as f64 / radius).sinh().atan().to_degrees();
+                Ok(vec![lat as f32, lng as f32])
+            }
+            _ => Err(ClosestError::TransformFailed(format!(
+                "expected 2 coordinates, found {}",
+                coordinates.len()
+            ))),
+        }
+    }
+}
+
+/// Tree that keeps track of the coordinate reference system its points are
+/// in, and refuses queries made in any other CRS.
+#[derive(Debug)]
+pub struct CrsKDTree<T: Clone> {
+    tree: KDTree<T>,
+    crs: String,
+}
+
+impl<T: Clone> CrsKDTree<T> {
+    /// Build a tree from data already in `crs`.
+    pub fn from_vec(
+        data: Vec<Data<T>>,
+        min_points: usize,
+        crs: &str,
+    ) -> Result<Self, ClosestError> {
+        Ok(CrsKDTree {
+            tree: KDTree::from_vec(data, min_points)?,
+            crs: crs.to_string(),
+        })
+    }
+    /// Build a tree from data in the transform's source CRS, the tree is in
+    /// the transform's target CRS.
+    pub fn from_vec_transformed<C: CrsTransform>(
+        mut data: Vec<Data<T>>,
+        min_points: usize,
+        transform: &C,
+    ) -> Result<Self, ClosestError> {
+        for d in data.iter_mut() {
+            d.point = Point::new(transform.transform(&d.point.coordinates)?);
+        }
+        Self::from_vec(data, min_points, transform.target())
+    }
+    /// The CRS the tree's points are in.
+    pub fn crs(&self) -> &str {
+        &self.crs
+    }
+    pub fn tree(&self) -> &KDTree<T> {
+        &self.tree
+    }
+    /// Get k nearest neighbors to a point in `crs`, which must be the CRS
+    /// of the tree.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        crs: &str,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if crs != self.crs {
+            return Err(ClosestError::CrsMismatch {
+                expected: self.crs.clone(),
+                found: crs.to_string(),
+            });
+        }
+        Ok(self.tree.get_nearest_neighbors(point, k, distance_metric))
+    }
+    /// Get k nearest neighbors to a point, transforming it into the tree's
+    /// CRS first.
+    pub fn get_nearest_neighbors_transformed<C: CrsTransform, D: DistanceMetric>(
+        &self,
+        point: &Point,
+        transform: &C,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        let point = Point::new(transform.transform(&point.coordinates)?);
+        self.get_nearest_neighbors(&point, transform.target(), k, distance_metric)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::grid_point;
+
+    #[test]
+    fn nearest_in_polygon() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 3).unwrap();
+        // Triangle in the upper right of the grid.
+        let polygon = Polygon::new(vec![[5.5, 5.2], [9.5, 5.2], [9.5, 9.2]]);
+        let nearest = tree.get_nearest_neighbors_in_polygon(
+            &Point::new(vec![0., 0.]),
+            3,
+            &SquaredEuclideanDistance::default(),
+            &polygon,
+        );
+        let found = nearest.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(found, vec![67, 68, 78]);
+    }
+
+    #[test]
+    fn radius_in_meters() {
+        let cities = vec![
+            ("london", 51.5074, -0.1278),
+            ("paris", 48.8566, 2.3522),
+            ("brussels", 50.8503, 4.3517),
+            ("new-york", 40.7128, -74.0060),
+            ("tokyo", 35.6762, 139.6503),
+        ];
+        let tree = KDTree::from_iter(
+            cities
+                .into_iter()
+                .map(|(name, lat, lng)| Data::new(name, lat_lng_to_ecef(lat, lng))),
+            1,
+        )
+        .unwrap();
+        let found = tree.query_radius_geo(51.5074, -0.1278, 350_000.);
+        let names = found.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(names, vec!["london", "brussels", "paris"]);
+        // London to Paris is roughly 344 km.
+        assert!((found[2].distance - 343_500.).abs() < 1_000.);
+    }
+
+    #[test]
+    fn across_antimeridian() {
+        let places = vec![
+            ("suva", -18.1416, 178.4419),
+            ("apia", -13.8507, -171.7514),
+            ("nukualofa", -21.1394, -175.2049),
+            ("sydney", -33.8688, 151.2093),
+        ];
+        let tree = KDTree::from_iter(
+            places
+                .into_iter()
+                .map(|(name, lat, lng)| Data::new(name, lat_lng_to_ecef(lat, lng))),
+            1,
+        )
+        .unwrap();
+        // Just east of the antimeridian, Suva is the nearest city although
+        // its longitude differs by almost 360 degrees.
+        let found = tree.get_nearest_neighbors_geo(-17., -179.9, 2);
+        let names = found.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(names, vec!["suva", "nukualofa"]);
+        assert!(found[0].distance < 250_000.);
+        let found = tree.query_radius_geo(-18.1416, 178.4419, 1_000_000.);
+        let names = found.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(names, vec!["suva", "nukualofa"]);
+    }
+
+    #[test]
+    fn haversine() {
+        let metric = HaversineDistance::default();
+        // London to Paris is roughly 344 km.
+        let london_paris = metric.distance(&[51.5074, -0.1278], &[48.8566, 2.3522]);
+        assert!((london_paris - 343_500.).abs() < 1_000.);
+
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut uniform = |lo: f32, hi: f32| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            lo + (hi - lo) * ((state >> 40) as f32 / (1u64 << 24) as f32)
+        };
+        let data = (0..2000)
+            .map(|i| Data::new(i, vec![uniform(-90., 90.), uniform(-180., 180.)]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        let queries = [
+            [0., 179.9],
+            [-17., -179.9],
+            [89.5, 10.],
+            [-60., 0.],
+            [35., 139.7],
+        ];
+        for query in queries {
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(&query, d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let found = tree.get_nearest_neighbors(&Point::from(query), 5, &metric);
+            assert_eq!(
+                found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..5]
+            );
+        }
+    }
+
+    #[test]
+    fn crs_transforms() {
+        // Web mercator coordinates of london, paris and new york.
+        let data = vec![
+            Data::new("london", vec![-14226.6, 6711533.7]),
+            Data::new("paris", vec![261845.7, 6250564.3]),
+            Data::new("new-york", vec![-8238310.2, 4970071.6]),
+        ];
+        let transform = WebMercatorToWgs84::default();
+        let tree = CrsKDTree::from_vec_transformed(data, 1, &transform).unwrap();
+        assert_eq!(tree.crs(), "EPSG:4326");
+        let metric = SquaredEuclideanDistance::default();
+        let near_paris = Point::new(vec![48.8, 2.3]);
+        let nearest = tree
+            .get_nearest_neighbors(&near_paris, "EPSG:4326", 1, &metric)
+            .unwrap();
+        assert_eq!(nearest[0].data, "paris");
+        assert!(tree
+            .get_nearest_neighbors(&near_paris, "EPSG:3857", 1, &metric)
+            .is_err());
+        let near_london = Point::new(vec![-20000., 6700000.]);
+        let nearest = tree
+            .get_nearest_neighbors_transformed(&near_london, &transform, 1, &metric)
+            .unwrap();
+        assert_eq!(nearest[0].data, "london");
+    }
+}
@@ -0,0 +1,92 @@
+//! Correspondences for iterative closest point (ICP) registration, which
+//! aligns a source cloud to a target cloud by repeatedly matching every
+//! source point to its nearest target point and moving the source to fit the
+//! matches. The tree over the target is built once and serves every
+//! iteration, leaving only the estimation of the transform to the caller.
+use crate::distance::SquaredEuclideanDistance;
+use crate::tree::{KDTree, NearestCollector, Point};
+
+/// A source point matched to its nearest target point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correspondence {
+    /// Position of the point in the source cloud.
+    pub source: usize,
+    /// Position of the point in the target cloud.
+    pub target: usize,
+    /// Euclidean distance between the two points.
+    pub distance: f32,
+}
+
+impl KDTree<()> {
+    /// Match every point of `source` to its nearest point in this tree,
+    /// built from the target cloud with [`KDTree::from_points`]. Source
+    /// points farther than `max_distance` from every target point are left
+    /// out, rejecting outliers and parts of the source the target does not
+    /// cover. Call this once per iteration, with the source moved by the
+    /// transform estimated so far.
+    pub fn correspondences(&self, source: &[Vec<f32>], max_distance: f32) -> Vec<Correspondence> {
+        let metric = SquaredEuclideanDistance::default();
+        let max_distance = max_distance * max_distance;
+        source
+            .iter()
+            .enumerate()
+            .filter_map(|(i, point)| {
+                let mut collector = NearestCollector::with_max_distance(1, max_distance);
+                self.search(&Point::new(point.clone()), &mut collector, &metric);
+                let nearest = collector.into_sorted_vec().pop()?;
+                Some(Correspondence {
+                    source: i,
+                    target: self.data[nearest.data_pointer].id,
+                    distance: nearest.distance.sqrt(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translation_by_icp() {
+        let target = (0..400)
+            .map(|i| {
+                let (x, y) = ((i % 20) as f32, (i / 20) as f32);
+                vec![x, y, (x * 0.7).sin() * 2. + (y * 0.4).cos() * 3.]
+            })
+            .collect::<Vec<_>>();
+        let offset = [0.3, -0.2, 0.25];
+        let mut source = target
+            .iter()
+            .map(|p| p.iter().zip(offset).map(|(c, o)| c + o).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        source.push(vec![100., 100., 100.]);
+        let tree = KDTree::from_points(target.clone(), 8).unwrap();
+
+        let matches = tree.correspondences(&source, 1.);
+        assert_eq!(matches.len(), target.len());
+        assert!(matches.iter().all(|m| m.source == m.target));
+
+        // Estimate a translation from the matches until it stops moving.
+        for _ in 0..20 {
+            let matches = tree.correspondences(&source, 1.);
+            let shift = (0..3)
+                .map(|axis| {
+                    matches
+                        .iter()
+                        .map(|m| target[m.target][axis] - source[m.source][axis])
+                        .sum::<f32>()
+                        / matches.len() as f32
+                })
+                .collect::<Vec<_>>();
+            for point in &mut source {
+                for (c, s) in point.iter_mut().zip(&shift) {
+                    *c += s;
+                }
+            }
+        }
+        let matches = tree.correspondences(&source, 1.);
+        assert!(matches.iter().all(|m| m.distance < 1e-4));
+    }
+}
@@ -0,0 +1,102 @@
+//! Filling in missing values from the nearest complete records.
+use crate::distance::NanEuclideanDistance;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, Point};
+
+/// Fill every NaN value of `dataset` with the distance weighted mean of that
+/// feature over the k nearest rows with no missing values, measured with
+/// [`NanEuclideanDistance`] over the features the row has. Neighbors are
+/// weighted by the inverse of their euclidean distance, and neighbors at no
+/// distance at all are used alone. Rows missing every value get the mean of
+/// each feature over the complete rows.
+pub fn impute_knn(dataset: &[Vec<f32>], k: usize) -> Result<Vec<Vec<f32>>, ClosestError> {
+    let dimension = dataset.first().map_or(0, |row| row.len());
+    if let Some(row) = dataset.iter().find(|row| row.len() != dimension) {
+        return Err(ClosestError::DimensionMismatch {
+            expected: dimension,
+            found: row.len(),
+        });
+    }
+    let complete = dataset
+        .iter()
+        .filter(|row| !row.iter().any(|v| v.is_nan()))
+        .collect::<Vec<_>>();
+    if complete.len() == dataset.len() {
+        return Ok(dataset.to_vec());
+    }
+    if complete.is_empty() || k == 0 {
+        return Err(ClosestError::NotEnoughSamples {
+            needed: k.max(1),
+            found: complete.len(),
+        });
+    }
+    let tree = KDTree::from_points(complete.iter().map(|row| row.to_vec()), 16)?;
+    let means = (0..dimension)
+        .map(|axis| complete.iter().map(|row| row[axis]).sum::<f32>() / complete.len() as f32)
+        .collect::<Vec<_>>();
+    let metric = NanEuclideanDistance {};
+    Ok(dataset
+        .iter()
+        .map(|row| {
+            if !row.iter().any(|v| v.is_nan()) {
+                return row.clone();
+            }
+            if row.iter().all(|v| v.is_nan()) {
+                return means.clone();
+            }
+            let nearest = tree.k_nearest(&Point::new(row.clone()), k, &metric);
+            let weights = if nearest.iter().any(|(_, distance)| *distance == 0.) {
+                nearest
+                    .iter()
+                    .map(|(_, distance)| if *distance == 0. { 1. } else { 0. })
+                    .collect::<Vec<f32>>()
+            } else {
+                nearest
+                    .iter()
+                    .map(|(_, distance)| 1. / distance.sqrt())
+                    .collect()
+            };
+            let total = weights.iter().sum::<f32>();
+            row.iter()
+                .enumerate()
+                .map(|(axis, value)| {
+                    if !value.is_nan() {
+                        return *value;
+                    }
+                    nearest
+                        .iter()
+                        .zip(&weights)
+                        .map(|((i, _), weight)| complete[*i][axis] * weight)
+                        .sum::<f32>()
+                        / total
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_missing_values() {
+        let nan = f32::NAN;
+        let dataset = vec![
+            vec![0., 0., 10.],
+            vec![1., 0., 20.],
+            vec![10., 10., 100.],
+            vec![0., 0., nan],
+            vec![0.5, 0., nan],
+            vec![nan, nan, nan],
+        ];
+        let completed = impute_knn(&dataset, 2).unwrap();
+        assert_eq!(&completed[..3], &dataset[..3]);
+        // An exact match to the first row.
+        assert_eq!(completed[3], vec![0., 0., 10.]);
+        // Halfway between the first two rows, weighted equally.
+        assert_eq!(completed[4], vec![0.5, 0., 15.]);
+        assert_eq!(completed[5], vec![11. / 3., 10. / 3., 130. / 3.]);
+        assert!(impute_knn(&[vec![nan, 1.]], 2).is_err());
+    }
+}
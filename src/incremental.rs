@@ -0,0 +1,194 @@
+//! Indexes for streams of records, by the logarithmic method: new records
+//! wait in a small buffer, which is built into a tree once full, merging
+//! with the trees built before it while they are as large. The trees have
+//! sizes growing by powers of two, so every record is rebuilt into a larger
+//! tree a logarithmic number of times, and queries search a logarithmic
+//! number of trees.
+use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+use crate::error::ClosestError;
+use crate::index::SpatialIndex;
+use crate::tree::{Data, KDTree, Neighbor, Point, DEFAULT_MIN_POINTS};
+
+/// Records buffered before a tree is built, when not set.
+const DEFAULT_BUFFER_SIZE: usize = 256;
+
+/// Index that takes records one at a time, cheaply, while queries stay
+/// close to those of a single tree over every record.
+#[derive(Debug, Clone)]
+pub struct IncrementalKDTree<T: Clone> {
+    /// Records not in any tree yet, scanned by every query.
+    buffer: Vec<Data<T>>,
+    buffer_size: usize,
+    /// Tree `i`, when present, holds `buffer_size * 2^i` records.
+    levels: Vec<Option<KDTree<T>>>,
+    min_points: usize,
+    dimension: usize,
+}
+
+impl<T: Clone> Default for IncrementalKDTree<T> {
+    fn default() -> Self {
+        IncrementalKDTree::new(DEFAULT_BUFFER_SIZE, DEFAULT_MIN_POINTS)
+    }
+}
+
+impl<T: Clone> IncrementalKDTree<T> {
+    /// An empty index, building a tree every `buffer_size` records with
+    /// leaves of `min_points` records.
+    pub fn new(buffer_size: usize, min_points: usize) -> Self {
+        IncrementalKDTree {
+            buffer: Vec::new(),
+            buffer_size: buffer_size.max(1),
+            levels: Vec::new(),
+            min_points,
+            dimension: 0,
+        }
+    }
+    /// Add a record. Once the buffer is full it is built into a tree,
+    /// together with the trees of the smaller levels.
+    pub fn insert(&mut self, record: Data<T>) -> Result<(), ClosestError> {
+        if self.dimension == 0 {
+            self.dimension = record.point.shape();
+        }
+        if record.point.shape() != self.dimension {
+            return Err(ClosestError::DimensionMismatch {
+                expected: self.dimension,
+                found: record.point.shape(),
+            });
+        }
+        self.buffer.push(record);
+        if self.buffer.len() >= self.buffer_size {
+            self.flush();
+        }
+        Ok(())
+    }
+    /// Build the buffer into a tree at the first empty level, merging in
+    /// the trees of every level below it.
+    fn flush(&mut self) {
+        let mut records = std::mem::take(&mut self.buffer);
+        let mut level = 0;
+        while let Some(Some(tree)) = self.levels.get_mut(level).map(Option::take) {
+            records.extend(tree.data);
+            level += 1;
+        }
+        if level == self.levels.len() {
+            self.levels.push(None);
+        }
+        self.levels[level] =
+            Some(KDTree::from_vec(records, self.min_points).expect("building never fails"));
+    }
+    /// The built trees, smallest first.
+    pub fn trees(&self) -> impl Iterator<Item = &KDTree<T>> {
+        self.levels.iter().flatten()
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest. The trees are searched largest first, each only for records
+    /// closer than the k-th found so far.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut found = self
+            .buffer
+            .iter()
+            .map(|d| Neighbor {
+                distance: distance_metric.distance(point.as_slice(), d.point.as_slice()),
+                data: d.data.clone(),
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        found.truncate(k);
+        for tree in self.levels.iter().rev().flatten() {
+            let bound = (k > 0 && found.len() == k).then(|| found[k - 1].distance);
+            found.extend(tree.get_nearest_neighbors_within(point, k, bound, distance_metric));
+            found.sort();
+            found.truncate(k);
+        }
+        found
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut found = self
+            .buffer
+            .iter()
+            .map(|d| Neighbor {
+                distance: distance_metric.distance(point.as_slice(), d.point.as_slice()),
+                data: d.data.clone(),
+            })
+            .filter(|n| n.distance <= radius)
+            .collect::<Vec<_>>();
+        for tree in self.trees() {
+            found.extend(tree.get_neighbors_within_radius(point, radius, distance_metric));
+        }
+        found.sort();
+        found
+    }
+}
+
+impl<T: Clone> Extend<Data<T>> for IncrementalKDTree<T> {
+    /// Add records, with [`IncrementalKDTree::insert`]. Records with another
+    /// dimension than the first are skipped.
+    fn extend<I: IntoIterator<Item = Data<T>>>(&mut self, iter: I) {
+        for record in iter {
+            let _ = self.insert(record);
+        }
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for IncrementalKDTree<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k, &SquaredEuclideanDistance::default())
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius, &SquaredEuclideanDistance::default())
+    }
+    fn len(&self) -> usize {
+        self.buffer.len() + self.trees().map(|t| t.data.len()).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::scattered_point;
+
+    #[test]
+    fn logarithmic_levels() {
+        let record = |i: usize| Data::new(i, scattered_point(i));
+        let mut index = IncrementalKDTree::new(16, 4);
+        for i in 0..1000 {
+            index.insert(record(i)).unwrap();
+        }
+        // 1000 = 62 full buffers of 16, 0b111110, and 8 buffered records.
+        let sizes = index.trees().map(|t| t.records().len()).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![32, 64, 128, 256, 512]);
+        assert_eq!(index.len(), 1000);
+
+        let tree = KDTree::from_vec((0..1000).map(record).collect(), 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        for point in [
+            Point::from([50.5, 20.5]),
+            Point::from([0., 0.]),
+            Point::from([300., 9.]),
+        ] {
+            assert_eq!(
+                distances(index.get_nearest_neighbors(&point, 7, &metric)),
+                distances(tree.get_nearest_neighbors(&point, 7, &metric))
+            );
+            assert_eq!(
+                distances(index.get_neighbors_within_radius(&point, 40., &metric)),
+                distances(tree.get_neighbors_within_radius(&point, 40., &metric))
+            );
+        }
+        assert!(index.insert(Data::new(0, vec![1.])).is_err());
+    }
+}
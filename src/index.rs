@@ -0,0 +1,137 @@
+use crate::brute_force::BruteForce;
+use crate::distance::SquaredEuclideanDistance;
+use crate::error::ClosestError;
+use crate::orthtree::{Octree, Quadtree};
+use crate::tree::{Data, KDTree, Neighbor, Point, DEFAULT_MIN_POINTS};
+
+/// Common interface of the spatial indexes in this crate, so they can be
+/// used interchangeably, including behind a `Box<dyn SpatialIndex<T>>`.
+/// Distances are squared euclidean.
+pub trait SpatialIndex<T: Clone> {
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>>;
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>>;
+    /// Number of records in the index.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for KDTree<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k, &SquaredEuclideanDistance::default())
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius, &SquaredEuclideanDistance::default())
+    }
+    fn len(&self) -> usize {
+        self.data.len() - self.tombstones
+    }
+}
+
+/// Below this many records, scanning everything is as fast as a tree.
+const BRUTE_FORCE_MAX_RECORDS: usize = 128;
+
+/// What is known about the data and workload, used by [`Index::auto`].
+#[derive(Debug, Default, Clone)]
+pub struct IndexHints {
+    /// The points are spread evenly over their extent, as in dense point
+    /// clouds, rather than clustered.
+    pub uniform_density: bool,
+}
+
+/// One of the spatial indexes in this crate, chosen to suit the data.
+// The tree is the usual choice, it is not boxed to save the others' size.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum Index<T: Clone> {
+    KDTree(KDTree<T>),
+    Quadtree(Quadtree<T>),
+    Octree(Octree<T>),
+    BruteForce(BruteForce<T>),
+}
+
+impl<T: Clone> Index<T> {
+    /// Pick and build an index from the number of records, their dimension
+    /// and the given hints. Only the indexes below are considered, and none
+    /// can be updated through [`Index`], build a [`KDTree`] or an
+    /// [`IncrementalKDTree`] directly for data that changes.
+    ///
+    /// - Brute force for small datasets, and whenever there are too few
+    ///   records per dimension for a tree to prune, fewer than 2^dimension.
+    /// - A quadtree or octree for 2 and 3 dimensional data that is uniformly
+    ///   spread.
+    /// - A KDTree otherwise.
+    ///
+    /// [`IncrementalKDTree`]: crate::IncrementalKDTree
+    pub fn auto(data: Vec<Data<T>>, hints: IndexHints) -> Result<Self, ClosestError> {
+        let n = data.len();
+        let dimension = data.first().map_or(0, |d| d.point.shape());
+        let too_few_per_dimension = dimension >= usize::BITS as usize || n < (1 << dimension);
+        if n <= BRUTE_FORCE_MAX_RECORDS || too_few_per_dimension {
+            return Ok(Index::BruteForce(BruteForce::from_vec(data)));
+        }
+        Ok(match dimension {
+            2 if hints.uniform_density => {
+                Index::Quadtree(Quadtree::from_vec(data, DEFAULT_MIN_POINTS)?)
+            }
+            3 if hints.uniform_density => {
+                Index::Octree(Octree::from_vec(data, DEFAULT_MIN_POINTS)?)
+            }
+            _ => Index::KDTree(KDTree::new(data)),
+        })
+    }
+    fn as_spatial_index(&self) -> &dyn SpatialIndex<T> {
+        match self {
+            Index::KDTree(index) => index,
+            Index::Quadtree(index) => index,
+            Index::Octree(index) => index,
+            Index::BruteForce(index) => index,
+        }
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for Index<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.as_spatial_index().nearest_neighbors(point, k)
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.as_spatial_index()
+            .neighbors_within_radius(point, radius)
+    }
+    fn len(&self) -> usize {
+        self.as_spatial_index().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_selection() {
+        let grid = |dimension: usize, n: usize| {
+            (0..n)
+                .map(|i| Data::new(i, (0..dimension).map(|d| ((i + d) % 17) as f32).collect()))
+                .collect::<Vec<_>>()
+        };
+        let small = Index::auto(grid(3, 50), IndexHints::default()).unwrap();
+        assert!(matches!(small, Index::BruteForce(_)));
+        let wide = Index::auto(grid(24, 5000), IndexHints::default()).unwrap();
+        assert!(matches!(wide, Index::BruteForce(_)));
+        let cloud = IndexHints {
+            uniform_density: true,
+        };
+        let dense = Index::auto(grid(3, 5000), cloud).unwrap();
+        assert!(matches!(dense, Index::Octree(_)));
+        let general = Index::auto(grid(5, 5000), IndexHints::default()).unwrap();
+        assert!(matches!(general, Index::KDTree(_)));
+        assert_eq!(general.len(), 5000);
+        let nearest = general.nearest_neighbors(&Point::new(vec![3., 4., 5., 6., 7.]), 1);
+        assert_eq!(nearest[0].distance, 0.);
+    }
+}
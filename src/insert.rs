@@ -0,0 +1,245 @@
+//! Inserting records into a built tree, for data that keeps growing.
+use crate::bounds::BoundingBox;
+use crate::error::ClosestError;
+use crate::tree::{at, Data, KDTree, NodeOrDataPointer, Point};
+
+/// Range of records of the leaf a point falls in.
+fn leaf_for<T: Clone>(
+    node: &NodeOrDataPointer,
+    data: &[Data<T>],
+    coordinates: &[f32],
+    depth: usize,
+) -> (usize, usize) {
+    match node {
+        NodeOrDataPointer::Node(n) => {
+            let axis = depth % coordinates.len();
+            let split_value = *at(&data[n.data_pointer].point.coordinates, axis);
+            let child = if coordinates[axis] <= split_value {
+                &n.left
+            } else {
+                &n.right
+            };
+            leaf_for(child, data, coordinates, depth + 1)
+        }
+        NodeOrDataPointer::Data(range) => *range,
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Insert a record into the tree without rebuilding it, and return its
+    /// id. The record is kept with the leaf it falls in, apart from the
+    /// leaf's own records, and queries scan it along with them, so an insert
+    /// costs about as much as a query. The tree is not rebalanced, it is
+    /// rebuilt once a leaf holds more records than its [`ReindexPolicy`]
+    /// allows, which happens often when records are inserted in sorted
+    /// order. Many records at once are better added with [`Extend`].
+    ///
+    /// [`ReindexPolicy`]: crate::ReindexPolicy
+    pub fn insert(&mut self, mut record: Data<T>) -> Result<usize, ClosestError> {
+        self.check_dimension(record.point.shape())?;
+        let id = self.next_id;
+        record.id = id;
+        self.next_id += 1;
+        self.place(record);
+        self.reindex_if_needed();
+        Ok(id)
+    }
+    /// Move the record with the given id to new coordinates, keeping its id
+    /// and data. A record that stays within its leaf's region is moved in
+    /// place, any other is removed, as with [`KDTree::delete`], and inserted
+    /// again at its new place.
+    pub fn update_point(&mut self, id: usize, coordinates: Vec<f32>) -> Result<(), ClosestError> {
+        self.check_dimension(coordinates.len())?;
+        let data_pointer = self
+            .position_of(id)
+            .ok_or(ClosestError::UnknownRecord { id })?;
+        let point = Point::new(coordinates);
+        if let Some(extent) = &mut self.extent {
+            extent.include(&point);
+        }
+        let in_place = self.unindexed().contains(&data_pointer) || {
+            let (start, stop) = leaf_for(&self.root_node, &self.data, point.as_slice(), 0);
+            (start..stop).contains(&data_pointer)
+                || self.leaf_overflow(start).contains(&data_pointer)
+        };
+        if in_place {
+            self.data[data_pointer].point = point;
+            return Ok(());
+        }
+        let record = Data {
+            data: self.data[data_pointer].data.clone(),
+            point,
+            id,
+        };
+        self.mark_removed(data_pointer);
+        self.place(record);
+        self.reindex_if_needed();
+        Ok(())
+    }
+    fn check_dimension(&mut self, found: usize) -> Result<(), ClosestError> {
+        if self.dimension == 0 {
+            self.dimension = if self.data.is_empty() {
+                found
+            } else {
+                self.records_dimension()
+            };
+        }
+        if found != self.dimension {
+            return Err(ClosestError::DimensionMismatch {
+                expected: self.dimension,
+                found,
+            });
+        }
+        Ok(())
+    }
+    /// Store a record after the inserted ones, and add it to the leaf it
+    /// falls in. The first record added with [`Extend`] that is not indexed
+    /// yet, if any, moves to the end to make room.
+    fn place(&mut self, record: Data<T>) {
+        match &mut self.extent {
+            Some(extent) => extent.include(&record.point),
+            None => self.extent = BoundingBox::from_points(std::iter::once(&record.point)),
+        }
+        let (start, stop) = leaf_for(&self.root_node, &self.data, record.point.as_slice(), 0);
+        let position = self.indexed + self.inserted;
+        self.positions.insert(record.id, position);
+        self.data.push(record);
+        let last = self.data.len() - 1;
+        self.data.swap(position, last);
+        if !self.removed.is_empty() {
+            self.removed.push(false);
+            self.removed.swap(position, last);
+        }
+        if last != position && !self.is_removed(last) {
+            self.positions.insert(self.data[last].id, last);
+        }
+        self.inserted += 1;
+        let overflow = self.overflow.entry(start).or_default();
+        overflow.push(position);
+        self.largest_leaf = self.largest_leaf.max(stop - start + overflow.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+    use crate::index::SpatialIndex;
+    use crate::tree::{grid_point, scattered_point, Neighbor};
+
+    #[test]
+    fn insert_records() {
+        let record = |i: usize| Data::new(i, scattered_point(i));
+        let mut tree = KDTree::from_vec((0..100).map(record).collect(), 4).unwrap();
+        for i in 100..400 {
+            assert_eq!(tree.insert(record(i)).unwrap(), i);
+        }
+        assert_eq!(tree.records().len(), 400);
+        // Leaves grow with inserts until the policy's limit rebuilds them.
+        assert!(tree.inserted > 0);
+        assert!(tree.largest_leaf <= 4 * 4);
+        for node in tree.nodes() {
+            if let (Some((axis, value)), Some((left, right))) = (node.split(), node.children()) {
+                assert!(left.records().all(|d| d.point.point(axis) <= value));
+                assert!(right.records().all(|d| d.point.point(axis) >= value));
+            }
+        }
+
+        let metric = SquaredEuclideanDistance::default();
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        let brute_force = |point: &Point| {
+            let mut expected = (0..400)
+                .map(|i| metric.distance(point.as_slice(), record(i).point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            expected
+        };
+        for i in 0..20 {
+            let point = Point::from([i as f32 * 5.1, i as f32 * 2.3]);
+            assert_eq!(
+                distances(tree.get_nearest_neighbors(&point, 10, &metric)),
+                brute_force(&point)[..10]
+            );
+        }
+        let point = Point::from([50.5, 20.5]);
+        let expected = brute_force(&point);
+
+        let stored = KDTree::<usize>::from_bytes(&tree.to_bytes()).unwrap();
+        assert_eq!(
+            distances(stored.get_nearest_neighbors(&point, 10, &metric)),
+            expected[..10]
+        );
+        let frozen = tree.clone().freeze();
+        assert_eq!(
+            distances(frozen.get_nearest_neighbors(&point, 10, &metric)),
+            expected[..10]
+        );
+        assert_eq!(tree.minimum_spanning_tree().len(), 399);
+
+        // Records inserted in order all land at the same edge of the tree.
+        let mut sorted = KDTree::from_vec(Vec::new(), 4).unwrap();
+        for i in 0..2000 {
+            sorted.insert(Data::new(i, vec![i as f32, 0.])).unwrap();
+        }
+        assert!(sorted.largest_leaf <= 4 * 4);
+        let mut out = [(0., 0); 3];
+        assert_eq!(sorted.k_nearest_into(&[1999., 0.], &metric, &mut out), 3);
+        assert_eq!(
+            out[0],
+            (
+                0.,
+                sorted
+                    .records()
+                    .iter()
+                    .position(|d| d.data == 1999)
+                    .unwrap()
+            )
+        );
+
+        let mut empty = KDTree::from_vec(Vec::new(), 4).unwrap();
+        empty.extend([record(0)]);
+        empty.insert(record(1)).unwrap();
+        // The inserted record takes the place of the one added before it.
+        for id in [0, 1] {
+            assert_eq!(empty.records()[empty.position_of(id).unwrap()].id, id);
+        }
+        assert!(matches!(
+            empty.insert(Data::new(1, vec![1.])),
+            Err(ClosestError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn moving_points() {
+        let record = |i: usize| Data::new(i, grid_point(i));
+        let mut tree = KDTree::from_vec((0..100).map(record).collect(), 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let nearest = |tree: &KDTree<usize>, x: f32, y: f32| {
+            tree.get_nearest_neighbors(&Point::from([x, y]), 1, &metric)[0].data
+        };
+        // A small move and a move across the tree.
+        tree.update_point(55, vec![5.1, 5.1]).unwrap();
+        tree.update_point(0, vec![20., 20.]).unwrap();
+        assert_eq!(nearest(&tree, 5.1, 5.1), 55);
+        assert_eq!(nearest(&tree, 19., 19.), 0);
+        assert_eq!(nearest(&tree, 0.9, 0.), 1);
+        assert_eq!(SpatialIndex::len(&tree), 100);
+        // Moving every record keeps every record findable at its new place.
+        for i in 0..100 {
+            tree.update_point(i, vec![(i / 10) as f32 + 0.5, (i % 10) as f32])
+                .unwrap();
+        }
+        for i in 0..100 {
+            assert_eq!(nearest(&tree, (i / 10) as f32 + 0.5, (i % 10) as f32), i);
+        }
+        assert!(matches!(
+            tree.update_point(1000, vec![0., 0.]),
+            Err(ClosestError::UnknownRecord { id: 1000 })
+        ));
+        assert!(tree.update_point(1, vec![0.]).is_err());
+    }
+}
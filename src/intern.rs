@@ -0,0 +1,155 @@
+//! Trees over string data with many repeats, such as category names, that
+//! store every distinct string once and small symbols in the records.
+use std::collections::HashMap;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{Data, KDTree, Neighbor, Point};
+
+/// Handle of a string stored in an [`Interner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Store of distinct strings, handing out a [`Symbol`] for each.
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    strings: Vec<Box<str>>,
+    symbols: HashMap<Box<str>, Symbol>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+    /// The symbol of a string, storing the string if it is new.
+    pub fn get_or_intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+        let symbol =
+            Symbol(u32::try_from(self.strings.len()).expect("fewer than 2^32 distinct strings"));
+        self.strings.push(string.into());
+        self.symbols.insert(string.into(), symbol);
+        symbol
+    }
+    /// The symbol of a string, if it is stored.
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).copied()
+    }
+    /// The string of a symbol handed out by this interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+    /// Number of distinct strings stored.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// Tree over string data, storing every distinct string once in an
+/// [`Interner`] and a 4 byte [`Symbol`] in every record. Queries resolve
+/// the symbols, and return the strings.
+#[derive(Debug, Clone)]
+pub struct InternedKDTree {
+    tree: KDTree<Symbol>,
+    interner: Interner,
+}
+
+impl InternedKDTree {
+    pub fn from_vec<S: AsRef<str> + Clone>(
+        data: Vec<Data<S>>,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        let mut interner = Interner::new();
+        let data = data
+            .into_iter()
+            .map(|d| Data {
+                data: interner.get_or_intern(d.data.as_ref()),
+                point: d.point,
+                id: d.id,
+            })
+            .collect();
+        Ok(InternedKDTree {
+            tree: KDTree::from_vec(data, min_points)?,
+            interner,
+        })
+    }
+    /// The tree of symbols, for queries that are not resolved.
+    pub fn tree(&self) -> &KDTree<Symbol> {
+        &self.tree
+    }
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+    fn resolve(&self, found: Vec<Neighbor<Symbol>>) -> Vec<Neighbor<&str>> {
+        found
+            .into_iter()
+            .map(|n| Neighbor {
+                distance: n.distance,
+                data: self.interner.resolve(n.data),
+            })
+            .collect()
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest, with their strings.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<&str>> {
+        self.resolve(self.tree.get_nearest_neighbors(point, k, distance_metric))
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest, with their strings.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<&str>> {
+        self.resolve(
+            self.tree
+                .get_neighbors_within_radius(point, radius, distance_metric),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::scattered_point;
+
+    #[test]
+    fn interned_categories() {
+        let categories = ["cafe", "park", "school", "station"];
+        let data = (0..1000)
+            .map(|i| Data::new(categories[i % 4].to_string(), scattered_point(i)))
+            .collect::<Vec<_>>();
+        let plain = KDTree::from_vec(data.clone(), 8).unwrap();
+        let tree = InternedKDTree::from_vec(data, 8).unwrap();
+        assert_eq!(tree.interner().len(), 4);
+        let symbol = tree.interner().get("park").unwrap();
+        assert_eq!(tree.interner().resolve(symbol), "park");
+        assert!(tree.interner().get("museum").is_none());
+
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50.5, 20.5]);
+        let expected = plain.get_nearest_neighbors(&point, 10, &metric);
+        let found = tree.get_nearest_neighbors(&point, 10, &metric);
+        for (found, expected) in found.iter().zip(&expected) {
+            assert_eq!(found.data, expected.data);
+            assert_eq!(found.distance, expected.distance);
+        }
+        assert_eq!(
+            tree.get_neighbors_within_radius(&point, 30., &metric).len(),
+            plain
+                .get_neighbors_within_radius(&point, 30., &metric)
+                .len()
+        );
+    }
+}
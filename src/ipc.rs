@@ -0,0 +1,176 @@
+//! Nearest neighbor queries over Arrow IPC streams, enabled with the `arrow`
+//! feature, so data platforms can send query batches in and read results
+//! back without converting rows one by one.
+//!
+//! Query batches hold one Float32 or Float64 column per coordinate, in
+//! order. For every query batch read, one result batch is written, in long
+//! form with a row per neighbor found:
+//!
+//! - `query`: UInt64, position of the query in the whole stream
+//! - `rank`: UInt32, 0 for the nearest neighbor
+//! - `index`: UInt64, position of the record in the data the tree was built
+//!   from
+//! - `distance`: Float32, in the units of the metric
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Float64Type};
+use arrow_array::{Array, ArrayRef, Float32Array, RecordBatch, UInt32Array, UInt64Array};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, NearestCollector, Point};
+
+fn result_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("query", DataType::UInt64, false),
+        Field::new("rank", DataType::UInt32, false),
+        Field::new("index", DataType::UInt64, false),
+        Field::new("distance", DataType::Float32, false),
+    ])
+}
+
+/// Coordinates of every row of a query batch.
+fn batch_points(batch: &RecordBatch, dimension: usize) -> Result<Vec<Point>, ArrowError> {
+    if batch.num_columns() != dimension {
+        return Err(ArrowError::SchemaError(format!(
+            "expected {} coordinate columns, found {}",
+            dimension,
+            batch.num_columns()
+        )));
+    }
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| {
+            if column.null_count() > 0 {
+                return Err(ArrowError::InvalidArgumentError(
+                    "query coordinates can not be null".to_string(),
+                ));
+            }
+            match column.data_type() {
+                DataType::Float32 => Ok(column.as_primitive::<Float32Type>().values().to_vec()),
+                DataType::Float64 => Ok(column
+                    .as_primitive::<Float64Type>()
+                    .values()
+                    .iter()
+                    .map(|c| *c as f32)
+                    .collect()),
+                other => Err(ArrowError::SchemaError(format!(
+                    "query coordinates must be Float32 or Float64, found {}",
+                    other
+                ))),
+            }
+        })
+        .collect::<Result<Vec<Vec<f32>>, ArrowError>>()?;
+    Ok((0..batch.num_rows())
+        .map(|row| Point::new(columns.iter().map(|column| column[row]).collect()))
+        .collect())
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Answer k nearest neighbor queries read as an Arrow IPC stream from
+    /// `queries`, writing an Arrow IPC stream of results to `results`, a
+    /// batch at a time as each query batch arrives. Returns the number of
+    /// queries answered.
+    pub fn serve_ipc_stream<D: DistanceMetric, R: Read, W: Write>(
+        &self,
+        queries: R,
+        results: W,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<u64, ClosestError> {
+        let reader = StreamReader::try_new(queries, None)?;
+        let schema = Arc::new(result_schema());
+        let mut writer = StreamWriter::try_new(results, &schema)?;
+        let mut answered = 0u64;
+        for batch in reader {
+            let points = batch_points(&batch?, self.dimension)?;
+            let (mut query, mut rank, mut index, mut distance) =
+                (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            for point in points {
+                let mut collector = NearestCollector::new(k);
+                self.search(&point, &mut collector, distance_metric);
+                for (r, found) in collector.into_sorted_vec().into_iter().enumerate() {
+                    query.push(answered);
+                    rank.push(r as u32);
+                    index.push(self.data[found.data_pointer].id as u64);
+                    distance.push(found.distance);
+                }
+                answered += 1;
+            }
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(UInt64Array::from(query)),
+                Arc::new(UInt32Array::from(rank)),
+                Arc::new(UInt64Array::from(index)),
+                Arc::new(Float32Array::from(distance)),
+            ];
+            writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+            writer.flush()?;
+        }
+        writer.finish()?;
+        Ok(answered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{grid_point, Data};
+    use arrow_array::Float64Array;
+
+    #[test]
+    fn answers_query_stream() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float32, false),
+        ]));
+        let mut queries = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut queries, &schema).unwrap();
+        for (xs, ys) in [(vec![3.1, 0.], vec![4.2, 0.]), (vec![9.], vec![9.])] {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Float64Array::from(xs)),
+                    Arc::new(Float32Array::from(ys)),
+                ],
+            )
+            .unwrap();
+            writer.write(&batch).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut results = Vec::new();
+        let metric = SquaredEuclideanDistance::default();
+        let answered = tree
+            .serve_ipc_stream(queries.as_slice(), &mut results, 2, &metric)
+            .unwrap();
+        assert_eq!(answered, 3);
+        let batches = StreamReader::try_new(results.as_slice(), None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 2);
+        let column = |batch: &RecordBatch, i: usize| {
+            batch
+                .column(i)
+                .as_primitive::<arrow_array::types::UInt64Type>()
+                .values()
+                .to_vec()
+        };
+        assert_eq!(column(&batches[0], 0), vec![0, 0, 1, 1]);
+        assert_eq!(column(&batches[0], 2)[0], 43);
+        assert_eq!(column(&batches[0], 2)[2], 0);
+        assert_eq!(column(&batches[1], 0), vec![2, 2]);
+        assert_eq!(column(&batches[1], 2)[0], 99);
+    }
+}
@@ -1,6 +1,108 @@
+mod aggregate;
+mod batch;
+mod bbf;
+mod bounds;
+mod brute_force;
+mod budget;
+mod calibration;
+mod cluster;
+mod color;
+mod coreset;
+#[cfg(feature = "polars")]
+mod dataframe;
 mod distance;
+mod dtw;
 mod error;
+mod estimators;
+#[cfg(feature = "json")]
+mod export;
+mod fixed;
+mod format;
+mod frozen;
+mod fusion;
+#[cfg(feature = "geo")]
+mod geo;
+mod icp;
+mod impute;
+mod incremental;
+mod index;
+mod insert;
+mod intern;
+#[cfg(feature = "arrow")]
+mod ipc;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod metrics;
+mod mst;
+mod normalized;
+mod normals;
+mod orientation;
+mod orthtree;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod payload;
+mod presorted;
+mod propagate;
+mod query;
+mod raster;
+mod reindex;
+mod remove;
+mod sample;
+mod segment;
+mod shard;
+mod smooth;
+mod stream;
+mod trace;
 mod tree;
+mod validate;
+mod vptree;
+mod warm;
 
-pub use crate::tree::{KDTree, Data, Point};
-pub use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+pub use crate::aggregate::{Aggregate, AggregateTree, Summary};
+pub use crate::bounds::BoundingBox;
+pub use crate::brute_force::BruteForce;
+pub use crate::calibration::DistanceCalibration;
+pub use crate::cluster::mutual_reachability;
+pub use crate::color::{srgb_to_lab, DeltaE2000, DeltaE76};
+#[cfg(feature = "polars")]
+pub use crate::dataframe::nearest_join;
+pub use crate::distance::{
+    DistanceMetric, EarthMoversDistance, FnMetric, MahalanobisDistance, ManhattanDistance,
+    MinkowskiDistance, NanEuclideanDistance, PeriodicEuclideanDistance, SquaredEuclideanDistance,
+    Summation, WeightedEuclideanDistance,
+};
+pub use crate::dtw::DynamicTimeWarping;
+pub use crate::error::ClosestError;
+pub use crate::estimators::{entropy_knn, kl_divergence_knn, mutual_information_knn};
+pub use crate::format::{Encode, FORMAT_VERSION};
+pub use crate::frozen::FrozenKDTree;
+pub use crate::fusion::{Fusion, FusionMethod};
+#[cfg(feature = "geo")]
+pub use crate::geo::{
+    lat_lng_to_ecef, CrsKDTree, CrsTransform, HaversineDistance, Polygon, WebMercatorToWgs84,
+    EARTH_RADIUS_METERS,
+};
+pub use crate::icp::Correspondence;
+pub use crate::impute::impute_knn;
+pub use crate::incremental::IncrementalKDTree;
+pub use crate::index::{Index, IndexHints, SpatialIndex};
+pub use crate::intern::{InternedKDTree, Interner, Symbol};
+#[cfg(feature = "mmap")]
+pub use crate::mapped::MappedKDTree;
+pub use crate::metrics::{MetricsRecorder, QueryMetrics};
+pub use crate::normalized::{CosineDistance, DotProductDistance, NormalizedKDTree};
+pub use crate::normals::estimate_normals;
+pub use crate::orientation::{AngularDistance, QuaternionDistance};
+pub use crate::orthtree::{Octree, Orthtree, Quadtree};
+#[cfg(feature = "rayon")]
+pub use crate::parallel::Threads;
+pub use crate::payload::PayloadStore;
+pub use crate::query::Query;
+pub use crate::raster::Grid;
+pub use crate::reindex::ReindexPolicy;
+pub use crate::sample::{RandomSource, SeededRandom};
+pub use crate::shard::{partition_by_curve, ShardedIndex};
+pub use crate::smooth::{smooth_points, Smoothing};
+pub use crate::trace::TraceMatch;
+pub use crate::tree::{Data, KDTree, Neighbor, Point, TreeNode};
+pub use crate::vptree::VPTree;
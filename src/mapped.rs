@@ -0,0 +1,366 @@
+//! Trees read in place from memory mapped files, so a large tree is opened
+//! without reading it, and shared read only between processes through the
+//! page cache.
+//!
+//! The file holds the tree as [`FrozenKDTree`] does, without nodes, in a
+//! layout that can be read where it lies. All numbers are little endian.
+//! After an 8 byte magic string, the layout version and a CRC-32 of
+//! everything after the header come the dimension, leaf size, record count
+//! and payload size. Then follow the coordinates stored one axis after
+//! another, padded to 8 bytes, every record's id, the offsets of every
+//! record's encoded data and at last the encoded data itself.
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::format::{crc32, ChecksumOutput, Encode};
+use crate::frozen::ImplicitTree;
+use crate::tree::{KDTree, NearestCollector, Neighbor, Point, RadiusCollector, RawNeighbor};
+use crate::warm::touch_pages;
+
+/// First bytes of every mapped tree file.
+const MAGIC: [u8; 8] = *b"CLOSESTM";
+
+/// Version of the layout written by this release.
+const LAYOUT_VERSION: u32 = 1;
+
+/// Size of the header, before the coordinates.
+const HEADER: usize = 48;
+
+fn invalid(message: &str) -> ClosestError {
+    ClosestError::InvalidFormat(message.to_string())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("sized slice"))
+}
+
+/// Where each part of the file starts.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    ids: usize,
+    offsets: usize,
+    payloads: usize,
+    end: usize,
+}
+
+impl Layout {
+    fn new(dimension: usize, len: usize, payload_len: usize) -> Option<Self> {
+        let coordinates = dimension.checked_mul(len)?.checked_mul(4)?;
+        let ids = HEADER.checked_add(coordinates)?.next_multiple_of(8);
+        let offsets = ids.checked_add(len.checked_mul(8)?)?;
+        let payloads = offsets.checked_add(len.checked_add(1)?.checked_mul(8)?)?;
+        let end = payloads.checked_add(payload_len)?;
+        Some(Layout {
+            ids,
+            offsets,
+            payloads,
+            end,
+        })
+    }
+}
+
+impl<T: Clone + Encode> KDTree<T> {
+    /// Store the tree in a file that [`MappedKDTree::open`] reads in place,
+    /// indexing any records added since the tree was last built. The file
+    /// is written as it is encoded, records are neither copied nor held in
+    /// memory encoded.
+    pub fn save_mapped<P: AsRef<Path>>(&self, path: P) -> Result<(), ClosestError> {
+        let order = self.rebuilt_order();
+        let (dimension, len) = (self.records_dimension(), order.len());
+        // The offsets of the encoded data come before it, so it is encoded
+        // once to measure it and again to write it.
+        let mut offsets = Vec::with_capacity(len + 1);
+        let mut payload_len = 0;
+        let mut scratch = Vec::new();
+        offsets.push(0);
+        for position in &order {
+            scratch.clear();
+            self.data[*position].data.encode(&mut scratch);
+            payload_len += scratch.len();
+            offsets.push(payload_len);
+        }
+        let layout = Layout::new(dimension, len, payload_len).expect("tree fits in memory");
+        let mut header = Vec::with_capacity(HEADER);
+        header.extend_from_slice(&MAGIC);
+        LAYOUT_VERSION.encode(&mut header);
+        // The checksum, written once the rest of the file is.
+        0u32.encode(&mut header);
+        dimension.encode(&mut header);
+        self.min_points.encode(&mut header);
+        len.encode(&mut header);
+        payload_len.encode(&mut header);
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&header)?;
+
+        let mut out = ChecksumOutput::new(writer);
+        for axis in 0..dimension {
+            for position in &order {
+                self.data[*position]
+                    .point
+                    .point(axis)
+                    .encode(&mut out.buffer);
+                out.write_if_full()?;
+            }
+        }
+        out.buffer.resize(
+            out.buffer.len() + layout.ids - HEADER - 4 * dimension * len,
+            0,
+        );
+        for position in &order {
+            self.data[*position].id.encode(&mut out.buffer);
+            out.write_if_full()?;
+        }
+        for offset in offsets {
+            offset.encode(&mut out.buffer);
+            out.write_if_full()?;
+        }
+        for position in &order {
+            self.data[*position].data.encode(&mut out.buffer);
+            out.write_if_full()?;
+        }
+        let (mut writer, checksum) = out.finish()?;
+        writer.seek(SeekFrom::Start(12))?;
+        writer.write_all(&checksum.to_le_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read only tree over a memory mapped file written by
+/// [`KDTree::save_mapped`].
+///
+/// Opening only checks the header and the file's size, pages are read as
+/// queries touch them, and the data of records is decoded only for the
+/// records a query returns. Call [`MappedKDTree::verify`] to check the whole
+/// file against its checksum, and [`MappedKDTree::warm`] to read it all in
+/// before the first query.
+#[derive(Debug)]
+pub struct MappedKDTree<T> {
+    map: Mmap,
+    layout: Layout,
+    dimension: usize,
+    min_points: usize,
+    len: usize,
+    data: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Clone + Encode> MappedKDTree<T> {
+    /// Map a tree file. The file must not be changed while it is mapped,
+    /// replace it by writing a new file and renaming it over the old one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ClosestError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read, and the file is documented to stay
+        // unchanged while it is mapped.
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < HEADER || map[..8] != MAGIC {
+            return Err(invalid("not a mapped tree file"));
+        }
+        let version = u32::from_le_bytes(map[8..12].try_into().expect("sized slice"));
+        if version != LAYOUT_VERSION {
+            return Err(ClosestError::UnsupportedFormatVersion {
+                found: version,
+                supported: LAYOUT_VERSION,
+            });
+        }
+        let header = |offset| usize::try_from(read_u64(&map, offset));
+        let (Ok(dimension), Ok(min_points), Ok(len), Ok(payload_len)) =
+            (header(16), header(24), header(32), header(40))
+        else {
+            return Err(invalid("value does not fit a usize"));
+        };
+        let layout = Layout::new(dimension, len, payload_len)
+            .ok_or_else(|| invalid("sizes out of range"))?;
+        if layout.end != map.len() {
+            return Err(invalid("file size does not match its header"));
+        }
+        if dimension == 0 && len > 0 {
+            return Err(invalid("records without coordinates"));
+        }
+        Ok(MappedKDTree {
+            map,
+            layout,
+            dimension,
+            min_points,
+            len,
+            data: std::marker::PhantomData,
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+    /// Read the whole file and check it against its checksum.
+    pub fn verify(&self) -> Result<(), ClosestError> {
+        let stored = u32::from_le_bytes(self.map[12..16].try_into().expect("sized slice"));
+        if crc32(&self.map[HEADER..]) != stored {
+            return Err(invalid("checksum mismatch, the data is corrupted"));
+        }
+        Ok(())
+    }
+    /// Touch every page of the file, so it is in memory before the first
+    /// query.
+    pub fn warm(&self) {
+        touch_pages(&self.map);
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        self.neighbors_from(collector.into_sorted_vec())
+    }
+    /// Positions, in the data the tree was built from, of the k nearest
+    /// neighbors of a given point, ordered from nearest to farthest.
+    pub fn k_nearest_indices<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<usize> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| read_u64(&self.map, self.layout.ids + 8 * r.data_pointer) as usize)
+            .collect()
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        self.neighbors_from(collector.into_sorted_vec())
+    }
+    /// Decode the data of a record, by its position in tree order.
+    fn data_at(&self, record: usize) -> Result<T, ClosestError> {
+        let offset = |i: usize| read_u64(&self.map, self.layout.offsets + 8 * i) as usize;
+        let (start, stop) = (offset(record), offset(record + 1));
+        let payloads = &self.map[self.layout.payloads..];
+        let mut input = payloads
+            .get(start..stop)
+            .ok_or_else(|| invalid("record data out of range"))?;
+        let data = T::decode(&mut input)?;
+        if !input.is_empty() {
+            return Err(invalid("record data longer than its encoding"));
+        }
+        Ok(data)
+    }
+    fn neighbors_from(&self, found: Vec<RawNeighbor>) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        found
+            .into_iter()
+            .map(|r| {
+                Ok(Neighbor {
+                    distance: r.distance,
+                    data: self.data_at(r.data_pointer)?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<T> ImplicitTree for MappedKDTree<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+    fn min_points(&self) -> usize {
+        self.min_points
+    }
+    fn coordinate(&self, record: usize, axis: usize) -> f32 {
+        let offset = HEADER + 4 * (axis * self.len + record);
+        f32::from_le_bytes(
+            self.map[offset..offset + 4]
+                .try_into()
+                .expect("sized slice"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn mapped_matches_tree() {
+        let data = (0..300)
+            .map(|i| Data::new(format!("record {i}"), scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 8).unwrap();
+        tree.extend([Data::new("added".to_string(), vec![50.5, 20.5])]);
+        tree.delete(7);
+        let path = std::env::temp_dir().join(format!("closest-mapped-{}.bin", std::process::id()));
+        tree.save_mapped(&path).unwrap();
+        let mapped = MappedKDTree::<String>::open(&path).unwrap();
+        mapped.verify().unwrap();
+        mapped.warm();
+        assert_eq!(mapped.len(), 300);
+        let metric = SquaredEuclideanDistance::default();
+        for i in 0..40 {
+            let point = Point::new(vec![i as f32 * 2.5, i as f32 * 1.1]);
+            let expected = tree.get_nearest_neighbors(&point, 5, &metric);
+            let found = mapped.get_nearest_neighbors(&point, 5, &metric).unwrap();
+            let distances =
+                |n: &[Neighbor<String>]| n.iter().map(|n| n.distance).collect::<Vec<_>>();
+            assert_eq!(distances(&expected), distances(&found));
+            let within = mapped
+                .get_neighbors_within_radius(&point, 30., &metric)
+                .unwrap();
+            assert_eq!(
+                within.len(),
+                tree.get_neighbors_within_radius(&point, 30., &metric).len()
+            );
+        }
+        let point = Point::from([50.5, 20.5]);
+        assert_eq!(
+            mapped.get_nearest_neighbors(&point, 1, &metric).unwrap()[0].data,
+            "added"
+        );
+        assert_eq!(mapped.k_nearest_indices(&point, 1, &metric), vec![300]);
+
+        // A changed byte fails verification, a cut file fails to open.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[HEADER + 5] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(MappedKDTree::<String>::open(&path)
+            .unwrap()
+            .verify()
+            .is_err());
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MappedKDTree::<String>::open(&path).is_err());
+        bytes[8..12].copy_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            MappedKDTree::<String>::open(&path),
+            Err(ClosestError::UnsupportedFormatVersion {
+                found: 0,
+                supported: LAYOUT_VERSION
+            })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
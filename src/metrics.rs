@@ -0,0 +1,167 @@
+//! Aggregate statistics over the queries a tree answers, for monitoring a
+//! service's query cost over time.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::bounds::BoundingBox;
+use crate::tree::{Collector, KDTree};
+
+/// Candidate counts are grouped in buckets of powers of two, bucket `i`
+/// holding counts below `2^i`.
+const BUCKETS: usize = 40;
+
+/// Counters shared by every query of the trees it is attached to, with
+/// [`KDTree::set_metrics_recorder`]. Counters are atomic, so one recorder
+/// can be read while queries run on other threads.
+#[derive(Debug)]
+pub struct MetricsRecorder {
+    queries: AtomicU64,
+    nodes_visited: AtomicU64,
+    candidates: AtomicU64,
+    candidate_buckets: [AtomicU64; BUCKETS],
+}
+
+/// Statistics read from a [`MetricsRecorder`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMetrics {
+    /// Number of queries answered.
+    pub queries: u64,
+    /// Mean number of tree nodes visited per query.
+    pub mean_nodes_visited: f64,
+    /// Mean number of records compared to the query point per query.
+    pub mean_candidates: f64,
+    /// 99th percentile of the number of records compared per query, rounded
+    /// up to one less than a power of two.
+    pub p99_candidates: u64,
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        MetricsRecorder {
+            queries: AtomicU64::new(0),
+            nodes_visited: AtomicU64::new(0),
+            candidates: AtomicU64::new(0),
+            candidate_buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl MetricsRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub(crate) fn record(&self, nodes_visited: u64, candidates: u64) {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.nodes_visited
+            .fetch_add(nodes_visited, Ordering::Relaxed);
+        self.candidates.fetch_add(candidates, Ordering::Relaxed);
+        let bucket = ((u64::BITS - candidates.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.candidate_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+    /// Statistics over every query recorded since the recorder was created
+    /// or last reset.
+    pub fn snapshot(&self) -> QueryMetrics {
+        let queries = self.queries.load(Ordering::Relaxed);
+        let mean = |total: &AtomicU64| {
+            if queries == 0 {
+                0.
+            } else {
+                total.load(Ordering::Relaxed) as f64 / queries as f64
+            }
+        };
+        let needed = (queries as f64 * 0.99).ceil() as u64;
+        let mut seen = 0;
+        let mut p99_candidates = 0;
+        for (bucket, count) in self.candidate_buckets.iter().enumerate() {
+            seen += count.load(Ordering::Relaxed);
+            if seen >= needed.max(1) {
+                p99_candidates = (1u64 << bucket) - 1;
+                break;
+            }
+        }
+        QueryMetrics {
+            queries,
+            mean_nodes_visited: mean(&self.nodes_visited),
+            mean_candidates: mean(&self.candidates),
+            p99_candidates,
+        }
+    }
+    /// Set every counter back to zero.
+    pub fn reset(&self) {
+        self.queries.store(0, Ordering::Relaxed);
+        self.nodes_visited.store(0, Ordering::Relaxed);
+        self.candidates.store(0, Ordering::Relaxed);
+        for count in &self.candidate_buckets {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Counts the nodes and records a search visits on the way to another
+/// collector.
+pub(crate) struct CountingCollector<'a, C: Collector> {
+    pub(crate) inner: &'a mut C,
+    pub(crate) nodes_visited: u64,
+    pub(crate) candidates: u64,
+}
+
+impl<C: Collector> Collector for CountingCollector<'_, C> {
+    const USES_REGIONS: bool = C::USES_REGIONS;
+    fn bound(&self) -> f32 {
+        self.inner.bound()
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        self.candidates += 1;
+        self.inner.offer(distance, data_pointer);
+    }
+    fn accepts_region(&self, region: &BoundingBox) -> bool {
+        self.inner.accepts_region(region)
+    }
+    fn visit_node(&mut self) {
+        self.nodes_visited += 1;
+        self.inner.visit_node();
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Record statistics of every query answered by the tree in `recorder`,
+    /// or stop recording with `None`. Clones of the tree share the recorder.
+    pub fn set_metrics_recorder(&mut self, recorder: Option<Arc<MetricsRecorder>>) {
+        self.recorder = recorder;
+    }
+    pub fn metrics_recorder(&self) -> Option<&Arc<MetricsRecorder>> {
+        self.recorder.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data, Point};
+
+    #[test]
+    fn records_queries() {
+        let data = (0..500)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 8).unwrap();
+        let recorder = Arc::new(MetricsRecorder::new());
+        tree.set_metrics_recorder(Some(recorder.clone()));
+        let metric = SquaredEuclideanDistance::default();
+        for i in 0..100 {
+            let point = Point::from([i as f32, i as f32 * 0.3]);
+            tree.get_nearest_neighbors(&point, 3, &metric);
+        }
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.queries, 100);
+        assert!(metrics.mean_nodes_visited >= 1.);
+        assert!(metrics.mean_candidates >= 3. && metrics.mean_candidates < 500.);
+        assert!(metrics.p99_candidates as f64 >= metrics.mean_candidates / 2.);
+        recorder.reset();
+        assert_eq!(recorder.snapshot().queries, 0);
+        tree.set_metrics_recorder(None);
+        tree.get_nearest_neighbors(&Point::from([0., 0.]), 3, &metric);
+        assert_eq!(recorder.snapshot().queries, 0);
+    }
+}
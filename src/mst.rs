@@ -0,0 +1,359 @@
+//! Euclidean minimum spanning tree and single linkage clustering, with
+//! dual-tree Borůvka.
+//!
+//! Every round, each component of the forest finds its nearest record in
+//! another component, and is joined to it. Rather than querying record by
+//! record, pairs of nodes of the tree are compared, and a pair is skipped when
+//! both nodes lie in one component, or when the nodes are farther apart than
+//! any edge their records could still use.
+use crate::bounds::BoundingBox;
+use crate::tree::{KDTree, NodeOrDataPointer};
+
+/// Node of the tree, with the record it splits on as a child leaf of its
+/// own, so that every record is in exactly one leaf.
+struct BoruvkaNode {
+    start: usize,
+    stop: usize,
+    children: Vec<usize>,
+    bounds: BoundingBox,
+}
+
+/// Union find over record positions.
+struct Components {
+    parent: Vec<usize>,
+}
+
+impl Components {
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b;
+        a != b
+    }
+}
+
+/// Squared euclidean distance between the closest points of two boxes.
+fn box_distance(a: &BoundingBox, b: &BoundingBox) -> f32 {
+    (0..a.min.shape())
+        .map(|i| {
+            let gap = (a.min.point(i) - b.max.point(i)).max(b.min.point(i) - a.max.point(i));
+            gap.max(0.).powi(2)
+        })
+        .sum()
+}
+
+/// State of one Borůvka round.
+struct Round<'a, T: Clone> {
+    tree: &'a KDTree<T>,
+    nodes: &'a [BoruvkaNode],
+    /// Component of each record.
+    component: Vec<usize>,
+    /// Component shared by all records under each node, if there is one.
+    uniform: Vec<Option<usize>>,
+    /// Shortest edge out of each component, as (distance, from, to).
+    best: Vec<(f32, usize, usize)>,
+    /// Upper bound on the distance of the edge any record under each node
+    /// could still improve on.
+    bound: Vec<f32>,
+}
+
+impl<T: Clone> Round<'_, T> {
+    fn mark_uniform(&mut self, node: usize) -> Option<usize> {
+        let n = &self.nodes[node];
+        let uniform = if n.children.is_empty() {
+            let first = self.component[n.start];
+            (n.start..n.stop)
+                .all(|i| self.component[i] == first)
+                .then_some(first)
+        } else {
+            let children = n
+                .children
+                .iter()
+                .map(|c| self.mark_uniform(*c))
+                .collect::<Vec<_>>();
+            children[0].filter(|first| children.iter().all(|c| *c == Some(*first)))
+        };
+        self.uniform[node] = uniform;
+        uniform
+    }
+    fn leaf_bound(&self, node: usize) -> f32 {
+        let n = &self.nodes[node];
+        (n.start..n.stop)
+            .map(|i| self.best[self.component[i]].0)
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+    fn search(&mut self, query: usize, reference: usize) {
+        let nodes = self.nodes;
+        let (q, r) = (&nodes[query], &nodes[reference]);
+        if self.uniform[query].is_some() && self.uniform[query] == self.uniform[reference] {
+            return;
+        }
+        if box_distance(&q.bounds, &r.bounds) > self.bound[query] {
+            return;
+        }
+        match (q.children.is_empty(), r.children.is_empty()) {
+            (true, true) => {
+                for i in q.start..q.stop {
+                    for j in r.start..r.stop {
+                        let component = self.component[i];
+                        if component == self.component[j] {
+                            continue;
+                        }
+                        let distance = self.tree.data[i]
+                            .point
+                            .coordinates
+                            .iter()
+                            .zip(&self.tree.data[j].point.coordinates)
+                            .map(|(a, b)| (a - b).powi(2))
+                            .sum::<f32>();
+                        if distance < self.best[component].0 {
+                            self.best[component] = (distance, i, j);
+                        }
+                    }
+                }
+                self.bound[query] = self.leaf_bound(query);
+            }
+            (true, false) => {
+                for child in self.closest_first(query, &r.children) {
+                    self.search(query, child);
+                }
+            }
+            (false, _) => {
+                for query_child in &q.children {
+                    let references = if r.children.is_empty() {
+                        vec![reference]
+                    } else {
+                        self.closest_first(*query_child, &r.children)
+                    };
+                    for child in references {
+                        self.search(*query_child, child);
+                    }
+                }
+                self.bound[query] = q
+                    .children
+                    .iter()
+                    .map(|c| self.bound[*c])
+                    .fold(f32::NEG_INFINITY, f32::max);
+            }
+        }
+    }
+    fn closest_first(&self, query: usize, references: &[usize]) -> Vec<usize> {
+        let mut references = references.to_vec();
+        let bounds = &self.nodes[query].bounds;
+        references.sort_by(|a, b| {
+            box_distance(bounds, &self.nodes[*a].bounds)
+                .total_cmp(&box_distance(bounds, &self.nodes[*b].bounds))
+        });
+        references
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    fn boruvka_nodes(&self) -> Vec<BoruvkaNode> {
+        fn add<T: Clone>(
+            tree: &KDTree<T>,
+            nodes: &mut Vec<BoruvkaNode>,
+            node: Option<&NodeOrDataPointer>,
+            start: usize,
+            stop: usize,
+        ) -> usize {
+            let index = nodes.len();
+            let bounds = BoundingBox::from_points(tree.data[start..stop].iter().map(|d| &d.point))
+                .expect("nodes hold records");
+            nodes.push(BoruvkaNode {
+                start,
+                stop,
+                children: Vec::new(),
+                bounds,
+            });
+            if let Some(NodeOrDataPointer::Node(n)) = node {
+                let median = n.data_pointer;
+                let mut children = vec![add(tree, nodes, None, median, median + 1)];
+                for (child, child_start, child_stop) in
+                    [(&n.left, start, median), (&n.right, median + 1, stop)]
+                {
+                    if child_start < child_stop {
+                        children.push(add(tree, nodes, Some(child), child_start, child_stop));
+                    }
+                }
+                nodes[index].children = children;
+            }
+            index
+        }
+        let mut nodes = Vec::new();
+        if self.data.is_empty() {
+            return nodes;
+        }
+        if self.indexed == self.data.len() {
+            add(self, &mut nodes, Some(&self.root_node), 0, self.indexed);
+            return nodes;
+        }
+        // Records added since the last build become leaves of a new root.
+        nodes.push(BoruvkaNode {
+            start: 0,
+            stop: self.data.len(),
+            children: Vec::new(),
+            bounds: self.extent.clone().expect("tree is not empty"),
+        });
+        let mut children = Vec::new();
+        if self.indexed > 0 {
+            children.push(add(
+                self,
+                &mut nodes,
+                Some(&self.root_node),
+                0,
+                self.indexed,
+            ));
+        }
+        for i in self.indexed..self.data.len() {
+            children.push(add(self, &mut nodes, None, i, i + 1));
+        }
+        nodes[0].children = children;
+        nodes
+    }
+    /// Euclidean minimum spanning tree of all records, as `(a, b, distance)`
+    /// edges ordered by increasing distance, with squared euclidean
+    /// distances. Records are identified by their positions in the order
+    /// they were added, which are their positions in the data the tree was
+    /// built from, unless records were removed.
+    pub fn minimum_spanning_tree(&self) -> Vec<(usize, usize, f32)> {
+        let nodes = self.boruvka_nodes();
+        let n = self.data.len();
+        let mut components = Components {
+            parent: (0..n).collect(),
+        };
+        let mut edges = Vec::with_capacity(n.saturating_sub(1));
+        while edges.len() + 1 < n {
+            let component = (0..n).map(|i| components.find(i)).collect();
+            let mut round = Round {
+                tree: self,
+                nodes: &nodes,
+                component,
+                uniform: vec![None; nodes.len()],
+                best: vec![(f32::INFINITY, 0, 0); n],
+                bound: vec![f32::INFINITY; nodes.len()],
+            };
+            round.mark_uniform(0);
+            round.search(0, 0);
+            let mut joined = false;
+            for (distance, a, b) in round.best {
+                if distance.is_finite() && components.union(a, b) {
+                    edges.push((a, b, distance));
+                    joined = true;
+                }
+            }
+            if !joined {
+                break;
+            }
+        }
+        let rank = self.by_input_order(0..n);
+        let mut position = vec![0; n];
+        for (r, i) in rank.into_iter().enumerate() {
+            position[i] = r;
+        }
+        let mut edges = edges
+            .into_iter()
+            .map(|(a, b, distance)| (position[a], position[b], distance))
+            .collect::<Vec<_>>();
+        edges.sort_by(|a, b| a.2.total_cmp(&b.2));
+        edges
+    }
+    /// Single linkage clustering of all records, in the layout of SciPy's
+    /// linkage matrix. Each step merges two clusters into a new one, and is
+    /// `(a, b, distance, size)`, where clusters below the number of records
+    /// are single records, identified as in [`KDTree::minimum_spanning_tree`],
+    /// and cluster `n + i` is the one made at step `i`.
+    pub fn single_linkage(&self) -> Vec<(usize, usize, f32, usize)> {
+        let n = self.data.len();
+        let mut components = Components {
+            parent: (0..n).collect(),
+        };
+        // Cluster id and size of each component, by its root.
+        let mut cluster = (0..n).map(|i| (i, 1)).collect::<Vec<_>>();
+        self.minimum_spanning_tree()
+            .into_iter()
+            .enumerate()
+            .map(|(step, (a, b, distance))| {
+                let (root_a, root_b) = (components.find(a), components.find(b));
+                let ((cluster_a, size_a), (cluster_b, size_b)) = (cluster[root_a], cluster[root_b]);
+                components.union(root_a, root_b);
+                cluster[components.find(root_a)] = (n + step, size_a + size_b);
+                (
+                    cluster_a.min(cluster_b),
+                    cluster_a.max(cluster_b),
+                    distance,
+                    size_a + size_b,
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tree::{Data, KDTree};
+
+    /// Prim's algorithm over every pair.
+    fn brute_force_weight(points: &[Vec<f32>]) -> f32 {
+        let n = points.len();
+        let distance = |a: usize, b: usize| -> f32 {
+            points[a]
+                .iter()
+                .zip(&points[b])
+                .map(|(x, y)| (x - y).powi(2))
+                .sum()
+        };
+        let mut in_tree = vec![false; n];
+        let mut cheapest = vec![f32::INFINITY; n];
+        cheapest[0] = 0.;
+        let mut total = 0.;
+        for _ in 0..n {
+            let next = (0..n)
+                .filter(|i| !in_tree[*i])
+                .min_by(|a, b| cheapest[*a].total_cmp(&cheapest[*b]))
+                .unwrap();
+            in_tree[next] = true;
+            total += cheapest[next];
+            for (i, c) in cheapest.iter_mut().enumerate() {
+                *c = c.min(distance(next, i));
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn spanning_tree_and_linkage() {
+        let points = (0..200)
+            .map(|i| vec![(i * 37 % 101) as f32 * 0.7, (i * 13 % 47) as f32 * 1.3])
+            .collect::<Vec<_>>();
+        let data = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Data::new(i, p.clone()))
+            .collect();
+        let mut tree = KDTree::from_vec(data, 6).unwrap();
+        let edges = tree.minimum_spanning_tree();
+        assert_eq!(edges.len(), 199);
+        let weight = edges.iter().map(|e| e.2).sum::<f32>();
+        assert!((weight - brute_force_weight(&points)).abs() < 1e-2);
+
+        let mut points = points;
+        points.push(vec![500., 500.]);
+        tree.extend(std::iter::once(Data::new(200, vec![500., 500.])));
+        let edges = tree.minimum_spanning_tree();
+        let weight = edges.iter().map(|e| e.2).sum::<f32>();
+        assert!((weight - brute_force_weight(&points)).abs() < 1e-2);
+
+        let linkage = tree.single_linkage();
+        assert_eq!(linkage.len(), 200);
+        let last = linkage.last().unwrap();
+        assert_eq!(last.3, 201);
+        assert!(last.1 == 200 || last.0 == 200);
+    }
+}
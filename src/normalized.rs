@@ -0,0 +1,196 @@
+use crate::distance::{shave, DistanceMetric, Summation};
+use crate::error::ClosestError;
+use crate::tree::{Data, KDTree, Neighbor, Point};
+
+/// One minus the dot product of two points.
+///
+/// For unit length vectors this is the cosine distance, and equal to half the
+/// squared euclidean distance, which gives the pruning bound. Only valid on
+/// L2 normalized coordinates, see [`NormalizedKDTree`].
+#[derive(Debug, Default)]
+pub struct DotProductDistance {
+    pub summation: Summation,
+}
+
+impl DistanceMetric for DotProductDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        1. - self
+            .summation
+            .sum(p1.iter().zip(p2).map(|(s1, s2)| s1 * s2))
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        (point[axis] - value).powi(2) / 2.
+    }
+}
+
+/// One minus the cosine similarity of two points, which compares their
+/// directions and ignores their lengths. Zero length points are at distance
+/// one from everything.
+///
+/// A splitting plane only bounds the direction of points past it when it
+/// does not pass the origin's side, so the tree prunes far less than with
+/// [`DotProductDistance`] over a [`NormalizedKDTree`], which gives the same
+/// neighbors. Use this metric when the stored vectors must keep their
+/// lengths.
+#[derive(Debug, Default)]
+pub struct CosineDistance {
+    pub summation: Summation,
+}
+
+impl DistanceMetric for CosineDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let dot = self
+            .summation
+            .sum(p1.iter().zip(p2).map(|(s1, s2)| s1 * s2));
+        let norms = (self.summation.sum(p1.iter().map(|c| c * c))
+            * self.summation.sum(p2.iter().map(|c| c * c)))
+        .sqrt();
+        if norms > 0. {
+            1. - (dot / norms).clamp(-1., 1.)
+        } else {
+            1.
+        }
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        // Points past a plane at a positive value all have a positive
+        // coordinate along the axis, and at a negative value a negative one.
+        // The nearest such direction lies on the boundary of that half of
+        // the sphere, unless the point's own direction falls inside it.
+        let c = point[axis];
+        let outside = (value > 0. && c <= 0.) || (value < 0. && c >= 0.);
+        let norm = self.summation.sum(point.iter().map(|c| c * c)).sqrt();
+        if !outside || norm == 0. {
+            return 0.;
+        }
+        let sine = (c / norm).abs().min(1.);
+        // One minus the cosine, written to keep its precision for small
+        // angles.
+        shave(sine * sine / (1. + (1. - sine * sine).sqrt()))
+    }
+}
+
+/// Scale a point to unit length. Zero length points are left untouched.
+fn normalize(point: &mut Point) {
+    let norm = point
+        .coordinates
+        .iter()
+        .map(|c| c.powi(2))
+        .sum::<f32>()
+        .sqrt();
+    if norm > 0. {
+        point.coordinates.iter_mut().for_each(|c| *c /= norm);
+    }
+}
+
+/// Tree over embedding vectors, that are L2 normalized when they are
+/// inserted, and queried by dot product similarity.
+#[derive(Debug)]
+pub struct NormalizedKDTree<T: Clone> {
+    tree: KDTree<T>,
+}
+
+impl<T: Clone> NormalizedKDTree<T> {
+    pub fn from_iter<I: Iterator<Item = Data<T>>>(
+        data: I,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        Self::from_vec(data.collect(), min_points)
+    }
+    pub fn from_vec(mut data: Vec<Data<T>>, min_points: usize) -> Result<Self, ClosestError> {
+        data.iter_mut().for_each(|d| normalize(&mut d.point));
+        Ok(NormalizedKDTree {
+            tree: KDTree::from_vec(data, min_points)?,
+        })
+    }
+    /// Insert a record, normalized first, without rebuilding the tree, see
+    /// [`KDTree::insert`].
+    pub fn insert(&mut self, mut record: Data<T>) -> Result<usize, ClosestError> {
+        normalize(&mut record.point);
+        self.tree.insert(record)
+    }
+    /// Get the k most similar records to a given point. The query is
+    /// normalized as well, and the distance of each neighbor is one minus
+    /// the cosine similarity.
+    pub fn get_nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        let mut point = point.clone();
+        normalize(&mut point);
+        self.tree
+            .get_nearest_neighbors(&point, k, &DotProductDistance::default())
+    }
+}
+
+impl<T: Clone> Extend<Data<T>> for NormalizedKDTree<T> {
+    /// Append records, normalized first, see [`KDTree`]'s `Extend`.
+    fn extend<I: IntoIterator<Item = Data<T>>>(&mut self, iter: I) {
+        self.tree.extend(iter.into_iter().map(|mut record| {
+            normalize(&mut record.point);
+            record
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_by_angle() {
+        let data = vec![
+            Data::new("east", vec![10., 0.]),
+            Data::new("north", vec![0., 0.5]),
+            Data::new("north-east", vec![3., 3.]),
+            Data::new("west", vec![-1., 0.]),
+            Data::new("south", vec![0., -7.]),
+        ];
+        let tree = NormalizedKDTree::from_vec(data, 1).unwrap();
+        let nearest = tree.get_nearest_neighbors(&Point::new(vec![0.1, 100.]), 1);
+        assert_eq!(nearest[0].data, "north");
+        let similarity = 1. - nearest[0].distance;
+        assert!((similarity - 0.99999).abs() < 1e-4);
+
+        let mut tree = tree;
+        tree.insert(Data::new("south-west", vec![-4., -4.]))
+            .unwrap();
+        tree.extend([Data::new("north-west", vec![-20., 20.])]);
+        for (query, expected) in [([-1., -1.], "south-west"), ([-1., 1.], "north-west")] {
+            let nearest = tree.get_nearest_neighbors(&Point::new(query.to_vec()), 1);
+            assert_eq!(nearest[0].data, expected);
+            assert!(nearest[0].distance.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cosine_distance() {
+        let metric = CosineDistance::default();
+        assert!(metric.distance(&[2., 0.], &[5., 0.]).abs() < 1e-6);
+        assert!((metric.distance(&[1., 0.], &[0., 3.]) - 1.).abs() < 1e-6);
+        assert!((metric.distance(&[1., 1.], &[-2., -2.]) - 2.).abs() < 1e-6);
+        assert_eq!(metric.distance(&[0., 0.], &[1., 2.]), 1.);
+
+        let data = (0..1000)
+            .map(|i| {
+                Data::new(
+                    i,
+                    vec![
+                        (i * 37 % 101) as f32 - 50.,
+                        (i * 13 % 47) as f32 - 23.,
+                        (i * 7 % 29) as f32 - 14.,
+                    ],
+                )
+            })
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        for query in [[1., 2., 3.], [-40., 0.5, 0.], [0., 0., -1.]] {
+            let mut expected = data
+                .iter()
+                .map(|d| metric.distance(&query, d.point.as_slice()))
+                .collect::<Vec<_>>();
+            expected.sort_by(f32::total_cmp);
+            let found = tree.get_nearest_neighbors(&Point::from(query), 6, &metric);
+            assert_eq!(
+                found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+                expected[..6]
+            );
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Surface normals of 3-D point clouds, estimated from the shape of every
+//! point's neighborhood.
+use crate::distance::SquaredEuclideanDistance;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, Point};
+
+/// Rotate the symmetric matrix `a` to zero its `(p, q)` entry, accumulating
+/// the rotation into the columns of `v`.
+fn jacobi_rotate(a: &mut [[f64; 3]; 3], v: &mut [[f64; 3]; 3], p: usize, q: usize) {
+    if a[p][q] == 0. {
+        return;
+    }
+    let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+    let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+    let c = 1. / (t * t + 1.).sqrt();
+    let s = t * c;
+    for row in a.iter_mut() {
+        let (kp, kq) = (row[p], row[q]);
+        row[p] = c * kp - s * kq;
+        row[q] = s * kp + c * kq;
+    }
+    let (row_p, row_q) = (a[p], a[q]);
+    a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+    a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+    for row in v.iter_mut() {
+        let (kp, kq) = (row[p], row[q]);
+        row[p] = c * kp - s * kq;
+        row[q] = s * kp + c * kq;
+    }
+}
+
+/// Unit eigenvector of the smallest eigenvalue of a symmetric matrix, by
+/// Jacobi rotations.
+fn smallest_eigenvector(mut a: [[f64; 3]; 3]) -> [f64; 3] {
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    for _ in 0..32 {
+        let off = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        let scale = a[0][0].abs() + a[1][1].abs() + a[2][2].abs();
+        if off <= scale * 1e-15 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            jacobi_rotate(&mut a, &mut v, p, q);
+        }
+    }
+    let smallest = (0..3)
+        .min_by(|i, j| a[*i][*i].total_cmp(&a[*j][*j]))
+        .expect("three eigenvalues");
+    [v[0][smallest], v[1][smallest], v[2][smallest]]
+}
+
+/// Estimate the unit surface normal at every point of a 3-D point cloud, as
+/// the direction of least variance of its k nearest neighbors, the point
+/// itself included. The sign of every normal is arbitrary, orient them
+/// towards the scanner or away from the object's center as needed. Normals
+/// of points whose neighbors lie on a line, or coincide, are undetermined.
+pub fn estimate_normals(points: &[[f32; 3]], k: usize) -> Result<Vec<[f32; 3]>, ClosestError> {
+    if k < 3 {
+        return Err(ClosestError::NotEnoughSamples {
+            needed: 3,
+            found: k,
+        });
+    }
+    if points.is_empty() {
+        return Ok(Vec::new());
+    }
+    let tree = KDTree::from_points(points.iter().map(|p| p.to_vec()), 16)?;
+    let metric = SquaredEuclideanDistance::default();
+    Ok(points
+        .iter()
+        .map(|point| {
+            let nearest = tree.k_nearest(&Point::from(*point), k, &metric);
+            let count = nearest.len() as f64;
+            let mut mean = [0f64; 3];
+            for (i, _) in &nearest {
+                for (m, c) in mean.iter_mut().zip(points[*i]) {
+                    *m += c as f64 / count;
+                }
+            }
+            let mut covariance = [[0f64; 3]; 3];
+            for (i, _) in &nearest {
+                let centered = [0, 1, 2].map(|axis| points[*i][axis] as f64 - mean[axis]);
+                for (row, r) in covariance.iter_mut().zip(centered) {
+                    for (entry, c) in row.iter_mut().zip(centered) {
+                        *entry += r * c;
+                    }
+                }
+            }
+            smallest_eigenvector(covariance).map(|c| c as f32)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normals_of_tilted_plane() {
+        // The plane z = x / 2 + y / 4, with a little noise off it.
+        let points = (0..400)
+            .map(|i| {
+                let (x, y) = ((i % 20) as f32, (i / 20) as f32);
+                let noise = ((i * 37 % 11) as f32 - 5.) / 500.;
+                [x, y, x / 2. + y / 4. + noise]
+            })
+            .collect::<Vec<_>>();
+        let normals = estimate_normals(&points, 10).unwrap();
+        assert_eq!(normals.len(), points.len());
+        let length = (0.25f32 + 0.0625 + 1.).sqrt();
+        let expected = [-0.5 / length, -0.25 / length, 1. / length];
+        for normal in normals {
+            let norm = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+            assert!((norm - 1.).abs() < 1e-5);
+            let alignment = normal.iter().zip(expected).map(|(a, b)| a * b).sum::<f32>();
+            assert!(alignment.abs() > 0.99);
+        }
+        assert!(estimate_normals(&points, 2).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Angular distances between directions and rotations, for finding the
+//! closest stored orientation.
+//!
+//! Directions are unit vectors, and rotations unit quaternions stored as
+//! `[w, x, y, z]`. Both metrics prune through the chord between unit
+//! vectors: any point on the far side of a splitting plane is at least the
+//! plane's distance away in euclidean terms, and the angle grows with the
+//! chord.
+use crate::distance::DistanceMetric;
+
+/// Euclidean lengths of the difference and sum of two vectors.
+fn difference_and_sum(p1: &[f32], p2: &[f32]) -> (f64, f64) {
+    let (difference, sum) = p1
+        .iter()
+        .zip(p2)
+        .fold((0f64, 0f64), |(difference, sum), (s1, s2)| {
+            let (s1, s2) = (*s1 as f64, *s2 as f64);
+            (difference + (s1 - s2).powi(2), sum + (s1 + s2).powi(2))
+        });
+    (difference.sqrt(), sum.sqrt())
+}
+
+/// Angle between unit vectors a chord of this length apart.
+fn chord_to_angle(chord: f64) -> f64 {
+    2. * (chord / 2.).min(1.).asin()
+}
+
+/// Great circle distance between unit vectors, the angle between them in
+/// radians, from 0 to π. On the unit sphere S² these are the directions of
+/// surface normals, and in any dimension the angle between unit vectors.
+#[derive(Debug, Default)]
+pub struct AngularDistance {}
+
+impl DistanceMetric for AngularDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let (difference, sum) = difference_and_sum(p1, p2);
+        (2. * difference.atan2(sum)) as f32
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        chord_to_angle((point[axis] - value).abs() as f64) as f32
+    }
+}
+
+/// Angle in radians, from 0 to π, of the rotation taking one orientation to
+/// another, between unit quaternions `[w, x, y, z]`. A quaternion and its
+/// negation are the same rotation, and are at no distance.
+#[derive(Debug, Default)]
+pub struct QuaternionDistance {}
+
+impl DistanceMetric for QuaternionDistance {
+    fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+        let (difference, sum) = difference_and_sum(p1, p2);
+        (4. * difference.min(sum).atan2(difference.max(sum))) as f32
+    }
+    fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+        // The far side must be at least as far from both the point and its
+        // negation, which may itself lie on the far side.
+        let (c, v) = (point[axis] as f64, value as f64);
+        let chord = if c < v {
+            (v - c).min((v + c).max(0.))
+        } else {
+            (c - v).min((-c - v).max(0.))
+        };
+        (2. * chord_to_angle(chord)).min(std::f64::consts::PI) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{Data, KDTree, Point};
+
+    #[test]
+    fn closest_orientations() {
+        let mut state = 0x2545f4914f6cdd1du64;
+        let mut unit = |dimension: usize| {
+            let v = (0..dimension)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    (state >> 40) as f32 / (1u64 << 23) as f32 - 1.
+                })
+                .collect::<Vec<_>>();
+            let norm = v.iter().map(|c| c * c).sum::<f32>().sqrt();
+            v.into_iter().map(|c| c / norm).collect::<Vec<_>>()
+        };
+        let normals = (0..500).map(|i| Data::new(i, unit(3))).collect::<Vec<_>>();
+        let rotations = (0..500).map(|i| Data::new(i, unit(4))).collect::<Vec<_>>();
+        let queries = (0..50).map(|_| (unit(3), unit(4))).collect::<Vec<_>>();
+
+        let check = |data: &[Data<usize>],
+                     query: &[f32],
+                     metric: &dyn Fn(&[f32], &[f32]) -> f32,
+                     found: Vec<usize>| {
+            let mut expected = data
+                .iter()
+                .map(|d| (metric(query, d.point.as_slice()), d.data))
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let expected = expected
+                .iter()
+                .take(found.len())
+                .map(|e| e.1)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        };
+        let normal_tree = KDTree::from_vec(normals.clone(), 8).unwrap();
+        let rotation_tree = KDTree::from_vec(rotations.clone(), 8).unwrap();
+        let (angular, quaternion) = (AngularDistance {}, QuaternionDistance {});
+        for (normal, rotation) in queries {
+            let found = normal_tree.get_nearest_neighbors(&Point::new(normal.clone()), 5, &angular);
+            check(
+                &normals,
+                &normal,
+                &|a, b| angular.distance(a, b),
+                found.iter().map(|n| n.data).collect(),
+            );
+            let found =
+                rotation_tree.get_nearest_neighbors(&Point::new(rotation.clone()), 5, &quaternion);
+            check(
+                &rotations,
+                &rotation,
+                &|a, b| quaternion.distance(a, b),
+                found.iter().map(|n| n.data).collect(),
+            );
+        }
+
+        let q = [0.5f32, 0.5, -0.5, 0.5];
+        let negated = q.map(|c| -c);
+        assert!(quaternion.distance(&q, &negated) < 1e-6);
+        // Identity against a half turn about z.
+        let half_turn = quaternion.distance(&[1., 0., 0., 0.], &[0., 0., 0., 1.]);
+        assert!((half_turn - std::f32::consts::PI).abs() < 1e-6);
+        assert!(
+            (angular.distance(&[1., 0., 0.], &[0., 1., 0.]) - std::f32::consts::FRAC_PI_2).abs()
+                < 1e-6
+        );
+    }
+}
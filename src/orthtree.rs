@@ -0,0 +1,312 @@
+use crate::error::ClosestError;
+use crate::index::SpatialIndex;
+use crate::tree::{Collector, Data, NearestCollector, Neighbor, Point, RadiusCollector};
+
+/// Cells are not split past this depth, so duplicated points can not
+/// recurse forever.
+const MAX_DEPTH: usize = 21;
+
+#[derive(Debug)]
+enum OrthtreeCell {
+    Branch(Vec<usize>),
+    Leaf((usize, usize)),
+}
+
+#[derive(Debug)]
+struct OrthtreeNode<const D: usize> {
+    min: [f32; D],
+    max: [f32; D],
+    cell: OrthtreeCell,
+}
+
+impl<const D: usize> OrthtreeNode<D> {
+    /// Squared euclidean distance from a point to the closest part of the
+    /// node's cell.
+    fn min_distance(&self, point: &Point) -> f32 {
+        (0..D)
+            .map(|i| {
+                let c = point.point(i);
+                (self.min[i] - c).max(c - self.max[i]).max(0.).powi(2)
+            })
+            .sum()
+    }
+    fn within(&self, min: &Point, max: &Point) -> bool {
+        (0..D).all(|i| min.point(i) <= self.min[i] && self.max[i] <= max.point(i))
+    }
+    fn overlaps(&self, min: &Point, max: &Point) -> bool {
+        (0..D).all(|i| min.point(i) <= self.max[i] && self.min[i] <= max.point(i))
+    }
+}
+
+/// Tree over D dimensional points, where every cell splits into 2^D equal
+/// sub cells around its center. For uniform and dense point clouds this gives
+/// shallower and better balanced trees than a KDTree. Distances are squared
+/// euclidean. Use it through the [`Quadtree`] and [`Octree`] aliases.
+#[derive(Debug)]
+pub struct Orthtree<T: Clone, const D: usize> {
+    nodes: Vec<OrthtreeNode<D>>,
+    data: Vec<Data<T>>,
+}
+
+/// Orthtree over 2 dimensional points, such as map features.
+pub type Quadtree<T> = Orthtree<T, 2>;
+
+/// Orthtree over 3 dimensional points, such as LiDAR scans or mesh vertices.
+pub type Octree<T> = Orthtree<T, 3>;
+
+/// Index of the sub cell a point falls into, one bit per axis.
+fn orthant<const D: usize>(point: &Point, center: &[f32; D]) -> usize {
+    (0..D)
+        .filter(|&i| point.point(i) >= center[i])
+        .map(|i| 1 << i)
+        .sum()
+}
+
+fn build_orthtree<T: Clone, const D: usize>(
+    data: &mut [Data<T>],
+    data_location: usize,
+    min: [f32; D],
+    max: [f32; D],
+    depth: usize,
+    leaf_size: usize,
+    nodes: &mut Vec<OrthtreeNode<D>>,
+) -> usize {
+    let node_idx = nodes.len();
+    nodes.push(OrthtreeNode {
+        min,
+        max,
+        cell: OrthtreeCell::Leaf((data_location, data_location + data.len())),
+    });
+    if data.len() <= leaf_size.max(1) || depth >= MAX_DEPTH {
+        return node_idx;
+    }
+    let center: [f32; D] = std::array::from_fn(|i| (min[i] + max[i]) / 2.);
+    data.sort_unstable_by_key(|d| orthant(&d.point, &center));
+    let mut children = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let code = orthant(&data[start].point, &center);
+        let stop = start
+            + data[start..]
+                .iter()
+                .take_while(|d| orthant(&d.point, &center) == code)
+                .count();
+        let child_min: [f32; D] = std::array::from_fn(|i| {
+            if code & (1 << i) == 0 {
+                min[i]
+            } else {
+                center[i]
+            }
+        });
+        let child_max: [f32; D] = std::array::from_fn(|i| {
+            if code & (1 << i) == 0 {
+                center[i]
+            } else {
+                max[i]
+            }
+        });
+        children.push(build_orthtree(
+            &mut data[start..stop],
+            data_location + start,
+            child_min,
+            child_max,
+            depth + 1,
+            leaf_size,
+            nodes,
+        ));
+        start = stop;
+    }
+    nodes[node_idx].cell = OrthtreeCell::Branch(children);
+    node_idx
+}
+
+impl<T: Clone, const D: usize> Orthtree<T, D> {
+    /// Build the tree, cells holding at most `leaf_size` records are not
+    /// split any further.
+    pub fn from_vec(mut data: Vec<Data<T>>, leaf_size: usize) -> Result<Self, ClosestError> {
+        if let Some(d) = data.iter().find(|d| d.point.shape() != D) {
+            return Err(ClosestError::DimensionMismatch {
+                expected: D,
+                found: d.point.shape(),
+            });
+        }
+        let mut min = [f32::INFINITY; D];
+        let mut max = [f32::NEG_INFINITY; D];
+        for d in &data {
+            for i in 0..D {
+                min[i] = min[i].min(d.point.point(i));
+                max[i] = max[i].max(d.point.point(i));
+            }
+        }
+        if data.is_empty() {
+            (min, max) = ([0.; D], [0.; D]);
+        }
+        // Use a hypercube, so sub cells stay cubes as well.
+        let side = (0..D).map(|i| max[i] - min[i]).fold(0., f32::max);
+        let max = std::array::from_fn(|i| min[i] + side);
+        let mut nodes = Vec::new();
+        build_orthtree(&mut data, 0, min, max, 0, leaf_size, &mut nodes);
+        Ok(Orthtree { nodes, data })
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    pub fn get_nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, 0, &mut collector);
+        collector.into_neighbors(&self.data)
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, 0, &mut collector);
+        collector.into_neighbors(&self.data)
+    }
+    /// Get the data of every record inside the box between `min` and `max`,
+    /// boundaries included, such as everything visible in a map viewport.
+    /// Cells entirely inside the box are taken whole, without checking their
+    /// records one by one.
+    pub fn get_within_box(&self, min: &Point, max: &Point) -> Vec<T> {
+        let mut found = Vec::new();
+        let mut stack = vec![0];
+        while let Some(node_idx) = stack.pop() {
+            let node = &self.nodes[node_idx];
+            if !node.overlaps(min, max) {
+                continue;
+            }
+            match &node.cell {
+                OrthtreeCell::Branch(children) if !node.within(min, max) => stack.extend(children),
+                _ => {
+                    let (start, stop) = self.range(node_idx);
+                    let whole = node.within(min, max);
+                    found.extend(
+                        self.data[start..stop]
+                            .iter()
+                            .filter(|d| {
+                                whole
+                                    || (0..D).all(|i| {
+                                        min.point(i) <= d.point.point(i)
+                                            && d.point.point(i) <= max.point(i)
+                                    })
+                            })
+                            .map(|d| d.data.clone()),
+                    );
+                }
+            }
+        }
+        found
+    }
+    /// Range of the records under a node, which are stored contiguously.
+    fn range(&self, node_idx: usize) -> (usize, usize) {
+        match &self.nodes[node_idx].cell {
+            OrthtreeCell::Leaf(range) => *range,
+            OrthtreeCell::Branch(children) => (
+                self.range(children[0]).0,
+                self.range(children[children.len() - 1]).1,
+            ),
+        }
+    }
+    fn search<C: Collector>(&self, point: &Point, node_idx: usize, collector: &mut C) {
+        let node = &self.nodes[node_idx];
+        if node.min_distance(point) > collector.bound() {
+            return;
+        }
+        match &node.cell {
+            OrthtreeCell::Leaf((start, stop)) => {
+                for data_pointer in *start..*stop {
+                    let distance = (0..D)
+                        .map(|i| (point.point(i) - self.data[data_pointer].point.point(i)).powi(2))
+                        .sum();
+                    collector.offer(distance, data_pointer);
+                }
+            }
+            OrthtreeCell::Branch(children) => {
+                // Visit the closest cells first, to tighten the bound early.
+                let mut children = children
+                    .iter()
+                    .map(|&c| (self.nodes[c].min_distance(point), c))
+                    .collect::<Vec<_>>();
+                children.sort_by(|a, b| a.0.total_cmp(&b.0));
+                for (_, child) in children {
+                    self.search(point, child, collector);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Clone, const D: usize> SpatialIndex<T> for Orthtree<T, D> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k)
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius)
+    }
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn octree_matches_brute_force() {
+        let coords = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                vec![(i * 0.37).sin() * 10., (i * 0.11).cos() * 10., i % 7.]
+            })
+            .collect::<Vec<_>>();
+        let data = coords
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Data::new(i, c.clone()))
+            .collect();
+        let tree = Octree::from_vec(data, 8).unwrap();
+        assert_eq!(tree.len(), 500);
+        let point = Point::new(vec![1., -2., 3.5]);
+        let mut expected = coords
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let d: f32 = c
+                    .iter()
+                    .zip(&point.coordinates)
+                    .map(|(a, b)| (a - b).powi(2))
+                    .sum();
+                (d, i)
+            })
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let nearest = tree.get_nearest_neighbors(&point, 5);
+        let found = nearest.iter().map(|n| n.data).collect::<Vec<_>>();
+        let wanted = expected[..5].iter().map(|e| e.1).collect::<Vec<_>>();
+        assert_eq!(found, wanted);
+
+        let within = tree.get_neighbors_within_radius(&point, 9.);
+        assert_eq!(within.len(), expected.iter().filter(|e| e.0 <= 9.).count());
+    }
+
+    #[test]
+    fn quadtree_window() {
+        let data = (0..400)
+            .map(|i| Data::new(i, vec![(i % 20) as f32, (i / 20) as f32]))
+            .collect();
+        let tree = Quadtree::from_vec(data, 4).unwrap();
+        let mut found = tree.get_within_box(&Point::new(vec![2.5, 3.]), &Point::new(vec![6., 4.5]));
+        found.sort();
+        assert_eq!(found, vec![63, 64, 65, 66, 83, 84, 85, 86]);
+        let nearest = tree.get_nearest_neighbors(&Point::new(vec![19.2, 19.4]), 1);
+        assert_eq!(nearest[0].data, 399);
+        assert!(Quadtree::from_vec(vec![Data::new(0, vec![1., 2., 3.])], 4).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Parallel construction and batch queries, enabled with the `rayon`
+//! feature. Every method takes a [`Threads`] argument, so the work can run on
+//! a thread pool the caller manages instead of rayon's global pool.
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{
+    apply_order, build_tree, is_leaf_size, number_records, records_dimension_of, split_at_median,
+    Data, KDTree, Neighbor, Node, NodeOrDataPointer, Point,
+};
+
+/// Below this many records subtrees are built on a single thread, as
+/// splitting the work further costs more than it saves.
+const SEQUENTIAL_BUILD_SIZE: usize = 4096;
+
+/// Where parallel work runs.
+#[derive(Debug, Clone, Copy)]
+pub enum Threads<'a> {
+    /// Rayon's global thread pool.
+    Global,
+    /// A new pool with this many threads, created for the call.
+    Count(usize),
+    /// A thread pool managed by the caller.
+    Pool(&'a ThreadPool),
+}
+
+impl Threads<'_> {
+    fn install<R: Send, F: FnOnce() -> R + Send>(self, f: F) -> Result<R, ClosestError> {
+        match self {
+            Threads::Global => Ok(f()),
+            Threads::Count(n) => {
+                let pool = ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build()
+                    .map_err(|e| ClosestError::ThreadPoolFailed(e.to_string()))?;
+                Ok(pool.install(f))
+            }
+            Threads::Pool(pool) => Ok(pool.install(f)),
+        }
+    }
+}
+
+fn build_tree_parallel<T: Clone + Sync>(
+    data: &[Data<T>],
+    order: &mut [usize],
+    data_location: usize,
+    depth: usize,
+    point_len: usize,
+    min_points: usize,
+) -> NodeOrDataPointer {
+    if order.len() < SEQUENTIAL_BUILD_SIZE || is_leaf_size(order.len(), min_points) {
+        return build_tree(data, order, data_location, depth, point_len, min_points);
+    }
+    let median = split_at_median(data, order, depth % point_len);
+    let (left_order, rest) = order.split_at_mut(median);
+    let right_order = &mut rest[1..];
+    let (left, right) = rayon::join(
+        || {
+            build_tree_parallel(
+                data,
+                left_order,
+                data_location,
+                depth + 1,
+                point_len,
+                min_points,
+            )
+        },
+        || {
+            build_tree_parallel(
+                data,
+                right_order,
+                data_location + median + 1,
+                depth + 1,
+                point_len,
+                min_points,
+            )
+        },
+    );
+    NodeOrDataPointer::Node(Node {
+        data_pointer: median + data_location,
+        left: Box::new(left),
+        right: Box::new(right),
+    })
+}
+
+impl<T: Clone + Send + Sync> KDTree<T> {
+    /// Build the tree, constructing independent subtrees in parallel.
+    pub fn from_vec_parallel(
+        mut data: Vec<Data<T>>,
+        min_points: usize,
+        threads: Threads,
+    ) -> Result<Self, ClosestError> {
+        number_records(&mut data);
+        let point_len = records_dimension_of(&data)?;
+        let mut order = (0..data.len()).collect::<Vec<_>>();
+        let root_node = threads
+            .install(|| build_tree_parallel(&data, &mut order, 0, 0, point_len, min_points))?;
+        apply_order(&mut data, &mut order);
+        Ok(KDTree::from_built(data, root_node, point_len, min_points))
+    }
+    /// Get k nearest neighbors to each of the given points, with the queries
+    /// split across threads. Like [`KDTree::get_nearest_neighbors_all`], the
+    /// queries run sorted along a space filling curve, so each thread gets
+    /// queries close to one another.
+    pub fn get_nearest_neighbors_batch<D: DistanceMetric + Sync>(
+        &self,
+        points: &[Point],
+        k: usize,
+        distance_metric: &D,
+        threads: Threads,
+    ) -> Result<Vec<Vec<Neighbor<T>>>, ClosestError> {
+        let order = self.curve_order(points);
+        let found = threads.install(|| {
+            order
+                .par_iter()
+                .map(|i| self.get_nearest_neighbors(&points[*i], k, distance_metric))
+                .collect::<Vec<_>>()
+        })?;
+        let mut results = Vec::new();
+        results.resize_with(points.len(), Vec::new);
+        for (i, neighbors) in order.into_iter().zip(found) {
+            results[i] = neighbors;
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let data = || {
+            (0..20_000)
+                .map(|i| Data::new(i, vec![((i * 31) % 977) as f32, ((i * 17) % 613) as f32]))
+                .collect::<Vec<_>>()
+        };
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let parallel = KDTree::from_vec_parallel(data(), 8, Threads::Pool(&pool)).unwrap();
+        let sequential = KDTree::from_vec(data(), 8).unwrap();
+        let points = (0..50)
+            .map(|i| Point::new(vec![i as f32 * 19., i as f32 * 11.]))
+            .collect::<Vec<_>>();
+        let metric = SquaredEuclideanDistance::default();
+        let batch = parallel
+            .get_nearest_neighbors_batch(&points, 3, &metric, Threads::Count(3))
+            .unwrap();
+        for (point, found) in points.iter().zip(batch) {
+            let expected = sequential.get_nearest_neighbors(point, 3, &metric);
+            let distances =
+                |n: &[Neighbor<usize>]| n.iter().map(|n| n.distance).collect::<Vec<_>>();
+            assert_eq!(distances(&found), distances(&expected));
+        }
+        let empty = KDTree::<usize>::from_vec_parallel(Vec::new(), 8, Threads::Global).unwrap();
+        assert!(empty.records().is_empty());
+        assert!(empty
+            .get_nearest_neighbors(&points[0], 3, &metric)
+            .is_empty());
+    }
+}
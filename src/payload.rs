@@ -0,0 +1,161 @@
+//! Data of records kept outside the tree, in a database or behind a
+//! service, and fetched only for the records a query returns.
+use std::collections::HashMap;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, NearestCollector, Neighbor, Point, RadiusCollector, RawNeighbor};
+
+/// Source of the data of records, looked up by the records' ids, their
+/// positions in the data the tree was built from or the order they were
+/// added in.
+pub trait PayloadStore {
+    type Payload: Clone;
+    /// The data of the record with the given id. Stores that can fail, such
+    /// as remote ones, report it with [`ClosestError::PayloadUnavailable`].
+    fn get(&self, id: usize) -> Result<Self::Payload, ClosestError>;
+}
+
+fn missing(id: usize) -> ClosestError {
+    ClosestError::PayloadUnavailable {
+        id,
+        reason: "no payload with this id".to_string(),
+    }
+}
+
+impl<P: Clone> PayloadStore for [P] {
+    type Payload = P;
+    fn get(&self, id: usize) -> Result<P, ClosestError> {
+        <[P]>::get(self, id).cloned().ok_or_else(|| missing(id))
+    }
+}
+
+impl<P: Clone> PayloadStore for Vec<P> {
+    type Payload = P;
+    fn get(&self, id: usize) -> Result<P, ClosestError> {
+        self.as_slice().get(id).cloned().ok_or_else(|| missing(id))
+    }
+}
+
+impl<P: Clone> PayloadStore for HashMap<usize, P> {
+    type Payload = P;
+    fn get(&self, id: usize) -> Result<P, ClosestError> {
+        HashMap::get(self, &id).cloned().ok_or_else(|| missing(id))
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// The neighbors found, with their data fetched from the store.
+    fn fetch<S: PayloadStore + ?Sized>(
+        &self,
+        found: Vec<RawNeighbor>,
+        store: &S,
+    ) -> Result<Vec<Neighbor<S::Payload>>, ClosestError> {
+        found
+            .into_iter()
+            .map(|r| {
+                Ok(Neighbor {
+                    distance: r.distance,
+                    data: store.get(self.data[r.data_pointer].id)?,
+                })
+            })
+            .collect()
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest, with their data fetched from `store` once the search is
+    /// done, so only the k records returned are looked up. Returns an error
+    /// when the point or the metric do not have the records' dimension, or
+    /// the point has coordinates that are not finite.
+    pub fn get_nearest_neighbors_from<D: DistanceMetric, S: PayloadStore + ?Sized>(
+        &self,
+        point: &Point,
+        k: usize,
+        store: &S,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<S::Payload>>, ClosestError> {
+        if !self.data.is_empty() {
+            self.check_query(point.as_slice(), distance_metric)?;
+        }
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        self.fetch(collector.into_sorted_vec(), store)
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest, with their data fetched from `store`, with the
+    /// checks of [`KDTree::get_nearest_neighbors_from`].
+    pub fn get_neighbors_within_radius_from<D: DistanceMetric, S: PayloadStore + ?Sized>(
+        &self,
+        point: &Point,
+        radius: f32,
+        store: &S,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<S::Payload>>, ClosestError> {
+        if !self.data.is_empty() {
+            self.check_query(point.as_slice(), distance_metric)?;
+        }
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        self.fetch(collector.into_sorted_vec(), store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::scattered_point;
+    use std::cell::Cell;
+
+    /// Store counting its lookups, as a remote store would be billed.
+    struct Remote {
+        names: Vec<String>,
+        lookups: Cell<usize>,
+    }
+
+    impl PayloadStore for Remote {
+        type Payload = String;
+        fn get(&self, id: usize) -> Result<String, ClosestError> {
+            self.lookups.set(self.lookups.get() + 1);
+            <[String]>::get(&self.names, id)
+                .cloned()
+                .ok_or(ClosestError::PayloadUnavailable {
+                    id,
+                    reason: "not found".to_string(),
+                })
+        }
+    }
+
+    #[test]
+    fn payloads_fetched_on_demand() {
+        let points = (0..500).map(scattered_point);
+        let tree = KDTree::from_points(points, 8).unwrap();
+        let remote = Remote {
+            names: (0..500).map(|i| format!("record {}", i)).collect(),
+            lookups: Cell::new(0),
+        };
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50.5, 20.5]);
+        let found = tree
+            .get_nearest_neighbors_from(&point, 5, &remote, &metric)
+            .unwrap();
+        assert_eq!(remote.lookups.get(), 5);
+        let ids = tree.k_nearest(&point, 5, &metric);
+        for (neighbor, (id, distance)) in found.iter().zip(ids) {
+            assert_eq!(neighbor.data, format!("record {}", id));
+            assert_eq!(neighbor.distance, distance);
+        }
+
+        let ids = (0..500).map(|i| i * 10).collect::<Vec<_>>();
+        let within = tree
+            .get_neighbors_within_radius_from(&point, 20., ids.as_slice(), &metric)
+            .unwrap();
+        assert_eq!(within.len(), tree.count_within_radius(&point, 20., &metric));
+        assert!(within.iter().all(|n| n.data % 10 == 0));
+
+        let partial = (0..10).map(|i| (i, i)).collect::<HashMap<_, _>>();
+        assert!(matches!(
+            tree.get_nearest_neighbors_from(&point, 5, &partial, &metric),
+            Err(ClosestError::PayloadUnavailable { .. })
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+//! Tree construction from positions sorted along every axis up front.
+//!
+//! Rather than sorting each node's records along its splitting axis, the
+//! positions of all records are sorted once per axis, and every split
+//! partitions those orders in place, keeping each sorted. Building takes
+//! O(k n log n) for k dimensions instead of O(n log² n), which pays off for
+//! large datasets with few dimensions.
+use crate::error::ClosestError;
+use crate::tree::{
+    apply_order, is_leaf_size, number_records, records_dimension_of, Data, KDTree, Node,
+    NodeOrDataPointer,
+};
+
+/// Which side of the current split each record falls on.
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Median,
+    Right,
+}
+
+struct PresortedBuild {
+    /// Positions of the records sorted along each axis, every node's records
+    /// are the same range of each.
+    sorted: Vec<Vec<usize>>,
+    side: Vec<Side>,
+    scratch: Vec<usize>,
+    min_points: usize,
+}
+
+impl PresortedBuild {
+    fn build(&mut self, start: usize, stop: usize, depth: usize) -> NodeOrDataPointer {
+        let len = stop - start;
+        if is_leaf_size(len, self.min_points) {
+            return NodeOrDataPointer::Data((start, stop));
+        }
+        let axis = depth % self.sorted.len();
+        let median = start + (len >> 1);
+        for (position, record) in self.sorted[axis][start..stop].iter().enumerate() {
+            self.side[*record] = match (start + position).cmp(&median) {
+                std::cmp::Ordering::Less => Side::Left,
+                std::cmp::Ordering::Equal => Side::Median,
+                std::cmp::Ordering::Greater => Side::Right,
+            };
+        }
+        let median_record = self.sorted[axis][median];
+        for (other, sorted) in self.sorted.iter_mut().enumerate() {
+            if other == axis {
+                continue;
+            }
+            // Stable partition into left, median, right, keeping each part
+            // sorted along its own axis.
+            self.scratch.clear();
+            let mut left = start;
+            for position in start..stop {
+                let record = sorted[position];
+                match self.side[record] {
+                    Side::Left => {
+                        sorted[left] = record;
+                        left += 1;
+                    }
+                    Side::Right => self.scratch.push(record),
+                    Side::Median => {}
+                }
+            }
+            sorted[median] = median_record;
+            sorted[(median + 1)..stop].copy_from_slice(&self.scratch);
+        }
+        NodeOrDataPointer::Node(Node {
+            data_pointer: median,
+            left: Box::new(self.build(start, median, depth + 1)),
+            right: Box::new(self.build(median + 1, stop, depth + 1)),
+        })
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Build the tree by sorting the records along each axis once, rather
+    /// than at every split. Queries work exactly as on a tree built with
+    /// [`KDTree::from_vec`], though records with equal coordinates may be
+    /// stored in another order.
+    pub fn from_vec_presorted(
+        mut data: Vec<Data<T>>,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        number_records(&mut data);
+        let dimension = records_dimension_of(&data)?;
+        if data.is_empty() {
+            return KDTree::from_vec(data, min_points);
+        }
+        let sorted = (0..dimension)
+            .map(|axis| {
+                let mut order = (0..data.len()).collect::<Vec<_>>();
+                // Positions break ties, so that every axis orders records
+                // the same way when their coordinates are equal.
+                order.sort_by(|a, b| {
+                    let (a_, b_) = (data[*a].point.point(axis), data[*b].point.point(axis));
+                    a_.total_cmp(&b_).then(a.cmp(b))
+                });
+                order
+            })
+            .collect();
+        let mut build = PresortedBuild {
+            sorted,
+            side: vec![Side::Left; data.len()],
+            scratch: Vec::with_capacity(data.len()),
+            min_points,
+        };
+        let root_node = build.build(0, data.len(), 0);
+        let mut order = build.sorted.swap_remove(0);
+        apply_order(&mut data, &mut order);
+        Ok(KDTree::from_built(data, root_node, dimension, min_points))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{Data, KDTree, Point};
+
+    #[test]
+    fn presorted_matches_from_vec() {
+        let data = (0..1000)
+            .map(|i: usize| {
+                Data::new(
+                    i,
+                    vec![(i * 37 % 101) as f32, (i * 13 % 47) as f32, (i % 7) as f32],
+                )
+            })
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        let presorted = KDTree::from_vec_presorted(data, 8).unwrap();
+        assert_eq!(presorted.largest_leaf, tree.largest_leaf);
+        // Every split record is the median of its range along the axis.
+        for node in presorted.nodes() {
+            let (Some((axis, split)), Some((left, right))) = (node.split(), node.children()) else {
+                continue;
+            };
+            for record in left.records() {
+                assert!(record.point().point(axis) <= split);
+            }
+            for record in right.records() {
+                assert!(record.point().point(axis) >= split);
+            }
+        }
+        let metric = SquaredEuclideanDistance::default();
+        for i in 0..50 {
+            let point = Point::new(vec![i as f32 * 2.1, i as f32 * 0.9, 3.]);
+            let distances = |tree: &KDTree<usize>| {
+                tree.get_nearest_neighbors(&point, 6, &metric)
+                    .iter()
+                    .map(|n| n.distance)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(distances(&presorted), distances(&tree));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Semi-supervised classification over the k nearest neighbor graph, which
+//! spreads the labels of a few records to the unlabeled records near them.
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, NearestCollector};
+
+impl<T: Clone> KDTree<T> {
+    /// Edges from every record to its k nearest other records, as
+    /// `(from, to, distance)`, nearest first for every record. Records are
+    /// identified by their positions in the data the tree was built from.
+    pub fn knn_graph<D: DistanceMetric>(
+        &self,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<(usize, usize, f32)> {
+        self.neighbor_positions(k, distance_metric)
+            .into_iter()
+            .enumerate()
+            .flat_map(|(from, neighbors)| {
+                neighbors
+                    .into_iter()
+                    .map(move |(to, distance)| (self.data[from].id, self.data[to].id, distance))
+            })
+            .collect()
+    }
+    /// Storage positions and distances of the k nearest other records of
+    /// every stored record.
+    pub(crate) fn neighbor_positions<D: DistanceMetric>(
+        &self,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Vec<(usize, f32)>> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(position, record)| {
+                let mut collector = NearestCollector::new(k + 1);
+                self.search(&record.point, &mut collector, distance_metric);
+                collector
+                    .into_sorted_vec()
+                    .into_iter()
+                    .filter(|n| n.data_pointer != position)
+                    .take(k)
+                    .map(|n| (n.data_pointer, n.distance))
+                    .collect()
+            })
+            .collect()
+    }
+    /// Predict a class for every record from the classes of the labeled
+    /// ones, by label propagation over the k nearest neighbor graph.
+    /// `labels` holds a class number or `None` for every record, in the
+    /// order of the data the tree was built from.
+    ///
+    /// Every unlabeled record repeatedly takes the average class
+    /// distribution of its k nearest neighbors, while labeled records keep
+    /// their class, until the distributions change by less than `1e-6` or
+    /// after `max_iterations` rounds. Returns the most likely class of every
+    /// record with its share of the distribution as the confidence, or
+    /// `None` for records no labeled record can be reached from.
+    pub fn propagate_labels<D: DistanceMetric>(
+        &self,
+        labels: &[Option<usize>],
+        k: usize,
+        max_iterations: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Option<(usize, f32)>>, ClosestError> {
+        if labels.len() != self.data.len() {
+            return Err(ClosestError::LengthMismatch {
+                expected: self.data.len(),
+                found: labels.len(),
+            });
+        }
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        // Labels by storage position rather than input position.
+        let stored = self.by_storage_order(labels);
+        let classes = labels.iter().flatten().max().map_or(0, |c| c + 1);
+        let graph = self.neighbor_positions(k, distance_metric);
+        let mut distributions = stored
+            .iter()
+            .map(|label| {
+                let mut distribution = vec![0f32; classes];
+                if let Some(class) = label {
+                    distribution[*class] = 1.;
+                }
+                distribution
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..max_iterations {
+            let mut change = 0f32;
+            let next = graph
+                .iter()
+                .zip(&stored)
+                .enumerate()
+                .map(|(position, (neighbors, label))| {
+                    if label.is_some() || neighbors.is_empty() {
+                        return distributions[position].clone();
+                    }
+                    let mut distribution = vec![0f32; classes];
+                    for (neighbor, _) in neighbors {
+                        for (d, n) in distribution.iter_mut().zip(&distributions[*neighbor]) {
+                            *d += n / neighbors.len() as f32;
+                        }
+                    }
+                    for (d, previous) in distribution.iter().zip(&distributions[position]) {
+                        change = change.max((d - previous).abs());
+                    }
+                    distribution
+                })
+                .collect();
+            distributions = next;
+            if change < 1e-6 {
+                break;
+            }
+        }
+        Ok(
+            self.by_input_order(distributions.iter().map(|distribution| {
+                let total = distribution.iter().sum::<f32>();
+                let (class, weight) = distribution
+                    .iter()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(b.1))?;
+                (total > 0.).then(|| (class, weight / total))
+            })),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn labels_spread_within_clusters() {
+        // Two separate clusters, with one labeled record in each.
+        let points = (0..200)
+            .map(|i| {
+                let offset = if i < 100 { 0. } else { 50. };
+                vec![offset + (i % 10) as f32, (i / 10 % 10) as f32]
+            })
+            .collect::<Vec<_>>();
+        let data = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Data::new(i, p.clone()))
+            .collect();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+
+        let graph = tree.knn_graph(3, &metric);
+        assert_eq!(graph.len(), 600);
+        assert!(graph.iter().all(|(from, to, _)| from != to));
+
+        let mut labels = vec![None; 200];
+        labels[0] = Some(0);
+        labels[150] = Some(1);
+        let predicted = tree.propagate_labels(&labels, 6, 200, &metric).unwrap();
+        for (i, prediction) in predicted.iter().enumerate() {
+            let (class, confidence) = prediction.unwrap();
+            assert_eq!(class, usize::from(i >= 100));
+            assert!(confidence > 0.99);
+        }
+        assert!(tree
+            .propagate_labels(&labels[1..], 6, 200, &metric)
+            .is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! Queries combining a number of neighbors, a radius, a filter on the
+//! records' data and approximate search, run with [`KDTree::run`].
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{Collector, Data, KDTree, NearestCollector, Neighbor, Point, RawNeighbor};
+
+/// Test on the records' data, for records to keep.
+type Filter<'a, T> = dyn Fn(&T) -> bool + 'a;
+
+/// What to search for around a point. By default every record is returned,
+/// ordered from nearest to farthest; setting `k`, a radius or a filter
+/// narrows the results down, and all of them can be combined.
+pub struct Query<'a, T, D> {
+    pub point: Point,
+    pub metric: D,
+    /// Keep at most this many neighbors, the nearest ones.
+    pub k: Option<usize>,
+    /// Only keep records within this distance, in the units of the metric.
+    pub radius: Option<f32>,
+    /// Only keep records whose data passes the filter.
+    pub filter: Option<Box<Filter<'a, T>>>,
+    /// Allowed relative error of approximate search. Branches are skipped
+    /// unless they could hold a record closer than the current bound divided
+    /// by `1 + eps`, so every neighbor returned is at most `1 + eps` times as
+    /// far as the true one of the same rank, in the units of the metric.
+    /// Exact at 0, the default.
+    pub eps: f32,
+}
+
+impl<'a, T, D: DistanceMetric> Query<'a, T, D> {
+    pub fn new(point: Point, metric: D) -> Self {
+        Query {
+            point,
+            metric,
+            k: None,
+            radius: None,
+            filter: None,
+            eps: 0.,
+        }
+    }
+    pub fn k(mut self, k: usize) -> Self {
+        self.k = Some(k);
+        self
+    }
+    pub fn radius(mut self, radius: f32) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+    pub fn filter<F: Fn(&T) -> bool + 'a>(mut self, filter: F) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+    pub fn eps(mut self, eps: f32) -> Self {
+        self.eps = eps;
+        self
+    }
+}
+
+/// Collects the records a [`Query`] asks for.
+struct QueryCollector<'a, 'q, T: Clone> {
+    nearest: Option<NearestCollector>,
+    found: Vec<RawNeighbor>,
+    radius: f32,
+    filter: Option<&'q Filter<'a, T>>,
+    shrink: f32,
+    data: &'q [Data<T>],
+}
+
+impl<T: Clone> Collector for QueryCollector<'_, '_, T> {
+    fn bound(&self) -> f32 {
+        let bound = match &self.nearest {
+            Some(nearest) => nearest.bound().min(self.radius),
+            None => self.radius,
+        };
+        bound / self.shrink
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        if distance > self.radius {
+            return;
+        }
+        if let Some(filter) = self.filter {
+            if !filter(&self.data[data_pointer].data) {
+                return;
+            }
+        }
+        match &mut self.nearest {
+            Some(nearest) => nearest.offer(distance, data_pointer),
+            None => self.found.push(RawNeighbor::new(distance, data_pointer)),
+        }
+    }
+}
+
+/// Inverse distance weights of records found, summing to one, or only
+/// weighting the records at no distance if there are any.
+fn inverse_distance_weights(found: &[RawNeighbor]) -> Option<Vec<f64>> {
+    if found.is_empty() {
+        return None;
+    }
+    let weights = if found.iter().any(|r| r.distance == 0.) {
+        found
+            .iter()
+            .map(|r| if r.distance == 0. { 1. } else { 0. })
+            .collect::<Vec<f64>>()
+    } else {
+        found.iter().map(|r| 1. / r.distance as f64).collect()
+    };
+    let total = weights.iter().sum::<f64>();
+    Some(weights.into_iter().map(|w| w / total).collect())
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Run a query, returning the neighbors found ordered from nearest to
+    /// farthest.
+    pub fn run<D: DistanceMetric>(&self, query: &Query<'_, T, D>) -> Vec<Neighbor<T>> {
+        self.run_raw(query)
+            .into_iter()
+            .map(|r| r.into_neighbor(&self.data))
+            .collect()
+    }
+    /// Like [`KDTree::run`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_run<D: DistanceMetric>(
+        &self,
+        query: &Query<'_, T, D>,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if query.k == Some(0) {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(query.point.as_slice(), &query.metric)?;
+        Ok(self.run(query))
+    }
+    fn run_raw<D: DistanceMetric>(&self, query: &Query<'_, T, D>) -> Vec<RawNeighbor> {
+        let mut collector = QueryCollector {
+            nearest: query.k.map(NearestCollector::new),
+            found: Vec::new(),
+            radius: query.radius.unwrap_or(f32::INFINITY),
+            filter: query.filter.as_deref(),
+            shrink: 1. + query.eps.max(0.),
+            data: &self.data,
+        };
+        self.search(&query.point, &mut collector, &query.metric);
+        match collector.nearest {
+            Some(nearest) => nearest.into_sorted_vec(),
+            None => {
+                let mut found = collector.found;
+                found.sort();
+                found
+            }
+        }
+    }
+    /// Distance weighted mean of the points of the records a query finds,
+    /// `None` if it finds none. Records are weighted by the inverse of their
+    /// distance in the units of the metric, so by the inverse squared
+    /// distance with [`SquaredEuclideanDistance`], and records at no
+    /// distance at all are used alone.
+    ///
+    /// [`SquaredEuclideanDistance`]: crate::SquaredEuclideanDistance
+    pub fn weighted_centroid<D: DistanceMetric>(&self, query: &Query<'_, T, D>) -> Option<Point> {
+        let found = self.run_raw(query);
+        let weights = inverse_distance_weights(&found)?;
+        let mut centroid = vec![0f64; self.records_dimension()];
+        for (r, weight) in found.iter().zip(&weights) {
+            for (c, x) in centroid
+                .iter_mut()
+                .zip(self.data[r.data_pointer].point.as_slice())
+            {
+                *c += weight * *x as f64;
+            }
+        }
+        Some(Point::new(centroid.into_iter().map(|c| c as f32).collect()))
+    }
+    /// Like [`KDTree::weighted_centroid`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_weighted_centroid<D: DistanceMetric>(
+        &self,
+        query: &Query<'_, T, D>,
+    ) -> Result<Option<Point>, ClosestError> {
+        if query.k == Some(0) {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(query.point.as_slice(), &query.metric)?;
+        Ok(self.weighted_centroid(query))
+    }
+    /// Distance weighted mean of `value` over the records a query finds,
+    /// weighted like [`KDTree::weighted_centroid`], `None` if it finds
+    /// none.
+    pub fn local_average<D: DistanceMetric, F: Fn(&T) -> f64>(
+        &self,
+        query: &Query<'_, T, D>,
+        value: F,
+    ) -> Option<f64> {
+        let found = self.run_raw(query);
+        let weights = inverse_distance_weights(&found)?;
+        Some(
+            found
+                .iter()
+                .zip(&weights)
+                .map(|(r, weight)| weight * value(&self.data[r.data_pointer].data))
+                .sum(),
+        )
+    }
+    /// Run every query against the tree as it is now, results in the same
+    /// order as the queries.
+    pub fn run_all<D: DistanceMetric>(&self, queries: &[Query<'_, T, D>]) -> Vec<Vec<Neighbor<T>>> {
+        queries.iter().map(|query| self.run(query)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::scattered_point;
+
+    #[test]
+    fn combined_options() {
+        let data = (0..400)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        let point = Point::from([50., 20.]);
+        let metric = SquaredEuclideanDistance::default();
+        let brute_force = |radius: f32, keep: &dyn Fn(&usize) -> bool, k: usize| {
+            let mut found = data
+                .iter()
+                .map(|d| {
+                    (
+                        metric.distance(point.as_slice(), d.point.as_slice()),
+                        d.data,
+                    )
+                })
+                .filter(|(distance, i)| *distance <= radius && keep(i))
+                .collect::<Vec<_>>();
+            found.sort_by(|a, b| a.0.total_cmp(&b.0));
+            found.truncate(k);
+            found.into_iter().map(|(d, _)| d).collect::<Vec<_>>()
+        };
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+
+        let query = Query::new(point.clone(), SquaredEuclideanDistance::default()).k(5);
+        assert_eq!(
+            distances(tree.run(&query)),
+            distances(tree.get_nearest_neighbors(&point, 5, &metric))
+        );
+        let query = Query::new(point.clone(), SquaredEuclideanDistance::default())
+            .radius(100.)
+            .filter(|i: &usize| i.is_multiple_of(2));
+        assert_eq!(
+            distances(tree.run(&query)),
+            brute_force(100., &|i| i.is_multiple_of(2), usize::MAX)
+        );
+        let query = Query::new(point.clone(), SquaredEuclideanDistance::default())
+            .k(3)
+            .radius(30.)
+            .filter(|i: &usize| i.is_multiple_of(3));
+        assert_eq!(
+            distances(tree.run(&query)),
+            brute_force(30., &|i| i.is_multiple_of(3), 3)
+        );
+        assert_eq!(
+            tree.run(&Query::new(
+                point.clone(),
+                SquaredEuclideanDistance::default()
+            ))
+            .len(),
+            400
+        );
+
+        let exact = brute_force(f32::INFINITY, &|_| true, 10);
+        let query = Query::new(point, SquaredEuclideanDistance::default())
+            .k(10)
+            .eps(0.5);
+        let found = tree.run(&query);
+        assert_eq!(found.len(), 10);
+        for (n, exact) in found.iter().zip(exact) {
+            assert!(n.distance <= exact * 1.5);
+        }
+    }
+
+    #[test]
+    fn weighted_averages() {
+        let data = vec![
+            Data::new(1., vec![0., 0.]),
+            Data::new(2., vec![2., 0.]),
+            Data::new(10., vec![10., 10.]),
+        ];
+        let tree = KDTree::from_vec(data, 1).unwrap();
+        let query = Query::new(Point::from([1.5, 0.]), SquaredEuclideanDistance::default()).k(2);
+        // Squared distances 2.25 and 0.25, so weights 0.1 and 0.9.
+        let average = tree.local_average(&query, |v: &f64| *v).unwrap();
+        assert!((average - 1.9).abs() < 1e-6);
+        let centroid = tree.weighted_centroid(&query).unwrap();
+        assert!((centroid.as_slice()[0] - 1.8).abs() < 1e-6);
+        assert_eq!(centroid.as_slice()[1], 0.);
+        let exact =
+            Query::new(Point::from([2., 0.]), SquaredEuclideanDistance::default()).radius(200.);
+        assert_eq!(tree.local_average(&exact, |v: &f64| *v), Some(2.));
+        let empty =
+            Query::new(Point::from([5., 5.]), SquaredEuclideanDistance::default()).radius(1.);
+        assert!(tree.weighted_centroid(&empty).is_none());
+    }
+}
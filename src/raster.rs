@@ -0,0 +1,139 @@
+//! Distance transforms of scattered points onto regular grids, as used for
+//! coverage maps and heatmaps.
+//!
+//! Cells are visited in row major order, and each search starts from the
+//! record nearest to the previous cell, which is almost always close to the
+//! nearest record of the next one, so the search prunes from the start.
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{Collector, KDTree, NearestCollector, Point};
+
+/// A regular grid of cells, the center of cell `(i, j, ...)` being at
+/// `origin + (i * spacing[0], j * spacing[1], ...)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub origin: Vec<f32>,
+    pub spacing: Vec<f32>,
+    /// Number of cells along each axis.
+    pub shape: Vec<usize>,
+}
+
+impl Grid {
+    pub fn new(origin: Vec<f32>, spacing: Vec<f32>, shape: Vec<usize>) -> Self {
+        Grid {
+            origin,
+            spacing,
+            shape,
+        }
+    }
+    /// Number of cells in the grid.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Center of the cell at a row major position.
+    fn center(&self, mut cell: usize, center: &mut [f32]) {
+        for axis in (0..self.shape.len()).rev() {
+            let i = cell % self.shape[axis];
+            cell /= self.shape[axis];
+            center[axis] = self.origin[axis] + i as f32 * self.spacing[axis];
+        }
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Distance from the center of every cell of `grid` to the nearest
+    /// record, in row major order, the last axis varying fastest. Distances
+    /// are in the units of the metric, squared for
+    /// [`SquaredEuclideanDistance`].
+    ///
+    /// [`SquaredEuclideanDistance`]: crate::SquaredEuclideanDistance
+    pub fn distance_transform<D: DistanceMetric>(
+        &self,
+        grid: &Grid,
+        distance_metric: &D,
+    ) -> Result<Vec<f32>, ClosestError> {
+        if self.data.is_empty() {
+            return Err(ClosestError::EmptyTree);
+        }
+        for axes in [grid.origin.len(), grid.spacing.len(), grid.shape.len()] {
+            if axes != self.dimension {
+                return Err(ClosestError::DimensionMismatch {
+                    expected: self.dimension,
+                    found: axes,
+                });
+            }
+        }
+        let mut center = Point::new(vec![0.; self.dimension]);
+        let mut previous: Option<usize> = None;
+        Ok((0..grid.len())
+            .map(|cell| {
+                grid.center(cell, &mut center.coordinates);
+                let mut collector = NearestCollector::new(1);
+                if let Some(previous) = previous {
+                    let distance = distance_metric
+                        .distance(center.as_slice(), self.data[previous].point.as_slice());
+                    collector.offer(distance, previous);
+                }
+                self.search(&center, &mut collector, distance_metric);
+                let nearest = collector.into_sorted_vec().remove(0);
+                previous = Some(nearest.data_pointer);
+                nearest.distance
+            })
+            .collect())
+    }
+    /// [`KDTree::distance_transform`] as an array shaped like the grid.
+    #[cfg(feature = "ndarray")]
+    pub fn distance_transform_array<D: DistanceMetric>(
+        &self,
+        grid: &Grid,
+        distance_metric: &D,
+    ) -> Result<ndarray::ArrayD<f32>, ClosestError> {
+        let distances = self.distance_transform(grid, distance_metric)?;
+        Ok(
+            ndarray::ArrayD::from_shape_vec(grid.shape.clone(), distances)
+                .expect("one distance per cell"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn grid_distances() {
+        let points = [[0.5, 0.5], [7.2, 3.1], [3.3, 8.8], [9.9, 9.9]];
+        let data = points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Data::new(i, p.to_vec()))
+            .collect();
+        let tree = KDTree::from_vec(data, 1).unwrap();
+        let grid = Grid::new(vec![0., 0.], vec![0.5, 1.], vec![21, 11]);
+        let metric = SquaredEuclideanDistance::default();
+        let distances = tree.distance_transform(&grid, &metric).unwrap();
+        assert_eq!(distances.len(), 231);
+        for (cell, distance) in distances.iter().enumerate() {
+            let (x, y) = ((cell / 11) as f32 * 0.5, (cell % 11) as f32);
+            let expected = points
+                .iter()
+                .map(|p| (p[0] - x).powi(2) + (p[1] - y).powi(2))
+                .fold(f32::INFINITY, f32::min);
+            assert_eq!(*distance, expected);
+        }
+        assert!(tree
+            .distance_transform(&Grid::new(vec![0.], vec![1.], vec![3]), &metric)
+            .is_err());
+        #[cfg(feature = "ndarray")]
+        {
+            let array = tree.distance_transform_array(&grid, &metric).unwrap();
+            assert_eq!(array.shape(), &[21, 11]);
+            assert_eq!(array[[3, 4]], distances[3 * 11 + 4]);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::tree::{number_records, Data, KDTree};
+
+/// When a tree that has been changed since it was built gets rebuilt.
+///
+/// Rebuilds only ever happen within a call on the tree. With `automatic`
+/// set, the call that passes any limit rebuilds the tree before it returns.
+/// Without it the tree is never rebuilt on its own, [`KDTree::needs_reindex`]
+/// tells when a limit is passed and [`KDTree::reindex`] rebuilds it, which a
+/// service can do to a clone of the tree on another thread, and swap it in
+/// when done.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReindexPolicy {
+    /// Rebuild once the records added since the last build, which every
+    /// query scans, are more than this fraction of the indexed records.
+    /// Fewer than `min_points` added records never cause a rebuild.
+    pub max_pending_fraction: f32,
+    /// Rebuild once removed records, still stored but skipped by queries,
+    /// are more than this fraction of all stored records.
+    pub max_tombstone_fraction: f32,
+    /// Rebuild once the largest leaf, counting the records placed into it
+    /// with [`KDTree::insert`] since the last build, holds more than this
+    /// many times `min_points` records.
+    pub max_imbalance: f32,
+    /// Rebuild within the call that passes a limit.
+    pub automatic: bool,
+}
+
+impl Default for ReindexPolicy {
+    fn default() -> Self {
+        ReindexPolicy {
+            max_pending_fraction: 0.25,
+            max_tombstone_fraction: 0.25,
+            max_imbalance: 4.,
+            automatic: true,
+        }
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    pub fn reindex_policy(&self) -> &ReindexPolicy {
+        &self.policy
+    }
+    pub fn set_reindex_policy(&mut self, policy: ReindexPolicy) {
+        self.policy = policy;
+        self.reindex_if_needed();
+    }
+    /// Whether the tree has changed past any limit of its policy.
+    pub fn needs_reindex(&self) -> bool {
+        let pending = self.unindexed().len();
+        let stored = self.data.len().max(1) as f32;
+        (pending > self.min_points
+            && pending as f32 > self.indexed as f32 * self.policy.max_pending_fraction)
+            || self.tombstones as f32 > stored * self.policy.max_tombstone_fraction
+            || self.largest_leaf as f32 > self.min_points.max(1) as f32 * self.policy.max_imbalance
+    }
+    /// Rebuild the tree over every stored record.
+    pub fn reindex(&mut self) {
+        self.rebuild();
+    }
+    /// Replace every record with `data` and rebuild, reusing the memory of
+    /// the record store, of the buffer used while building and of the
+    /// lookup of records by id. Only the new records' own data and the
+    /// tree's nodes, which are built anew, are allocated again.
+    /// Records are identified by their position in `data` afterwards, as if
+    /// the tree was built from it, and records without the dimension of the
+    /// first one are skipped, as with [`Extend`].
+    pub fn rebuild_from<I: IntoIterator<Item = Data<T>>>(&mut self, data: I) {
+        self.data.clear();
+        let mut data = data.into_iter().peekable();
+        let dimension = data.peek().map_or(0, |d| d.point.shape());
+        self.data
+            .extend(data.filter(|d| d.point.shape() == dimension));
+        number_records(&mut self.data);
+        self.next_id = self.data.len();
+        self.tombstones = 0;
+        self.removed.clear();
+        self.dimension = 0;
+        self.rebuild();
+    }
+    /// Rebuild if the policy is automatic and any of its limits is passed.
+    pub(crate) fn reindex_if_needed(&mut self) {
+        if self.policy.automatic && self.needs_reindex() {
+            self.rebuild();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_limit() {
+        let point = |i: usize| vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32];
+        let mut tree =
+            KDTree::from_vec((0..100).map(|i| Data::new(i, point(i))).collect(), 4).unwrap();
+        tree.set_reindex_policy(ReindexPolicy {
+            automatic: false,
+            ..Default::default()
+        });
+        tree.extend((100..140).map(|i| Data::new(i, point(i))));
+        assert!(tree.needs_reindex());
+        assert_eq!(tree.indexed, 100);
+        tree.reindex();
+        assert!(!tree.needs_reindex());
+
+        tree.set_reindex_policy(ReindexPolicy {
+            max_pending_fraction: 0.5,
+            ..Default::default()
+        });
+        tree.extend((140..200).map(|i| Data::new(i, point(i))));
+        assert_eq!(tree.indexed, 140);
+        tree.extend((200..250).map(|i| Data::new(i, point(i))));
+        assert_eq!(tree.indexed, 250);
+
+        let capacity = tree.records().len();
+        tree.rebuild_from((0..200).map(|i| Data::new(i, point(i + 1))));
+        assert_eq!(tree.indexed, 200);
+        assert!(tree.data.capacity() >= capacity);
+        assert_eq!(
+            tree,
+            KDTree::from_vec((0..200).map(|i| Data::new(i, point(i + 1))).collect(), 4).unwrap()
+        );
+    }
+
+    #[test]
+    fn imbalance_limit() {
+        let point = |i: usize| vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32];
+        let mut tree =
+            KDTree::from_vec((0..100).map(|i| Data::new(i, point(i))).collect(), 4).unwrap();
+        tree.set_reindex_policy(ReindexPolicy {
+            automatic: false,
+            ..Default::default()
+        });
+        // Every record inserted lands in the same leaf.
+        for i in 0..20 {
+            tree.insert(Data::new(i, vec![20., 20. + i as f32]))
+                .unwrap();
+        }
+        assert!(tree.largest_leaf > 16);
+        assert!(tree.needs_reindex());
+        tree.reindex();
+        assert!(!tree.needs_reindex());
+
+        tree.set_reindex_policy(ReindexPolicy::default());
+        for i in 0..20 {
+            tree.insert(Data::new(i, vec![30., 20. + i as f32]))
+                .unwrap();
+            assert!(tree.largest_leaf <= 16);
+        }
+        assert!(tree.inserted < 20);
+    }
+}
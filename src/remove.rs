@@ -0,0 +1,248 @@
+use crate::bounds::BoundingBox;
+use crate::distance::DistanceMetric;
+use crate::tree::{BoxCollector, Data, KDTree, Point, RadiusCollector, Unpruned};
+
+impl<T: Clone> KDTree<T> {
+    /// Remove every record within `radius` of a given point, and return them
+    /// ordered from nearest to farthest. The records are found with the tree,
+    /// and it is rebuilt once over the remaining records.
+    pub fn remove_within_radius<D: DistanceMetric>(
+        &mut self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Data<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        let found = collector.into_sorted_vec();
+        self.remove_records(found.iter().map(|r| r.data_pointer).collect())
+    }
+    /// Remove every record inside the box from `min` to `max`, boundaries
+    /// included, and return them in the order the tree stored them.
+    pub fn remove_in_box(&mut self, min: &Point, max: &Point) -> Vec<Data<T>> {
+        let bounds = BoundingBox {
+            min: min.clone(),
+            max: max.clone(),
+        };
+        let mut collector = BoxCollector::new(&bounds, &self.data);
+        self.search(min, &mut collector, &Unpruned {});
+        let mut found = collector.found;
+        found.sort_unstable();
+        self.remove_records(found)
+    }
+    /// Remove the record with the given id, its position in the data the
+    /// tree was built from or the order it was added in, and return it. The
+    /// tree is not rebuilt, the record stays stored as a tombstone, as with
+    /// [`KDTree::delete`].
+    pub fn remove(&mut self, id: usize) -> Option<Data<T>> {
+        let data_pointer = self.position_of(id)?;
+        let record = self.data[data_pointer].clone();
+        self.mark_removed(data_pointer);
+        self.reindex_if_needed();
+        Some(record)
+    }
+    /// Remove every record whose data matches `predicate`, and return them in
+    /// the order the tree stored them. The tree is rebuilt once over the
+    /// remaining records.
+    pub fn remove_by<F: FnMut(&T) -> bool>(&mut self, mut predicate: F) -> Vec<Data<T>> {
+        let found = (0..self.data.len())
+            .filter(|i| !self.is_removed(*i) && predicate(&self.data[*i].data))
+            .collect();
+        self.remove_records(found)
+    }
+    /// Keep only the records for which `keep` returns true, given each
+    /// record's data and point, and return how many were dropped. The tree
+    /// is rebuilt once over the remaining records.
+    pub fn retain<F: FnMut(&T, &Point) -> bool>(&mut self, mut keep: F) -> usize {
+        let found = (0..self.data.len())
+            .filter(|i| {
+                let record = &self.data[*i];
+                !self.is_removed(*i) && !keep(&record.data, &record.point)
+            })
+            .collect();
+        self.remove_records(found).len()
+    }
+    /// Mark the record with the given id as removed, without rebuilding, and
+    /// return whether it was found. Queries skip it from then on, while it
+    /// stays stored as a tombstone until the tree is compacted, by a rebuild
+    /// once tombstones pass the fraction of records set by the tree's
+    /// [`ReindexPolicy`], or by [`KDTree::compact`].
+    ///
+    /// [`ReindexPolicy`]: crate::ReindexPolicy
+    pub fn delete(&mut self, id: usize) -> bool {
+        let Some(data_pointer) = self.position_of(id) else {
+            return false;
+        };
+        self.mark_removed(data_pointer);
+        self.reindex_if_needed();
+        true
+    }
+    /// Mark every record whose data matches `predicate` as removed, like
+    /// [`KDTree::delete`], and return how many were.
+    pub fn delete_by<F: FnMut(&T) -> bool>(&mut self, mut predicate: F) -> usize {
+        let found = (0..self.data.len())
+            .filter(|i| !self.is_removed(*i) && predicate(&self.data[*i].data))
+            .collect::<Vec<_>>();
+        for data_pointer in &found {
+            self.mark_removed(*data_pointer);
+        }
+        self.reindex_if_needed();
+        found.len()
+    }
+    /// Number of removed records still stored, which queries skip. They are
+    /// still part of [`KDTree::records`] and whole tree computations, such
+    /// as the minimum spanning tree, until the tree is compacted.
+    pub fn tombstones(&self) -> usize {
+        self.tombstones
+    }
+    /// Drop every removed record still stored, rebuilding the tree.
+    pub fn compact(&mut self) {
+        if self.tombstones > 0 {
+            self.rebuild();
+        }
+    }
+    /// Storage position of the record with the given id, unless removed.
+    pub(crate) fn position_of(&self, id: usize) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+    pub(crate) fn mark_removed(&mut self, data_pointer: usize) {
+        if self.removed.is_empty() {
+            self.removed = vec![false; self.data.len()];
+        }
+        self.removed[data_pointer] = true;
+        self.tombstones += 1;
+        self.positions.remove(&self.data[data_pointer].id);
+    }
+    /// Take the records at the given positions out of the store, returned in
+    /// the same order, and rebuild the tree if any were removed.
+    fn remove_records(&mut self, data_pointers: Vec<usize>) -> Vec<Data<T>> {
+        if data_pointers.is_empty() {
+            return Vec::new();
+        }
+        let mut slots = self.data.drain(..).map(Some).collect::<Vec<_>>();
+        let removed = data_pointers
+            .into_iter()
+            .filter_map(|data_pointer| slots[data_pointer].take())
+            .collect();
+        // Tombstones go with them, and are not returned.
+        let tombstones = std::mem::take(&mut self.removed);
+        self.data = slots
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !tombstones.get(*i).copied().unwrap_or(false))
+            .filter_map(|(_, slot)| slot)
+            .collect();
+        self.tombstones = 0;
+        self.rebuild();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::index::SpatialIndex;
+    use crate::tree::grid_point;
+
+    #[test]
+    fn remove_regions() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let removed = tree.remove_within_radius(&Point::new(vec![0., 0.]), 1., &metric);
+        let mut ids = removed.iter().map(|d| *d.data()).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![0, 1, 10]);
+        let removed = tree.remove_in_box(&Point::new(vec![4.5, 4.5]), &Point::new(vec![6., 9.]));
+        assert_eq!(removed.len(), 10);
+        assert_eq!(tree.records().len(), 87);
+        let nearest = tree.get_nearest_neighbors(&Point::new(vec![5., 5.]), 1, &metric);
+        assert_eq!(nearest[0].distance, 1.);
+        // Positions in the original data are kept.
+        assert_eq!(
+            tree.k_nearest_indices(&Point::new(vec![9., 9.]), 1, &metric),
+            vec![99]
+        );
+    }
+
+    #[test]
+    fn remove_records_by_id_and_data() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        assert_eq!(tree.remove(44).map(|d| d.data), Some(44));
+        assert!(tree.remove(44).is_none());
+        assert_eq!(tree.tombstones(), 1);
+        let removed = tree.remove_by(|i| i % 2 == 1);
+        assert_eq!(removed.len(), 50);
+        assert_eq!(tree.records().len(), 49);
+        let nearest = tree.get_nearest_neighbors(&Point::new(vec![4., 4.]), 100, &metric);
+        assert_eq!(nearest.len(), 49);
+        assert!(nearest.iter().all(|n| n.data % 2 == 0 && n.data != 44));
+        assert_eq!(nearest[0].distance, 1.);
+    }
+
+    #[test]
+    fn retain_records() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        tree.delete(0);
+        assert_eq!(
+            tree.retain(|i, point| i % 3 != 0 && point.point(0) < 5.),
+            66
+        );
+        assert_eq!(tree.tombstones(), 0);
+        assert_eq!(tree.records().len(), 33);
+        let metric = SquaredEuclideanDistance::default();
+        let nearest = tree.get_nearest_neighbors(&Point::new(vec![9., 9.]), 1, &metric);
+        assert_eq!((nearest[0].data, nearest[0].distance), (94, 25.));
+        assert_eq!(tree.retain(|_, _| true), 0);
+    }
+
+    #[test]
+    fn tombstones() {
+        let data = (0..100)
+            .map(|i| Data::new(i, grid_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        assert!(tree.delete(44));
+        assert!(!tree.delete(44));
+        assert_eq!(tree.delete_by(|i| i / 10 == 4), 9);
+        assert_eq!(tree.tombstones(), 10);
+        assert_eq!(tree.records().len(), 100);
+        assert_eq!(SpatialIndex::len(&tree), 90);
+        let point = Point::new(vec![4., 4.]);
+        let nearest = tree.get_nearest_neighbors(&point, 100, &metric);
+        assert_eq!(nearest.len(), 90);
+        assert!(nearest.iter().all(|n| n.data / 10 != 4));
+        assert_eq!(nearest[0].distance, 1.);
+        let mut out = [(0., 0); 90];
+        assert_eq!(tree.k_nearest_into(point.as_slice(), &metric, &mut out), 90);
+
+        // Added records are found, removed ones are not, until compaction.
+        tree.extend([Data::new(100, vec![4., 4.])]);
+        for id in [0, 39, 100] {
+            let position = tree.position_of(id);
+            assert_eq!(position.map(|p| tree.records()[p].id), Some(id));
+        }
+        assert_eq!(tree.position_of(44), None);
+        assert_eq!(tree.get_nearest_neighbors(&point, 1, &metric)[0].data, 100);
+        assert!(tree.remove(44).is_none());
+        tree.compact();
+        assert_eq!(tree.tombstones(), 0);
+        assert_eq!(tree.records().len(), 91);
+
+        // Past the policy's fraction of tombstones the tree compacts itself.
+        assert_eq!(tree.delete_by(|i| *i < 30), 30);
+        assert_eq!(tree.tombstones(), 0);
+        assert_eq!(tree.records().len(), 61);
+    }
+}
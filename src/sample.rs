@@ -0,0 +1,191 @@
+//! Randomized, distance aware selection of neighbors, for stochastic
+//! algorithms that need more than the strict k nearest, such as
+//! approximating a softmax over neighbors.
+//!
+//! Records are drawn without replacement with weighted reservoir sampling
+//! (Efraimidis and Spirakis), which gives every record the key `u^(1 / w)`
+//! for a uniform `u` and its weight `w`, and keeps the records with the
+//! largest keys. Keys are compared as their logarithms, `ln(u) / w`, so
+//! small weights do not underflow.
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, Neighbor, Point, RadiusCollector};
+
+/// Source of the random numbers a sampling query uses, given per call so
+/// concurrent queries do not share one generator, and a query repeated with
+/// a generator in the same state draws the same records. With the `rand`
+/// feature, every `rand` generator is a source.
+pub trait RandomSource {
+    /// Uniformly distributed bits.
+    fn next_u64(&mut self) -> u64;
+    /// Uniform value in `(0, 1]`.
+    fn next_unit(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Small generator seeded with a single number (splitmix64), for
+/// reproducible draws without other dependencies.
+#[derive(Debug, Clone)]
+pub struct SeededRandom(u64);
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom(seed)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<R: rand_core::RngCore + ?Sized> RandomSource for R {
+    fn next_u64(&mut self) -> u64 {
+        rand_core::RngCore::next_u64(self)
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Draw `m` distinct records within `radius` of `point`, each with
+    /// probability proportional to `kernel` of its distance, and return
+    /// them ordered from nearest to farthest. For a softmax over neighbors
+    /// with temperature `t`, use `|d| (-d / t).exp()`. Records the kernel
+    /// gives no positive weight are never drawn, so fewer than `m` are
+    /// returned when fewer have a positive weight. Random numbers are taken
+    /// from `random`, such as a [`SeededRandom`].
+    pub fn sample_neighbors<D: DistanceMetric, K: Fn(f32) -> f32, R: RandomSource + ?Sized>(
+        &self,
+        point: &Point,
+        m: usize,
+        radius: f32,
+        kernel: K,
+        random: &mut R,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        // Records are keyed in storage order, so a draw does not depend on
+        // the order the search found them in.
+        let mut found = collector.into_sorted_vec();
+        found.sort_unstable_by_key(|r| r.data_pointer);
+        let mut keyed = found
+            .into_iter()
+            .filter_map(|r| {
+                let weight = kernel(r.distance) as f64;
+                let key = random.next_unit().ln() / weight;
+                (weight > 0.).then_some((key, r))
+            })
+            .collect::<Vec<_>>();
+        if keyed.len() > m {
+            keyed.select_nth_unstable_by(m, |a, b| b.0.total_cmp(&a.0));
+            keyed.truncate(m);
+        }
+        let mut drawn = keyed.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+        drawn.sort();
+        drawn
+            .into_iter()
+            .map(|r| r.into_neighbor(&self.data))
+            .collect()
+    }
+    /// Like [`KDTree::sample_neighbors`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_sample_neighbors<D: DistanceMetric, K: Fn(f32) -> f32, R: RandomSource + ?Sized>(
+        &self,
+        point: &Point,
+        m: usize,
+        radius: f32,
+        kernel: K,
+        random: &mut R,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if m == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.sample_neighbors(point, m, radius, kernel, random, distance_metric))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn kernel_weighted_draws() {
+        let data = (0..10)
+            .map(|i| Data::new(i, vec![i as f32, 0.]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 2).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([0., 0.]);
+        let draw = |m, seed| {
+            let mut random = SeededRandom::new(seed);
+            tree.sample_neighbors(&point, m, 30., |d| (-d / 4.).exp(), &mut random, &metric)
+                .iter()
+                .map(|n| n.data)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draw(3, 7), draw(3, 7));
+        // Only records 0 to 5 are within the radius.
+        assert_eq!(draw(10, 7), vec![0, 1, 2, 3, 4, 5]);
+        assert!(draw(0, 7).is_empty());
+
+        // Single draws follow the kernel's weights.
+        let mut counts = [0usize; 6];
+        for seed in 0..4000 {
+            counts[draw(1, seed)[0]] += 1;
+        }
+        let total = (0..6).map(|i| (-((i * i) as f32) / 4.).exp()).sum::<f32>();
+        for (i, count) in counts.iter().enumerate() {
+            let expected = 4000. * (-((i * i) as f32) / 4.).exp() / total;
+            assert!((*count as f32 - expected).abs() < 4. * expected.sqrt() + 5.);
+        }
+
+        // Records without weight are never drawn.
+        let mut random = SeededRandom::new(1);
+        let weight = |d: f32| (d < 2.) as u8 as f32;
+        let drawn = tree.sample_neighbors(&point, 10, 30., weight, &mut random, &metric);
+        assert_eq!(drawn.len(), 2);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn rand_generators() {
+        /// A `rand` generator repeating the bits of a seeded generator.
+        struct Repeat(SeededRandom);
+        impl rand_core::RngCore for Repeat {
+            fn next_u32(&mut self) -> u32 {
+                RandomSource::next_u64(&mut self.0) as u32
+            }
+            fn next_u64(&mut self) -> u64 {
+                RandomSource::next_u64(&mut self.0)
+            }
+            fn fill_bytes(&mut self, dst: &mut [u8]) {
+                rand_core::impls::fill_bytes_via_next(self, dst)
+            }
+        }
+        let data = (0..50)
+            .map(|i| Data::new(i, vec![i as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 2).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([0.]);
+        let kernel = |d: f32| 1. / (1. + d);
+        let seeded =
+            tree.sample_neighbors(&point, 5, 100., kernel, &mut SeededRandom::new(3), &metric);
+        let mut generator = Repeat(SeededRandom::new(3));
+        let generic: &mut dyn rand_core::RngCore = &mut generator;
+        let drawn = tree.sample_neighbors(&point, 5, 100., kernel, generic, &metric);
+        let data = |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(data(seeded), data(drawn));
+    }
+}
@@ -0,0 +1,201 @@
+//! Queries for the records nearest to a line segment or ray, such as the
+//! closest points of interest along a route.
+use crate::bounds::BoundingBox;
+use crate::tree::{Collector, Data, KDTree, NearestCollector, Neighbor, Point, Unpruned};
+
+/// The points `start + t * direction`, for `t` from 0 to `t_max`.
+struct Segment {
+    start: Vec<f32>,
+    direction: Vec<f32>,
+    t_max: f32,
+}
+
+impl Segment {
+    fn at(&self, t: f32) -> impl Iterator<Item = f32> + '_ {
+        self.start
+            .iter()
+            .zip(&self.direction)
+            .map(move |(s, d)| s + t * d)
+    }
+    /// Squared euclidean distance from a point to the nearest point on the
+    /// segment.
+    fn distance_to_point(&self, point: &[f32]) -> f32 {
+        let length = self.direction.iter().map(|d| d * d).sum::<f32>();
+        let t = if length > 0. {
+            let along = point
+                .iter()
+                .zip(&self.start)
+                .zip(&self.direction)
+                .map(|((p, s), d)| (p - s) * d)
+                .sum::<f32>();
+            (along / length).clamp(0., self.t_max)
+        } else {
+            0.
+        };
+        self.at(t).zip(point).map(|(c, p)| (c - p).powi(2)).sum()
+    }
+    fn distance_to_box_at(&self, t: f32, region: &BoundingBox) -> f32 {
+        self.at(t)
+            .zip(region.min.coordinates.iter().zip(&region.max.coordinates))
+            .map(|(c, (lo, hi))| (c - c.max(*lo).min(*hi)).powi(2))
+            .sum()
+    }
+    /// Squared euclidean distance from the segment to the nearest point in a
+    /// box. Between the values of `t` where the segment crosses a face of the
+    /// box, the distance is a quadratic in `t`, so each piece is minimized
+    /// exactly.
+    fn distance_to_box(&self, region: &BoundingBox) -> f32 {
+        let mut breaks = vec![0., self.t_max];
+        for (i, (s, d)) in self.start.iter().zip(&self.direction).enumerate() {
+            if *d == 0. {
+                continue;
+            }
+            for face in [region.min.point(i), region.max.point(i)] {
+                let t = (face - s) / d;
+                if t > 0. && t < self.t_max {
+                    breaks.push(t);
+                }
+            }
+        }
+        breaks.sort_by(|a, b| a.total_cmp(b));
+        breaks
+            .windows(2)
+            .map(|piece| {
+                let (a, b) = (piece[0], piece[1]);
+                let middle = if b.is_finite() { (a + b) / 2. } else { a + 1. };
+                // Axes outside the box over the piece add (s + t d - face)^2.
+                let (mut quadratic, mut linear) = (0., 0.);
+                for (i, c) in self.at(middle).enumerate() {
+                    let (lo, hi) = (region.min.point(i), region.max.point(i));
+                    let face = if c < lo {
+                        lo
+                    } else if c > hi {
+                        hi
+                    } else {
+                        continue;
+                    };
+                    let d = self.direction[i];
+                    quadratic += d * d;
+                    linear += 2. * d * (self.start[i] - face);
+                }
+                let t = if quadratic > 0. {
+                    (-linear / (2. * quadratic)).clamp(a, b)
+                } else {
+                    a
+                };
+                self.distance_to_box_at(t, region)
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
+}
+
+/// Keeps the k records closest to a segment, and only visits branches whose
+/// region could hold a closer one.
+struct SegmentCollector<'a, T: Clone> {
+    nearest: NearestCollector,
+    segment: &'a Segment,
+    data: &'a [Data<T>],
+}
+
+impl<T: Clone> Collector for SegmentCollector<'_, T> {
+    const USES_REGIONS: bool = true;
+    fn bound(&self) -> f32 {
+        self.nearest.bound()
+    }
+    fn offer(&mut self, _distance: f32, data_pointer: usize) {
+        let point = self.data[data_pointer].point.as_slice();
+        self.nearest
+            .offer(self.segment.distance_to_point(point), data_pointer);
+    }
+    fn accepts_region(&self, region: &BoundingBox) -> bool {
+        self.segment.distance_to_box(region) <= self.nearest.bound()
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Get the k records nearest to the line segment from `start` to `end`,
+    /// ordered from nearest to farthest, with squared euclidean distances to
+    /// the closest point on the segment.
+    pub fn get_nearest_to_segment(&self, start: &Point, end: &Point, k: usize) -> Vec<Neighbor<T>> {
+        let segment = Segment {
+            start: start.coordinates.to_vec(),
+            direction: end
+                .coordinates
+                .iter()
+                .zip(&start.coordinates)
+                .map(|(e, s)| e - s)
+                .collect(),
+            t_max: 1.,
+        };
+        self.get_nearest_to(segment, k)
+    }
+    /// Get the k records nearest to the ray from `origin` in `direction`,
+    /// ordered from nearest to farthest, with squared euclidean distances to
+    /// the closest point on the ray.
+    pub fn get_nearest_to_ray(
+        &self,
+        origin: &Point,
+        direction: &Point,
+        k: usize,
+    ) -> Vec<Neighbor<T>> {
+        let segment = Segment {
+            start: origin.coordinates.to_vec(),
+            direction: direction.coordinates.to_vec(),
+            t_max: f32::INFINITY,
+        };
+        self.get_nearest_to(segment, k)
+    }
+    fn get_nearest_to(&self, segment: Segment, k: usize) -> Vec<Neighbor<T>> {
+        let mut collector = SegmentCollector {
+            nearest: NearestCollector::new(k),
+            segment: &segment,
+            data: &self.data,
+        };
+        let start = Point::new(segment.start.clone());
+        self.search(&start, &mut collector, &Unpruned {});
+        collector.nearest.into_neighbors(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::scattered_point;
+
+    #[test]
+    fn nearest_to_segment_and_ray() {
+        let data = (0..400)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        let brute_force = |segment: &Segment| {
+            let mut distances = tree
+                .records()
+                .iter()
+                .map(|d| segment.distance_to_point(d.point.as_slice()))
+                .collect::<Vec<_>>();
+            distances.sort_by(|a, b| a.total_cmp(b));
+            distances.truncate(5);
+            distances
+        };
+        let (start, end) = (Point::new(vec![10.5, 3.2]), Point::new(vec![60.1, 20.7]));
+        let found = tree.get_nearest_to_segment(&start, &end, 5);
+        let segment = Segment {
+            start: vec![10.5, 3.2],
+            direction: vec![49.6, 17.5],
+            t_max: 1.,
+        };
+        let distances = found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        assert_eq!(distances, brute_force(&segment));
+
+        let (origin, direction) = (Point::new(vec![120., 50.]), Point::new(vec![-1., -0.7]));
+        let found = tree.get_nearest_to_ray(&origin, &direction, 5);
+        let ray = Segment {
+            start: vec![120., 50.],
+            direction: vec![-1., -0.7],
+            t_max: f32::INFINITY,
+        };
+        let distances = found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        assert_eq!(distances, brute_force(&ray));
+    }
+}
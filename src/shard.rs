@@ -0,0 +1,227 @@
+//! Indexes split into several trees, each of which can be built on its own
+//! thread or from another machine's partition of the data, queried together.
+use crate::bounds::BoundingBox;
+use crate::distance::{DistanceMetric, SquaredEuclideanDistance};
+use crate::error::ClosestError;
+use crate::index::SpatialIndex;
+use crate::tree::{Data, KDTree, Neighbor, Point};
+
+/// Bits per axis of the Morton codes, as many as fit in 64 bits, at most 16.
+pub(crate) fn morton_bits(dimension: usize) -> u32 {
+    (64 / dimension.max(1)).min(16) as u32
+}
+
+/// Morton (Z-order) code of a point within `bounds`, interleaving the bits
+/// of every coordinate quantized to `bits` bits, so points close along the
+/// curve are close in space.
+pub(crate) fn morton_code(point: &Point, bounds: &BoundingBox, bits: u32) -> u64 {
+    let cells = ((1u64 << bits) - 1) as f32;
+    let quantized = point
+        .coordinates
+        .iter()
+        .zip(bounds.min.coordinates.iter().zip(&bounds.max.coordinates))
+        .map(|(c, (lo, hi))| {
+            let width = hi - lo;
+            if width > 0. {
+                (((c - lo) / width).clamp(0., 1.) * cells) as u64
+            } else {
+                0
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut code = 0u64;
+    for bit in (0..bits).rev() {
+        for q in &quantized {
+            code = (code << 1) | ((q >> bit) & 1);
+        }
+    }
+    code
+}
+
+/// Split records into `shards` partitions of nearly equal size, each a
+/// contiguous range of the Morton curve through the records' bounding box,
+/// so every shard covers a compact region of space and queries touch few of
+/// them.
+pub fn partition_by_curve<T: Clone>(mut data: Vec<Data<T>>, shards: usize) -> Vec<Vec<Data<T>>> {
+    let shards = shards.max(1);
+    let Some(bounds) = BoundingBox::from_points(data.iter().map(|d| &d.point)) else {
+        return vec![Vec::new(); shards];
+    };
+    let bits = morton_bits(bounds.min.shape());
+    data.sort_by_cached_key(|d| morton_code(&d.point, &bounds, bits));
+    let size = data.len().div_ceil(shards);
+    let mut partitions = Vec::with_capacity(shards);
+    let mut rest = data.into_iter();
+    for _ in 0..shards {
+        partitions.push(rest.by_ref().take(size).collect());
+    }
+    partitions
+}
+
+/// Several trees queried as one index. Queries probe the shards from the
+/// nearest bounding box outwards, and skip shards that can not hold a
+/// record closer than the ones already found.
+#[derive(Debug, Clone)]
+pub struct ShardedIndex<T: Clone> {
+    shards: Vec<KDTree<T>>,
+}
+
+impl<T: Clone> ShardedIndex<T> {
+    /// Query several trees as one. The trees must have the same dimension.
+    pub fn new(shards: Vec<KDTree<T>>) -> Result<Self, ClosestError> {
+        let mut dimensions = shards
+            .iter()
+            .filter(|s| !s.records().is_empty())
+            .map(|s| s.dimension);
+        if let Some(expected) = dimensions.next() {
+            if let Some(found) = dimensions.find(|d| *d != expected) {
+                return Err(ClosestError::DimensionMismatch { expected, found });
+            }
+        }
+        Ok(ShardedIndex { shards })
+    }
+    /// Partition records with [`partition_by_curve`] and build a tree for
+    /// every partition.
+    pub fn from_vec(
+        data: Vec<Data<T>>,
+        shards: usize,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        let shards = partition_by_curve(data, shards)
+            .into_iter()
+            .map(|partition| KDTree::from_vec(partition, min_points))
+            .collect::<Result<Vec<_>, _>>()?;
+        ShardedIndex::new(shards)
+    }
+    pub fn shards(&self) -> &[KDTree<T>] {
+        &self.shards
+    }
+    /// Non empty shards ordered by a lower bound on the distance from
+    /// `point` to their records, with that bound.
+    fn probe_order<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        distance_metric: &D,
+    ) -> Vec<(f32, &KDTree<T>)> {
+        let mut order = self
+            .shards
+            .iter()
+            .filter_map(|s| Some((s.bounds()?.plane_bound(point, distance_metric), s)))
+            .collect::<Vec<_>>();
+        order.sort_by(|a, b| a.0.total_cmp(&b.0));
+        order
+    }
+    /// Get k nearest neighbors to a given point over all shards, ordered
+    /// from nearest to farthest.
+    pub fn get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut found: Vec<Neighbor<T>> = Vec::new();
+        if k == 0 {
+            return found;
+        }
+        for (distance, shard) in self.probe_order(point, distance_metric) {
+            if found.len() == k && distance > found[k - 1].distance {
+                break;
+            }
+            found.extend(shard.get_nearest_neighbors(point, k, distance_metric));
+            found.sort();
+            found.truncate(k);
+        }
+        found
+    }
+    /// Get every record within `radius` of a given point over all shards,
+    /// ordered from nearest to farthest.
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut found = self
+            .probe_order(point, distance_metric)
+            .into_iter()
+            .take_while(|(distance, _)| *distance <= radius)
+            .flat_map(|(_, shard)| {
+                shard.get_neighbors_within_radius(point, radius, distance_metric)
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        found
+    }
+}
+
+impl<T: Clone> SpatialIndex<T> for ShardedIndex<T> {
+    fn nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        self.get_nearest_neighbors(point, k, &SquaredEuclideanDistance::default())
+    }
+    fn neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        self.get_neighbors_within_radius(point, radius, &SquaredEuclideanDistance::default())
+    }
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.records().len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::PeriodicEuclideanDistance;
+    use crate::tree::scattered_point;
+
+    #[test]
+    fn sharded_queries() {
+        let data = (0..2000)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let partitions = partition_by_curve(data.clone(), 4);
+        assert_eq!(
+            partitions.iter().map(|p| p.len()).collect::<Vec<_>>(),
+            vec![500; 4]
+        );
+        let sharded = ShardedIndex::from_vec(data.clone(), 4, 8).unwrap();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let distances =
+            |found: Vec<Neighbor<usize>>| found.iter().map(|n| n.distance).collect::<Vec<_>>();
+        for point in [
+            Point::from([50., 20.]),
+            Point::from([0., 0.]),
+            Point::from([200., -5.]),
+        ] {
+            assert_eq!(
+                distances(sharded.get_nearest_neighbors(&point, 7, &metric)),
+                distances(tree.get_nearest_neighbors(&point, 7, &metric))
+            );
+            assert_eq!(
+                distances(sharded.get_neighbors_within_radius(&point, 90., &metric)),
+                distances(tree.get_neighbors_within_radius(&point, 90., &metric))
+            );
+        }
+        assert_eq!(sharded.len(), 2000);
+        let periodic = (10..100)
+            .map(|i| Data::new(i, vec![i as f32, 0.]))
+            .collect::<Vec<_>>();
+        let sharded = ShardedIndex::from_vec(periodic.clone(), 2, 8).unwrap();
+        let tree = KDTree::from_vec(periodic, 8).unwrap();
+        let metric = PeriodicEuclideanDistance::new(vec![100., 100.]);
+        let origin = Point::from([0., 0.]);
+        assert_eq!(
+            sharded.get_nearest_neighbors(&origin, 1, &metric)[0].data,
+            tree.get_nearest_neighbors(&origin, 1, &metric)[0].data
+        );
+        assert_eq!(
+            sharded.get_nearest_neighbors(&origin, 1, &metric)[0].distance,
+            1.
+        );
+        assert_eq!(
+            distances(sharded.get_neighbors_within_radius(&origin, 4., &metric)),
+            distances(tree.get_neighbors_within_radius(&origin, 4., &metric))
+        );
+        let other = KDTree::from_vec(vec![Data::new(0, vec![1., 2., 3.])], 8).unwrap();
+        assert!(ShardedIndex::new(vec![tree, other]).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Smoothing point clouds by their neighborhoods, to remove noise from
+//! scans before estimating normals or matching them.
+use crate::distance::SquaredEuclideanDistance;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, Point};
+
+/// How a point is replaced by its neighborhood.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing {
+    /// The mean of the neighbors, along every axis.
+    #[default]
+    Centroid,
+    /// The median of the neighbors along every axis, which outliers among
+    /// them do not pull.
+    Median,
+}
+
+/// Replace every point of `points` with the centroid or median of its k
+/// nearest neighbors in euclidean distance, the point itself included, and
+/// return the smoothed points in the same order. Neighbors are always taken
+/// from the original points.
+pub fn smooth_points(
+    points: &[Vec<f32>],
+    k: usize,
+    smoothing: Smoothing,
+) -> Result<Vec<Vec<f32>>, ClosestError> {
+    if points.is_empty() {
+        return Ok(Vec::new());
+    }
+    if k == 0 {
+        return Err(ClosestError::ZeroNeighbors);
+    }
+    let tree = KDTree::from_points(points.iter().map(|p| p.to_vec()), 16)?;
+    let metric = SquaredEuclideanDistance::default();
+    let dimension = points[0].len();
+    Ok(points
+        .iter()
+        .map(|point| {
+            let nearest = tree.k_nearest(&Point::new(point.clone()), k, &metric);
+            (0..dimension)
+                .map(|axis| {
+                    let mut values = nearest
+                        .iter()
+                        .map(|(i, _)| points[*i][axis])
+                        .collect::<Vec<_>>();
+                    match smoothing {
+                        Smoothing::Centroid => values.iter().sum::<f32>() / values.len() as f32,
+                        Smoothing::Median => {
+                            values.sort_by(f32::total_cmp);
+                            let middle = values.len() / 2;
+                            if values.len().is_multiple_of(2) {
+                                (values[middle - 1] + values[middle]) / 2.
+                            } else {
+                                values[middle]
+                            }
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smoothed_plane() {
+        // A noisy grid on the plane z = 0, with one outlier far above it.
+        let mut points = (0..400)
+            .map(|i| {
+                let noise = ((i * 37 % 11) as f32 - 5.) / 50.;
+                vec![(i % 20) as f32, (i / 20) as f32, noise]
+            })
+            .collect::<Vec<_>>();
+        points[210][2] = 5.;
+        let roughness = |points: &[Vec<f32>]| points.iter().map(|p| p[2].abs()).sum::<f32>();
+
+        let centroids = smooth_points(&points, 9, Smoothing::Centroid).unwrap();
+        assert_eq!(centroids.len(), points.len());
+        assert!(roughness(&centroids) < roughness(&points));
+
+        let medians = smooth_points(&points, 9, Smoothing::Median).unwrap();
+        assert!(medians[210][2].abs() < 0.1);
+        assert!(centroids[210][2] > 0.5);
+        assert_eq!(
+            smooth_points(&points, 1, Smoothing::Median).unwrap(),
+            points
+        );
+
+        assert!(matches!(
+            smooth_points(&points, 0, Smoothing::Centroid),
+            Err(ClosestError::ZeroNeighbors)
+        ));
+    }
+}
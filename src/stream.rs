@@ -0,0 +1,113 @@
+use std::ops::ControlFlow;
+use std::sync::mpsc::SyncSender;
+
+use crate::distance::DistanceMetric;
+use crate::tree::{Collector, Data, KDTree, Neighbor, Point};
+
+/// Hands every record within a fixed distance to a callback as soon as it
+/// is found, until the callback breaks.
+struct CallbackCollector<'a, T: Clone, F> {
+    radius: f32,
+    data: &'a [Data<T>],
+    callback: F,
+    stopped: bool,
+}
+
+impl<T: Clone, F: FnMut(Neighbor<T>) -> ControlFlow<()>> Collector for CallbackCollector<'_, T, F> {
+    fn bound(&self) -> f32 {
+        if self.stopped {
+            f32::NEG_INFINITY
+        } else {
+            self.radius
+        }
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        if self.stopped || distance > self.radius {
+            return;
+        }
+        let neighbor = Neighbor {
+            distance,
+            data: self.data[data_pointer].data.clone(),
+        };
+        self.stopped = (self.callback)(neighbor).is_break();
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Call `callback` with every record within `radius` of a given point,
+    /// as soon as each one is found, in no particular order. Returning
+    /// `ControlFlow::Break` from the callback stops the search. Nothing is
+    /// collected, so this suits result sets too large to hold in memory.
+    pub fn for_each_within_radius<D, F>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+        callback: F,
+    ) where
+        D: DistanceMetric,
+        F: FnMut(Neighbor<T>) -> ControlFlow<()>,
+    {
+        let mut collector = CallbackCollector {
+            radius,
+            data: &self.data,
+            callback,
+            stopped: false,
+        };
+        self.search(point, &mut collector, distance_metric);
+    }
+    /// Send every record within `radius` of a given point down a bounded
+    /// channel as soon as each one is found, so a consumer on another thread
+    /// can process them while the search is still running. The search blocks
+    /// while the channel is full, and stops early if the receiver hangs up.
+    pub fn send_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+        sender: &SyncSender<Neighbor<T>>,
+    ) {
+        self.for_each_within_radius(point, radius, distance_metric, |neighbor| {
+            match sender.send(neighbor) {
+                Ok(()) => ControlFlow::Continue(()),
+                Err(_) => ControlFlow::Break(()),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+
+    #[test]
+    fn streamed_radius() {
+        let data = (0..1000)
+            .map(|i| Data::new(i, vec![(i % 100) as f32, (i / 100) as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let point = Point::new(vec![50., 5.]);
+        let metric = SquaredEuclideanDistance::default();
+
+        let (sender, receiver) = sync_channel(4);
+        let consumer = thread::spawn(move || receiver.iter().count());
+        tree.send_within_radius(&point, 9., &metric, &sender);
+        drop(sender);
+        // 7 columns on the middle row, 5 on the rows above and below, 1 at the edges.
+        assert_eq!(consumer.join().unwrap(), 7 + 2 * 5 + 2 * 5 + 2);
+
+        let mut seen = 0;
+        tree.for_each_within_radius(&point, 9., &metric, |_| {
+            seen += 1;
+            if seen == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(seen, 3);
+    }
+}
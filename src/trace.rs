@@ -0,0 +1,151 @@
+//! Matching ordered traces, such as GPS tracks, to stored points, such as
+//! points sampled along roads, where consecutive matches should stay close
+//! to each other instead of each trace point taking its own nearest point.
+use crate::distance::DistanceMetric;
+use crate::tree::{KDTree, NearestCollector, Neighbor, Point, RawNeighbor};
+
+/// The candidates found for one point of a trace, and the one it was
+/// matched to.
+#[derive(Debug)]
+pub struct TraceMatch<T: Clone> {
+    /// The nearest records to the trace point, ordered from nearest to
+    /// farthest.
+    pub candidates: Vec<Neighbor<T>>,
+    /// Position among the candidates of the record the point was matched
+    /// to, `None` only when there were no candidates.
+    pub matched: Option<usize>,
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Match every point of an ordered trace to one of its `candidates`
+    /// nearest records. Without `max_jump` every point takes its nearest
+    /// record. With it, consecutive matches may be at most `max_jump` apart,
+    /// measured with the same metric, and the matches minimize the total
+    /// distance from the trace points among the sequences that respect that
+    /// limit. Where no candidate of a point is in reach of any candidate of
+    /// the point before, the trace is matched in two independent parts.
+    pub fn match_trace<D: DistanceMetric>(
+        &self,
+        trace: &[Point],
+        candidates: usize,
+        max_jump: Option<f32>,
+        distance_metric: &D,
+    ) -> Vec<TraceMatch<T>> {
+        let found = trace
+            .iter()
+            .map(|point| {
+                let mut collector = NearestCollector::new(candidates);
+                self.search(point, &mut collector, distance_metric);
+                collector.into_sorted_vec()
+            })
+            .collect::<Vec<_>>();
+        let matched = match max_jump {
+            None => found.iter().map(|c| (!c.is_empty()).then_some(0)).collect(),
+            Some(max_jump) => self.best_sequence(&found, max_jump, distance_metric),
+        };
+        found
+            .into_iter()
+            .zip(matched)
+            .map(|(candidates, matched)| TraceMatch {
+                candidates: candidates
+                    .into_iter()
+                    .map(|c| c.into_neighbor(&self.data))
+                    .collect(),
+                matched,
+            })
+            .collect()
+    }
+    /// The sequence of candidates, one per trace point, with the least total
+    /// distance whose consecutive candidates are within `max_jump`, by
+    /// dynamic programming over the candidates of every point.
+    fn best_sequence<D: DistanceMetric>(
+        &self,
+        found: &[Vec<RawNeighbor>],
+        max_jump: f32,
+        distance_metric: &D,
+    ) -> Vec<Option<usize>> {
+        // Least cost of a sequence ending at every candidate, and the
+        // candidate of the previous point it continues, if any.
+        let mut costs: Vec<Vec<f32>> = Vec::with_capacity(found.len());
+        let mut previous: Vec<Vec<Option<usize>>> = Vec::with_capacity(found.len());
+        for (i, current) in found.iter().enumerate() {
+            let mut cost = Vec::with_capacity(current.len());
+            let mut from = Vec::with_capacity(current.len());
+            for candidate in current {
+                let point = self.get_data_point(candidate.data_pointer).as_slice();
+                let best = i.checked_sub(1).and_then(|p| {
+                    found[p]
+                        .iter()
+                        .zip(&costs[p])
+                        .enumerate()
+                        .filter(|(_, (before, _))| {
+                            let before = self.get_data_point(before.data_pointer).as_slice();
+                            distance_metric.distance(before, point) <= max_jump
+                        })
+                        .min_by(|a, b| a.1 .1.total_cmp(b.1 .1))
+                        .map(|(j, (_, c))| (j, *c))
+                });
+                from.push(best.map(|(j, _)| j));
+                cost.push(candidate.distance + best.map_or(0., |(_, c)| c));
+            }
+            // With no candidate in reach, every candidate starts a new part.
+            if i > 0 && from.iter().all(|f| f.is_none()) {
+                cost = current.iter().map(|c| c.distance).collect();
+            }
+            costs.push(cost);
+            previous.push(from);
+        }
+        let mut matched = vec![None; found.len()];
+        let mut next: Option<usize> = None;
+        for i in (0..found.len()).rev() {
+            let here = next.or_else(|| {
+                (0..costs[i].len()).min_by(|a, b| costs[i][*a].total_cmp(&costs[i][*b]))
+            });
+            matched[i] = here;
+            next = here.and_then(|h| previous[i][h]);
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::Data;
+
+    #[test]
+    fn stays_on_one_road() {
+        // Two parallel roads, y = 0 and y = 3, sampled every unit of x.
+        let roads = (0..200)
+            .map(|i| Data::new(i, vec![(i % 100) as f32, (i / 100 * 3) as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(roads, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        // A trace along the lower road that drifts closer to the upper one
+        // at one point.
+        let trace = (10..30)
+            .map(|x| Point::from([x as f32, if x == 20 { 1.6 } else { 1.4 }]))
+            .collect::<Vec<_>>();
+        let road = |m: &TraceMatch<usize>| m.candidates[m.matched.unwrap()].data / 100;
+
+        let greedy = tree.match_trace(&trace, 4, None, &metric);
+        assert_eq!(road(&greedy[10]), 1);
+        assert!(greedy
+            .iter()
+            .enumerate()
+            .all(|(i, m)| i == 10 || road(m) == 0));
+
+        let matched = tree.match_trace(&trace, 4, Some(4.), &metric);
+        assert!(matched.iter().all(|m| road(m) == 0));
+        for m in &matched {
+            assert_eq!(m.candidates.len(), 4);
+        }
+
+        // Far apart traces can not be joined, and are matched in parts.
+        let jump = [Point::from([10., 0.2]), Point::from([80., 2.9])];
+        let matched = tree.match_trace(&jump, 2, Some(4.), &metric);
+        assert_eq!(road(&matched[0]), 0);
+        assert_eq!(road(&matched[1]), 1);
+    }
+}
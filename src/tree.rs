@@ -1,28 +1,40 @@
+use crate::bounds::BoundingBox;
 use crate::distance::DistanceMetric;
 use crate::error::ClosestError;
+use crate::metrics::{CountingCollector, MetricsRecorder};
+use crate::reindex::ReindexPolicy;
+use smallvec::SmallVec;
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
+use std::hash::Hash;
+use std::ops::Range;
+use std::sync::Arc;
 
 /// Points to a node on the node store
 /// or data on the data store.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
 pub enum NodeOrDataPointer {
     Node(Node),
     Data((usize, usize)),
 }
 
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone)]
 pub struct Node {
-    data_pointer: usize,
-    left: Box<NodeOrDataPointer>,
-    right: Box<NodeOrDataPointer>,
+    pub(crate) data_pointer: usize,
+    pub(crate) left: Box<NodeOrDataPointer>,
+    pub(crate) right: Box<NodeOrDataPointer>,
 }
 
 /// Arbitrary data that is queried from n dimensional coordinates.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Data<T: Clone> {
-    data: T,
-    point: Point,
+    pub(crate) data: T,
+    pub(crate) point: Point,
+    /// Position of the record in the data the tree was built from.
+    pub(crate) id: usize,
 }
 
 impl<T: Clone> Data<T> {
@@ -30,21 +42,44 @@ impl<T: Clone> Data<T> {
     pub fn new(data: T, coordinates: Vec<f32>) -> Self {
         Data {
             data,
-            point: Point { coordinates },
+            point: Point::new(coordinates),
+            id: 0,
         }
     }
+    /// The data identifier of the record.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+    /// Coordinates of the record.
+    pub fn point(&self) -> &Point {
+        &self.point
+    }
 }
 
+/// Points with up to this many dimensions keep their coordinates inline,
+/// without a heap allocation of their own.
+pub const INLINE_DIMENSIONS: usize = 4;
+
 /// Point defining location in N
 /// dimensional coordinates.
-#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Point {
-    pub coordinates: Vec<f32>,
+    pub coordinates: SmallVec<[f32; INLINE_DIMENSIONS]>,
 }
 
 impl Point {
     pub fn new(coordinates: Vec<f32>) -> Self {
-        Point { coordinates }
+        Point {
+            coordinates: SmallVec::from_vec(coordinates),
+        }
+    }
+    /// Create a point by copying coordinates, which allocates nothing for
+    /// points of up to [`INLINE_DIMENSIONS`] dimensions.
+    pub fn from_slice(coordinates: &[f32]) -> Self {
+        Point {
+            coordinates: SmallVec::from_slice(coordinates),
+        }
     }
 }
 
@@ -55,8 +90,27 @@ impl Point {
     pub fn point(&self, i: usize) -> f32 {
         self.coordinates[i]
     }
+    pub fn coordinates(&self) -> &[f32] {
+        &self.coordinates
+    }
+    pub fn as_slice(&self) -> &[f32] {
+        &self.coordinates
+    }
+}
+
+impl From<Vec<f32>> for Point {
+    fn from(coordinates: Vec<f32>) -> Self {
+        Point::new(coordinates)
+    }
+}
+
+impl<const N: usize> From<[f32; N]> for Point {
+    fn from(coordinates: [f32; N]) -> Self {
+        Point::from_slice(&coordinates)
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug)]
 pub struct Neighbor<T: Clone> {
     pub distance: f32,
@@ -84,13 +138,13 @@ impl<T: Clone> PartialEq for Neighbor<T> {
 impl<T: Clone> Eq for Neighbor<T> {}
 
 #[derive(Debug)]
-struct RawNeighbor {
-    distance: f32,
-    data_pointer: usize,
+pub(crate) struct RawNeighbor {
+    pub(crate) distance: f32,
+    pub(crate) data_pointer: usize,
 }
 
 impl RawNeighbor {
-    pub fn as_neighbor<T: Clone>(self, data: &[Data<T>]) -> Neighbor<T> {
+    pub fn into_neighbor<T: Clone>(self, data: &[Data<T>]) -> Neighbor<T> {
         Neighbor {
             distance: self.distance,
             data: data[self.data_pointer].data.clone(),
@@ -128,54 +182,698 @@ impl PartialEq for RawNeighbor {
 
 impl Eq for RawNeighbor {}
 
-/// Tree that is used to partition the data.
+/// Gathers the records found while searching the tree.
+pub(crate) trait Collector {
+    /// Distance beyond which no record can be accepted, branches that are
+    /// farther away than this are pruned.
+    fn bound(&self) -> f32;
+    fn offer(&mut self, distance: f32, data_pointer: usize);
+    /// Whether the collector implements [`Collector::accepts_region`].
+    /// Searches only keep track of the region each branch covers for
+    /// collectors that do.
+    const USES_REGIONS: bool = false;
+    /// Whether a branch covering `region` could hold any accepted record,
+    /// branches outside the accepted regions are pruned.
+    fn accepts_region(&self, _region: &BoundingBox) -> bool {
+        true
+    }
+    /// Called for every node the search visits.
+    fn visit_node(&mut self) {}
+}
+
+/// Keeps the k closest records in a max heap, ignoring records farther than
+/// `max_distance`.
+pub(crate) struct NearestCollector {
+    k: usize,
+    max_distance: f32,
+    heap: BinaryHeap<RawNeighbor>,
+}
+
+impl NearestCollector {
+    pub(crate) fn new(k: usize) -> Self {
+        NearestCollector::with_max_distance(k, f32::INFINITY)
+    }
+    pub(crate) fn with_max_distance(k: usize, max_distance: f32) -> Self {
+        NearestCollector {
+            k,
+            max_distance,
+            heap: BinaryHeap::with_capacity(k),
+        }
+    }
+    /// The collected neighbors, ordered from nearest to farthest.
+    pub(crate) fn into_neighbors<T: Clone>(self, data: &[Data<T>]) -> Vec<Neighbor<T>> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| r.into_neighbor(data))
+            .collect()
+    }
+    /// The collected records, ordered from nearest to farthest.
+    pub(crate) fn into_sorted_vec(self) -> Vec<RawNeighbor> {
+        self.heap.into_sorted_vec()
+    }
+}
+
+impl Collector for NearestCollector {
+    fn bound(&self) -> f32 {
+        if self.heap.len() < self.k {
+            self.max_distance
+        } else {
+            self.heap
+                .peek()
+                .map_or(f32::NEG_INFINITY, |worst| worst.distance)
+        }
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        if distance > self.max_distance {
+            return;
+        }
+        if self.heap.len() < self.k {
+            self.heap.push(RawNeighbor::new(distance, data_pointer));
+        } else if distance < self.bound() {
+            self.heap.pop();
+            self.heap.push(RawNeighbor::new(distance, data_pointer));
+        }
+    }
+}
+
+/// Keeps every record within a fixed distance.
+pub(crate) struct RadiusCollector {
+    radius: f32,
+    found: Vec<RawNeighbor>,
+}
+
+impl RadiusCollector {
+    pub(crate) fn new(radius: f32) -> Self {
+        RadiusCollector {
+            radius,
+            found: Vec::new(),
+        }
+    }
+    /// The collected neighbors, ordered from nearest to farthest.
+    pub(crate) fn into_neighbors<T: Clone>(self, data: &[Data<T>]) -> Vec<Neighbor<T>> {
+        self.into_sorted_vec()
+            .into_iter()
+            .map(|r| r.into_neighbor(data))
+            .collect()
+    }
+    /// The collected records, ordered from nearest to farthest.
+    pub(crate) fn into_sorted_vec(mut self) -> Vec<RawNeighbor> {
+        self.found.sort();
+        self.found
+    }
+}
+
+impl Collector for RadiusCollector {
+    fn bound(&self) -> f32 {
+        self.radius
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        if distance <= self.radius {
+            self.found.push(RawNeighbor::new(distance, data_pointer));
+        }
+    }
+}
+
+/// Counts the records within a fixed distance, without keeping them.
+struct CountCollector {
+    radius: f32,
+    count: usize,
+}
+
+impl Collector for CountCollector {
+    fn bound(&self) -> f32 {
+        self.radius
+    }
+    fn offer(&mut self, distance: f32, _data_pointer: usize) {
+        if distance <= self.radius {
+            self.count += 1;
+        }
+    }
+}
+
+/// Keeps every record inside a box, in no particular order.
+pub(crate) struct BoxCollector<'a, T: Clone> {
+    bounds: &'a BoundingBox,
+    data: &'a [Data<T>],
+    pub(crate) found: Vec<usize>,
+}
+
+impl<'a, T: Clone> BoxCollector<'a, T> {
+    pub(crate) fn new(bounds: &'a BoundingBox, data: &'a [Data<T>]) -> Self {
+        BoxCollector {
+            bounds,
+            data,
+            found: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> Collector for BoxCollector<'_, T> {
+    const USES_REGIONS: bool = true;
+    fn bound(&self) -> f32 {
+        f32::INFINITY
+    }
+    fn offer(&mut self, _distance: f32, data_pointer: usize) {
+        if self.bounds.contains(&self.data[data_pointer].point) {
+            self.found.push(data_pointer);
+        }
+    }
+    fn accepts_region(&self, region: &BoundingBox) -> bool {
+        self.bounds.intersects(region)
+    }
+}
+
+/// Has the tree visit both sides of every split without computing any
+/// distances, pruning is left to [`Collector::accepts_region`].
+pub(crate) struct Unpruned {}
+
+impl DistanceMetric for Unpruned {
+    fn distance(&self, _p1: &[f32], _p2: &[f32]) -> f32 {
+        0.
+    }
+    fn min_distance_to_plane(&self, _point: &[f32], _axis: usize, _value: f32) -> f32 {
+        0.
+    }
+}
+
+/// Keeps the closest record for each of the k closest distinct keys. Keys
+/// that fall out of the k closest are dropped, a closer record for them is
+/// always offered again later if one exists.
+struct DistinctCollector<'a, T: Clone, K, F> {
+    k: usize,
+    data: &'a [Data<T>],
+    key: F,
+    best: HashMap<K, RawNeighbor>,
+    /// Distance of the worst kept record once k are kept, updated only when
+    /// the worst record changes.
+    worst: f32,
+}
+
+impl<'a, T: Clone, K: Hash + Eq, F: Fn(&T) -> K> DistinctCollector<'a, T, K, F> {
+    fn new(k: usize, data: &'a [Data<T>], key: F) -> Self {
+        DistinctCollector {
+            k,
+            data,
+            key,
+            best: HashMap::with_capacity(k + 1),
+            worst: f32::INFINITY,
+        }
+    }
+    fn update_worst(&mut self) {
+        self.worst = if self.best.len() < self.k {
+            f32::INFINITY
+        } else {
+            self.best
+                .values()
+                .map(|r| r.distance)
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+    }
+}
+
+impl<T: Clone, K: Hash + Eq, F: Fn(&T) -> K> Collector for DistinctCollector<'_, T, K, F> {
+    fn bound(&self) -> f32 {
+        self.worst
+    }
+    fn offer(&mut self, distance: f32, data_pointer: usize) {
+        if self.k == 0 || distance >= self.worst {
+            return;
+        }
+        let key = (self.key)(&self.data[data_pointer].data);
+        match self.best.get_mut(&key) {
+            Some(current) => {
+                if distance < current.distance {
+                    let was_worst = current.distance >= self.worst;
+                    *current = RawNeighbor::new(distance, data_pointer);
+                    if was_worst {
+                        self.update_worst();
+                    }
+                }
+            }
+            None => {
+                self.best
+                    .insert(key, RawNeighbor::new(distance, data_pointer));
+                if self.best.len() > self.k {
+                    let worst = self
+                        .best
+                        .iter()
+                        .max_by(|a, b| a.1.cmp(b.1))
+                        .map(|(_, r)| r.data_pointer);
+                    self.best.retain(|_, r| Some(r.data_pointer) != worst);
+                }
+                if self.best.len() == self.k {
+                    self.update_worst();
+                }
+            }
+        }
+    }
+}
+
+/// View of a node in the tree, and the region of the data it covers. This
+/// exposes the structure of the tree, so custom branch and bound algorithms
+/// can be written on top of it.
 #[derive(Debug)]
+pub struct TreeNode<'a, T: Clone> {
+    tree: &'a KDTree<T>,
+    node: &'a NodeOrDataPointer,
+    depth: usize,
+    start: usize,
+    stop: usize,
+}
+
+// Manual impls, deriving would require `T: Copy`.
+impl<T: Clone> Clone for TreeNode<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Clone> Copy for TreeNode<'_, T> {}
+
+impl<'a, T: Clone> TreeNode<'a, T> {
+    /// Depth of the node, the root is at depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn is_leaf(&self) -> bool {
+        matches!(self.node, NodeOrDataPointer::Data(_))
+    }
+    /// The axis and value the node splits its records on, `None` for leaves.
+    pub fn split(&self) -> Option<(usize, f32)> {
+        match self.node {
+            NodeOrDataPointer::Node(n) => {
+                let axis = self.depth % self.tree.dimension;
+                Some((axis, self.tree.get_data_point(n.data_pointer).point(axis)))
+            }
+            NodeOrDataPointer::Data(_) => None,
+        }
+    }
+    /// The record stored on a split node, `None` for leaves.
+    pub fn split_record(&self) -> Option<&'a Data<T>> {
+        match self.node {
+            NodeOrDataPointer::Node(n) => Some(self.tree.get_data(n.data_pointer)),
+            NodeOrDataPointer::Data(_) => None,
+        }
+    }
+    /// Left and right children of a split node, `None` for leaves.
+    pub fn children(&self) -> Option<(TreeNode<'a, T>, TreeNode<'a, T>)> {
+        match self.node {
+            NodeOrDataPointer::Node(n) => Some((
+                TreeNode {
+                    tree: self.tree,
+                    node: n.left.as_ref(),
+                    depth: self.depth + 1,
+                    start: self.start,
+                    stop: n.data_pointer,
+                },
+                TreeNode {
+                    tree: self.tree,
+                    node: n.right.as_ref(),
+                    depth: self.depth + 1,
+                    start: n.data_pointer + 1,
+                    stop: self.stop,
+                },
+            )),
+            NodeOrDataPointer::Data(_) => None,
+        }
+    }
+    /// Positions of the records the node was built with, removed ones
+    /// included.
+    pub(crate) fn range(&self) -> Range<usize> {
+        self.start..self.stop
+    }
+    /// All records in the subtree under this node, those inserted into its
+    /// leaves since the tree was built included and removed ones left out.
+    pub fn records(&self) -> impl Iterator<Item = &'a Data<T>> + 'a {
+        let tree = self.tree;
+        let mut positions = self.range().collect::<Vec<_>>();
+        let mut stack = vec![*self];
+        while let Some(node) = stack.pop() {
+            match node.children() {
+                Some((left, right)) => stack.extend([left, right]),
+                None => positions.extend_from_slice(tree.leaf_overflow(node.start)),
+            }
+        }
+        positions
+            .into_iter()
+            .filter(move |p| !tree.is_removed(*p))
+            .map(move |p| tree.get_data(p))
+    }
+    /// Tight bounding box of the records under this node, `None` if the node
+    /// holds no records.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        BoundingBox::from_points(self.records().map(|d| &d.point))
+    }
+}
+
+/// Tree that is used to partition the data.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "StoredKDTree<T>",
+        bound(deserialize = "T: serde::Deserialize<'de>")
+    )
+)]
+#[derive(Debug, Clone)]
 pub struct KDTree<T: Clone> {
+    pub(crate) root_node: NodeOrDataPointer,
+    pub(crate) data: Vec<Data<T>>,
+    pub(crate) dimension: usize,
+    pub(crate) min_points: usize,
+    /// Number of records covered by the tree, records past this were added
+    /// since it was last built and are scanned on every query.
+    pub(crate) indexed: usize,
+    /// Removed records that are still stored, and skipped by queries.
+    pub(crate) tombstones: usize,
+    /// Whether every stored record, by position, is a removed record still
+    /// stored. Empty while there are none.
+    pub(crate) removed: Vec<bool>,
+    /// Number of records in the largest leaf.
+    pub(crate) largest_leaf: usize,
+    /// Records placed into leaves with [`KDTree::insert`] since the tree was
+    /// last built. They are stored after the indexed records, before those
+    /// added with [`Extend`], and are scanned along with their leaf.
+    pub(crate) inserted: usize,
+    /// Positions of the inserted records of each leaf, by the position its
+    /// range of records starts at.
+    pub(crate) overflow: HashMap<usize, Vec<usize>>,
+    /// Id given to the next record added, ids of removed records are not
+    /// reused.
+    pub(crate) next_id: usize,
+    /// Storage position of every record by its id, removed records left
+    /// out.
+    pub(crate) positions: HashMap<usize, usize>,
+    /// Box enclosing every stored record, the smallest one right after a
+    /// build.
+    pub(crate) extent: Option<BoundingBox>,
+    pub(crate) policy: ReindexPolicy,
+    /// Buffer of record positions used while building, kept so rebuilding
+    /// does not allocate it again.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) order: Vec<usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) recorder: Option<Arc<MetricsRecorder>>,
+}
+
+/// A deserialized tree, checked with [`KDTree::check_structure`] before it
+/// is used, since searches rely on its positions being in range.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredKDTree<T: Clone> {
     root_node: NodeOrDataPointer,
     data: Vec<Data<T>>,
     dimension: usize,
+    min_points: usize,
+    indexed: usize,
+    tombstones: usize,
+    removed: Vec<bool>,
+    largest_leaf: usize,
+    inserted: usize,
+    overflow: HashMap<usize, Vec<usize>>,
+    next_id: usize,
+    positions: HashMap<usize, usize>,
+    extent: Option<BoundingBox>,
+    policy: ReindexPolicy,
 }
 
-fn build_tree<T: Clone>(
-    data: &mut [Data<T>],
+#[cfg(feature = "serde")]
+impl<T: Clone> TryFrom<StoredKDTree<T>> for KDTree<T> {
+    type Error = ClosestError;
+    fn try_from(stored: StoredKDTree<T>) -> Result<Self, ClosestError> {
+        let tree = KDTree {
+            root_node: stored.root_node,
+            data: stored.data,
+            dimension: stored.dimension,
+            min_points: stored.min_points,
+            indexed: stored.indexed,
+            tombstones: stored.tombstones,
+            removed: stored.removed,
+            largest_leaf: stored.largest_leaf,
+            inserted: stored.inserted,
+            overflow: stored.overflow,
+            next_id: stored.next_id,
+            positions: stored.positions,
+            extent: stored.extent,
+            policy: stored.policy,
+            order: Vec::new(),
+            recorder: None,
+        };
+        tree.check_structure()?;
+        Ok(tree)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Clone> KDTree<T> {
+    /// Check that every position the tree holds is in range and every record
+    /// has its dimension, as they are for any tree this crate builds.
+    fn check_structure(&self) -> Result<(), ClosestError> {
+        let invalid = |what: &str| Err(ClosestError::InvalidFormat(what.to_string()));
+        let len = self.data.len();
+        if self.indexed + self.inserted > len {
+            return invalid("more indexed and inserted records than records");
+        }
+        let dimension = records_dimension_of(&self.data)?;
+        if (len > 0 && dimension != self.records_dimension())
+            || (self.dimension == 0 && matches!(self.root_node, NodeOrDataPointer::Node(_)))
+        {
+            return invalid("records without the tree's dimension");
+        }
+        if !self.removed.is_empty() && self.removed.len() != len {
+            return invalid("removed flags for another number of records");
+        }
+        if self.removed.iter().filter(|r| **r).count() != self.tombstones {
+            return invalid("removed records miscounted");
+        }
+        let overflow = self.indexed..self.indexed + self.inserted;
+        if self
+            .overflow
+            .values()
+            .flatten()
+            .any(|p| !overflow.contains(p))
+            || self.positions.values().any(|p| *p >= len)
+        {
+            return invalid("record positions out of range");
+        }
+        let mut pending = vec![&self.root_node];
+        while let Some(node) = pending.pop() {
+            let in_range = match node {
+                NodeOrDataPointer::Node(n) => {
+                    pending.push(&n.left);
+                    pending.push(&n.right);
+                    n.data_pointer < self.indexed
+                }
+                NodeOrDataPointer::Data((start, stop)) => start <= stop && *stop <= self.indexed,
+            };
+            if !in_range {
+                return invalid("node positions out of range");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Element `i` of `values`, without a bounds check when the
+/// `perf-unchecked` feature is enabled. Only for positions the tree itself
+/// produces, and axes below its dimension, of records, which are checked to
+/// share one dimension as they are added, and of query points, which
+/// searches check with `assert_point` first. Debug builds
+/// check the bounds either way.
+#[inline(always)]
+pub(crate) fn at<V>(values: &[V], i: usize) -> &V {
+    debug_assert!(i < values.len());
+    #[cfg(feature = "perf-unchecked")]
+    // SAFETY: positions and axes are below the lengths they index, as
+    // documented above.
+    unsafe {
+        values.get_unchecked(i)
+    }
+    #[cfg(not(feature = "perf-unchecked"))]
+    &values[i]
+}
+
+/// Leaf size used when none is given.
+pub(crate) const DEFAULT_MIN_POINTS: usize = 16;
+
+/// Number of records in the largest leaf under a node.
+pub(crate) fn largest_leaf(node: &NodeOrDataPointer) -> usize {
+    match node {
+        NodeOrDataPointer::Node(n) => largest_leaf(&n.left).max(largest_leaf(&n.right)),
+        NodeOrDataPointer::Data((start, stop)) => stop - start,
+    }
+}
+
+/// Whether a slice of records is too small to split any further.
+pub(crate) fn is_leaf_size(len: usize, min_points: usize) -> bool {
+    // Only can split further if there is at least 3 records
+    (len < min_points) || (len < 3)
+}
+
+/// Record each record's position in the input, before building reorders them.
+pub(crate) fn number_records<T: Clone>(data: &mut [Data<T>]) {
+    for (id, record) in data.iter_mut().enumerate() {
+        record.id = id;
+    }
+}
+
+/// Dimension shared by every record, 0 without any.
+pub(crate) fn records_dimension_of<T: Clone>(data: &[Data<T>]) -> Result<usize, ClosestError> {
+    let dimension = data.first().map_or(0, |d| d.point.shape());
+    if data.iter().any(|d| d.point.shape() != dimension) {
+        return Err(ClosestError::DifferingPositionLength);
+    }
+    Ok(dimension)
+}
+
+/// Check `point` has `dimension` coordinates, all finite, and
+/// `distance_metric` is made for that dimension.
+pub(crate) fn check_point<D: DistanceMetric>(
+    point: &[f32],
+    dimension: usize,
+    distance_metric: &D,
+) -> Result<(), ClosestError> {
+    for found in std::iter::once(point.len()).chain(distance_metric.dimension()) {
+        if found != dimension {
+            return Err(ClosestError::DimensionMismatch {
+                expected: dimension,
+                found,
+            });
+        }
+    }
+    match point.iter().position(|c| !c.is_finite()) {
+        Some(axis) => Err(ClosestError::NonFiniteCoordinate { axis }),
+        None => Ok(()),
+    }
+}
+
+/// Panic unless `point` and `distance_metric` have `dimension` coordinates,
+/// for searches that cannot return an error, as tree walks index points by
+/// axis.
+pub(crate) fn assert_point<D: DistanceMetric>(
+    point: &[f32],
+    dimension: usize,
+    distance_metric: &D,
+) {
+    assert!(
+        point.len() == dimension,
+        "query points must have {} coordinates, found {}",
+        dimension,
+        point.len()
+    );
+    if let Some(found) = distance_metric.dimension() {
+        assert!(
+            found == dimension,
+            "the distance metric is made for {} coordinates, the records have {}",
+            found,
+            dimension
+        );
+    }
+}
+
+/// Fill `positions` with the storage position of every record by its id,
+/// keeping its allocation.
+fn index_positions<T: Clone>(positions: &mut HashMap<usize, usize>, data: &[Data<T>]) {
+    positions.clear();
+    positions.extend(
+        data.iter()
+            .enumerate()
+            .map(|(position, record)| (record.id, position)),
+    );
+}
+
+/// Sort positions of records along the splitting axis, and return the
+/// position of the median record.
+pub(crate) fn split_at_median<T: Clone>(
+    data: &[Data<T>],
+    order: &mut [usize],
+    axis: usize,
+) -> usize {
+    order.sort_by(|a, b| {
+        let a_ = data[*a].point.point(axis);
+        let b_ = data[*b].point.point(axis);
+        // Consider NaN values Less than everything.
+        a_.partial_cmp(&b_).unwrap_or(std::cmp::Ordering::Less)
+    });
+    order.len() >> 1
+}
+
+/// Build the tree over a permutation of positions in `data`, leaving the
+/// records where they are. Only positions move while sorting, so building
+/// costs the same however large each record's data is, the records are put
+/// in tree order once at the end with [`apply_order`].
+pub(crate) fn build_tree<T: Clone>(
+    data: &[Data<T>],
+    order: &mut [usize],
     data_location: usize,
     depth: usize,
     point_len: usize,
     min_points: usize,
 ) -> NodeOrDataPointer {
-    // Only can split further if there is at least 3 records
-    if (data.len() < min_points) || (data.len() < 3) {
-        return NodeOrDataPointer::Data((data_location, (data_location + data.len())));
+    if is_leaf_size(order.len(), min_points) {
+        return NodeOrDataPointer::Data((data_location, (data_location + order.len())));
     }
-    let axis = depth % point_len;
-    data.sort_by(|a, b| {
-        let a_ = a.point.point(axis);
-        let b_ = b.point.point(axis);
-        // Consider NaN values Less than everything.
-        a_.partial_cmp(&b_).unwrap_or(std::cmp::Ordering::Less)
-    });
-    let median = data.len() >> 1;
+    let median = split_at_median(data, order, depth % point_len);
     let node = Node {
         data_pointer: median + data_location,
         left: Box::new(build_tree(
-            &mut data[..median],
+            data,
+            &mut order[..median],
             data_location,
             depth + 1,
             point_len,
             min_points,
         )),
         right: Box::new(build_tree(
-            &mut data[(median + 1)..],
+            data,
+            &mut order[(median + 1)..],
             data_location + median + 1,
             depth + 1,
             point_len,
             min_points,
         )),
     };
-    return NodeOrDataPointer::Node(node);
+    NodeOrDataPointer::Node(node)
+}
+
+/// Move every record to its place in the tree, so that `data[i]` becomes the
+/// record at `order[i]`. Each cycle of the permutation is followed with
+/// swaps, so records move at most twice. `order` is left as the identity.
+pub(crate) fn apply_order<T>(data: &mut [T], order: &mut [usize]) {
+    for start in 0..order.len() {
+        let mut position = start;
+        while order[position] != start {
+            let next = order[position];
+            data.swap(position, next);
+            order[position] = position;
+            position = next;
+        }
+        order[position] = position;
+    }
+}
+
+/// Build the tree over `data`, reordering the records into tree order.
+/// `order` is only used as a buffer, so its allocation can be reused.
+pub(crate) fn build<T: Clone>(
+    data: &mut [Data<T>],
+    order: &mut Vec<usize>,
+    point_len: usize,
+    min_points: usize,
+) -> NodeOrDataPointer {
+    order.clear();
+    order.extend(0..data.len());
+    let root_node = build_tree(data, order, 0, 0, point_len, min_points);
+    apply_order(data, order);
+    root_node
 }
 
 impl<T: Clone> KDTree<T> {
+    /// Build a tree with the default leaf size of 16 records, which suits
+    /// most data. Use [`KDTree::from_vec`] to pick another.
+    pub fn new(data: Vec<Data<T>>) -> Self {
+        data.into_iter().collect()
+    }
     pub fn from_iter<I: Iterator<Item = Data<T>>>(
         data: I,
         min_points: usize,
@@ -183,123 +881,742 @@ impl<T: Clone> KDTree<T> {
         Self::from_vec(data.collect(), min_points)
     }
     pub fn from_vec(mut data: Vec<Data<T>>, min_points: usize) -> Result<Self, ClosestError> {
-        let point_len = data[0].point.shape();
-        let root_node = build_tree(&mut data, 0, 0, point_len, min_points);
-        Ok(KDTree {
+        number_records(&mut data);
+        let point_len = records_dimension_of(&data)?;
+        let mut order = Vec::new();
+        let root_node = build(&mut data, &mut order, point_len, min_points);
+        let mut tree = Self::from_built(data, root_node, point_len, min_points);
+        tree.order = order;
+        Ok(tree)
+    }
+    /// Tree over records already numbered and in tree order under
+    /// `root_node`.
+    pub(crate) fn from_built(
+        data: Vec<Data<T>>,
+        root_node: NodeOrDataPointer,
+        dimension: usize,
+        min_points: usize,
+    ) -> Self {
+        let mut positions = HashMap::new();
+        index_positions(&mut positions, &data);
+        KDTree {
+            largest_leaf: largest_leaf(&root_node),
             root_node,
+            indexed: data.len(),
+            next_id: data.len(),
+            positions,
+            extent: BoundingBox::from_points(data.iter().map(|d| &d.point)),
             data,
-            dimension: point_len,
-        })
+            dimension,
+            min_points,
+            tombstones: 0,
+            removed: Vec::new(),
+            inserted: 0,
+            overflow: HashMap::new(),
+            policy: ReindexPolicy::default(),
+            order: Vec::new(),
+            recorder: None,
+        }
+    }
+    /// Build a tree from payloads and a row major matrix of their
+    /// coordinates, `dimension` values per payload, without assembling
+    /// [`Data`] records first.
+    pub fn from_parts(
+        payloads: Vec<T>,
+        coordinates: Vec<f32>,
+        dimension: usize,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        if coordinates.len() != payloads.len() * dimension
+            || (dimension == 0 && !payloads.is_empty())
+        {
+            return Err(ClosestError::CoordinateCountMismatch {
+                expected: payloads.len() * dimension,
+                found: coordinates.len(),
+            });
+        }
+        let data = payloads
+            .into_iter()
+            .zip(coordinates.chunks_exact(dimension.max(1)))
+            .map(|(data, point)| Data {
+                data,
+                point: Point::from_slice(point),
+                id: 0,
+            })
+            .collect();
+        Self::from_vec(data, min_points)
+    }
+    /// Rebuild the tree over every record, including those added since it
+    /// was last built.
+    pub(crate) fn rebuild(&mut self) {
+        if !self.removed.is_empty() {
+            let mut removed = std::mem::take(&mut self.removed).into_iter();
+            self.data.retain(|_| !removed.next().unwrap_or(false));
+        }
+        self.tombstones = 0;
+        self.dimension = self.records_dimension();
+        self.root_node = build(
+            &mut self.data,
+            &mut self.order,
+            self.dimension,
+            self.min_points,
+        );
+        self.indexed = self.data.len();
+        self.inserted = 0;
+        self.overflow.clear();
+        index_positions(&mut self.positions, &self.data);
+        self.largest_leaf = largest_leaf(&self.root_node);
+        self.extent = BoundingBox::from_points(self.data.iter().map(|d| &d.point));
+    }
+    /// Positions of the records in the order a rebuild would store them in,
+    /// removed records left out, without rebuilding. Records are in that
+    /// order already while none were added, inserted or removed since the
+    /// tree was built.
+    pub(crate) fn rebuilt_order(&self) -> Vec<usize> {
+        let mut order = (0..self.data.len())
+            .filter(|i| !self.is_removed(*i))
+            .collect::<Vec<_>>();
+        if self.indexed < self.data.len() || self.inserted > 0 || self.tombstones > 0 {
+            let dimension = self.records_dimension();
+            build_tree(&self.data, &mut order, 0, 0, dimension, self.min_points);
+        }
+        order
+    }
+    /// Number of coordinates of the records, which a tree built without any
+    /// only learns from the first record added.
+    pub(crate) fn records_dimension(&self) -> usize {
+        if self.dimension == 0 {
+            self.data.first().map_or(0, |d| d.point.shape())
+        } else {
+            self.dimension
+        }
+    }
+    /// Box enclosing every record, `None` if the tree is empty. It grows as
+    /// records are added or moved, and may be larger than the current
+    /// records until the next rebuild, since removed or moved records do not
+    /// shrink it.
+    pub fn bounds(&self) -> Option<&BoundingBox> {
+        self.extent.as_ref()
+    }
+    /// Records smaller than this are not split any further.
+    pub fn min_points(&self) -> usize {
+        self.min_points
+    }
+    /// All records, in the order the tree stores them. The tree's structure
+    /// follows from this order and `min_points` alone, every split record is
+    /// the median of its range, and ranges below `min_points` are leaves.
+    /// Records inserted with [`KDTree::insert`] since the tree was last
+    /// built come after those, held apart in the leaves they fall in, and
+    /// records added with [`Extend`] that are not indexed yet come last.
+    pub fn records(&self) -> &[Data<T>] {
+        &self.data
     }
     pub fn get_root_node(&self) -> Result<&Node, ClosestError> {
         match &self.root_node {
             NodeOrDataPointer::Data(_) => Err(ClosestError::RootNodeIsData),
-            NodeOrDataPointer::Node(n) => Ok(&n),
+            NodeOrDataPointer::Node(n) => Ok(n),
         }
     }
-    fn get_data(&self, data_idx: usize) -> &Data<T> {
-        &self.data[data_idx]
+    /// The root of the tree. Records added with [`Extend`] that are not
+    /// indexed yet are not under it.
+    pub fn root(&self) -> TreeNode<'_, T> {
+        TreeNode {
+            tree: self,
+            node: &self.root_node,
+            depth: 0,
+            start: 0,
+            stop: self.indexed,
+        }
+    }
+    /// Iterate over every node in the tree, parents before their children.
+    pub fn nodes(&self) -> impl Iterator<Item = TreeNode<'_, T>> {
+        let mut stack = vec![self.root()];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            if let Some((left, right)) = node.children() {
+                stack.push(right);
+                stack.push(left);
+            }
+            Some(node)
+        })
+    }
+    /// Positions of the records inserted into the leaf whose range starts at
+    /// `start`.
+    #[inline(always)]
+    pub(crate) fn leaf_overflow(&self, start: usize) -> &[usize] {
+        if self.inserted == 0 {
+            return &[];
+        }
+        self.overflow.get(&start).map_or(&[], Vec::as_slice)
+    }
+    /// Positions of the records added with [`Extend`] and not indexed yet,
+    /// which every query scans.
+    pub(crate) fn unindexed(&self) -> std::ops::Range<usize> {
+        self.indexed + self.inserted..self.data.len()
+    }
+    /// Whether the record at a position was removed, but is still stored.
+    #[inline(always)]
+    pub(crate) fn is_removed(&self, data_pointer: usize) -> bool {
+        !self.removed.is_empty() && *at(&self.removed, data_pointer)
+    }
+    pub(crate) fn get_data(&self, data_idx: usize) -> &Data<T> {
+        at(&self.data, data_idx)
     }
-    fn get_data_point(&self, data_idx: usize) -> &Point {
+    pub(crate) fn get_data_point(&self, data_idx: usize) -> &Point {
         &self.get_data(data_idx).point
     }
-    /// Get k nearest neighbors to a given point.
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
     pub fn get_nearest_neighbors<D: DistanceMetric>(
         &self,
         point: &Point,
         k: usize,
         distance_metric: &D,
     ) -> Vec<Neighbor<T>> {
-        let mut heap = BinaryHeap::new();
-        self.nearest_neighbors(point, k, &self.root_node, 0, &mut heap, distance_metric);
-        heap.into_iter()
-            .map(|r| r.as_neighbor(&self.data))
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector.into_neighbors(&self.data)
+    }
+    /// Get up to k nearest neighbors to a given point, ordered from nearest
+    /// to farthest, leaving out records farther than `max_distance`, in the
+    /// units of the metric, so fewer than k may be returned. Without a
+    /// maximum this is [`KDTree::get_nearest_neighbors`].
+    pub fn get_nearest_neighbors_within<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        max_distance: Option<f32>,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector =
+            NearestCollector::with_max_distance(k, max_distance.unwrap_or(f32::INFINITY));
+        self.search(point, &mut collector, distance_metric);
+        collector.into_neighbors(&self.data)
+    }
+    /// Like [`KDTree::get_nearest_neighbors`], but returns an error instead
+    /// of an empty or meaningless result when the tree is empty, `k` is 0,
+    /// or the point does not have the tree's dimension or has coordinates
+    /// that are not finite.
+    pub fn try_get_nearest_neighbors<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_nearest_neighbors(point, k, distance_metric))
+    }
+    /// Like [`KDTree::get_nearest_neighbors_within`], with the checks of
+    /// [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_get_nearest_neighbors_within<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        max_distance: Option<f32>,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_nearest_neighbors_within(point, k, max_distance, distance_metric))
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest. The radius is in the units of the metric,
+    /// squared for [`SquaredEuclideanDistance`].
+    pub fn get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point, &mut collector, distance_metric);
+        collector.into_neighbors(&self.data)
+    }
+    /// Like [`KDTree::get_nearest_neighbors`], but with true distances, such
+    /// as the euclidean distance rather than its square, see
+    /// [`DistanceMetric::to_true_distance`]. The search itself still runs on
+    /// the cheaper reduced distances.
+    pub fn get_nearest_neighbors_true_distance<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let mut found = self.get_nearest_neighbors(point, k, distance_metric);
+        for neighbor in &mut found {
+            neighbor.distance = distance_metric.to_true_distance(neighbor.distance);
+        }
+        found
+    }
+    /// Like [`KDTree::get_neighbors_within_radius`], but with the radius and
+    /// the distances returned as true distances.
+    pub fn get_neighbors_within_true_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Vec<Neighbor<T>> {
+        let reduced = distance_metric.to_reduced_distance(radius);
+        let mut found = self.get_neighbors_within_radius(point, reduced, distance_metric);
+        for neighbor in &mut found {
+            neighbor.distance = distance_metric.to_true_distance(neighbor.distance);
+        }
+        found
+    }
+    /// Like [`KDTree::get_nearest_neighbors_true_distance`], with the checks
+    /// of [`KDTree::try_get_nearest_neighbors`].
+    pub fn try_get_nearest_neighbors_true_distance<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        if k == 0 {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_nearest_neighbors_true_distance(point, k, distance_metric))
+    }
+    /// Like [`KDTree::get_neighbors_within_true_radius`], with the checks of
+    /// [`KDTree::try_get_neighbors_within_radius`].
+    pub fn try_get_neighbors_within_true_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_neighbors_within_true_radius(point, radius, distance_metric))
+    }
+    /// Number of records within `radius` of a given point, counted without
+    /// allocating or cloning anything.
+    pub fn count_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> usize {
+        let mut collector = CountCollector { radius, count: 0 };
+        self.search(point, &mut collector, distance_metric);
+        collector.count
+    }
+    /// Like [`KDTree::count_within_radius`], but returns an error when the
+    /// tree is empty, or the point does not have the tree's dimension or has
+    /// coordinates that are not finite.
+    pub fn try_count_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Result<usize, ClosestError> {
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.count_within_radius(point, radius, distance_metric))
+    }
+    /// Like [`KDTree::get_neighbors_within_radius`], but returns an error
+    /// when the tree is empty, or the point does not have the tree's
+    /// dimension or has coordinates that are not finite.
+    pub fn try_get_neighbors_within_radius<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        radius: f32,
+        distance_metric: &D,
+    ) -> Result<Vec<Neighbor<T>>, ClosestError> {
+        self.check_query(point.as_slice(), distance_metric)?;
+        Ok(self.get_neighbors_within_radius(point, radius, distance_metric))
+    }
+    /// Get the data of every record inside the box between `min` and `max`,
+    /// boundaries included, in the order the tree stores them. Branches
+    /// whose region misses the box are never visited, and no distances are
+    /// computed.
+    pub fn query_box(&self, min: &Point, max: &Point) -> Vec<T> {
+        let bounds = BoundingBox {
+            min: min.clone(),
+            max: max.clone(),
+        };
+        let mut collector = BoxCollector::new(&bounds, &self.data);
+        self.search(min, &mut collector, &Unpruned {});
+        let mut found = collector.found;
+        found.sort_unstable();
+        found
+            .into_iter()
+            .map(|i| self.data[i].data.clone())
+            .collect()
+    }
+    /// Like [`KDTree::query_box`], but returns an error when the tree is
+    /// empty, or `min` and `max` do not both have the tree's dimension.
+    pub fn try_query_box(&self, min: &Point, max: &Point) -> Result<Vec<T>, ClosestError> {
+        if self.data.is_empty() {
+            return Err(ClosestError::EmptyTree);
+        }
+        let dimension = self.records_dimension();
+        if min.shape() != dimension || max.shape() != dimension {
+            return Err(ClosestError::DifferingPositionLength);
+        }
+        Ok(self.query_box(min, max))
+    }
+    /// Check the tree has records, and `point` has the tree's dimension and
+    /// finite coordinates, and `distance_metric` is made for that dimension.
+    pub(crate) fn check_query<D: DistanceMetric>(
+        &self,
+        point: &[f32],
+        distance_metric: &D,
+    ) -> Result<(), ClosestError> {
+        if self.data.is_empty() {
+            return Err(ClosestError::EmptyTree);
+        }
+        check_point(point, self.records_dimension(), distance_metric)
+    }
+    /// Distances to the k nearest neighbors of a given point, ordered from
+    /// nearest to farthest. Skips cloning the records' data.
+    pub fn k_nearest_distances<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<f32> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| r.distance)
+            .collect()
+    }
+    /// Positions, in the data the tree was built from, of the k nearest
+    /// neighbors of a given point, ordered from nearest to farthest. Skips
+    /// cloning the records' data.
+    pub fn k_nearest_indices<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<usize> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| self.data[r.data_pointer].id)
             .collect()
     }
-    fn nearest_neighbors<D: DistanceMetric>(
+    /// Get the k nearest neighbors to a given point, keeping at most one
+    /// record for each key returned by `key`. The search continues until k
+    /// distinct keys are found, and the nearest record of each key is
+    /// returned, ordered from nearest to farthest.
+    pub fn get_nearest_neighbors_distinct_by<D, K, F>(
         &self,
         point: &Point,
         k: usize,
+        distance_metric: &D,
+        key: F,
+    ) -> Vec<Neighbor<T>>
+    where
+        D: DistanceMetric,
+        K: Hash + Eq,
+        F: Fn(&T) -> K,
+    {
+        let mut collector = DistinctCollector::new(k, &self.data, key);
+        self.search(point, &mut collector, distance_metric);
+        let mut best = collector.best.into_values().collect::<Vec<RawNeighbor>>();
+        best.sort();
+        best.into_iter()
+            .map(|r| r.into_neighbor(&self.data))
+            .collect()
+    }
+    /// Panic unless `point` has as many coordinates as the records, and
+    /// `distance_metric` is made for that many. Searches check this once,
+    /// before reading coordinates by axis.
+    pub(crate) fn assert_searchable<D: DistanceMetric>(&self, point: &[f32], distance_metric: &D) {
+        if !self.data.is_empty() {
+            assert_point(point, self.records_dimension(), distance_metric);
+        }
+    }
+    /// Walk the tree, offering every record that could be closer than the
+    /// collector's bound, and falls in a region the collector accepts.
+    pub(crate) fn search<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        self.assert_searchable(point.as_slice(), distance_metric);
+        match &self.recorder {
+            Some(recorder) => {
+                let mut counting = CountingCollector {
+                    inner: collector,
+                    nodes_visited: 0,
+                    candidates: 0,
+                };
+                self.search_tree(point, &mut counting, distance_metric);
+                recorder.record(counting.nodes_visited, counting.candidates);
+            }
+            None => self.search_tree(point, collector, distance_metric),
+        }
+    }
+    fn search_tree<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        let mut region = if C::USES_REGIONS {
+            BoundingBox {
+                min: Point::new(vec![f32::NEG_INFINITY; self.dimension]),
+                max: Point::new(vec![f32::INFINITY; self.dimension]),
+            }
+        } else {
+            BoundingBox {
+                min: Point::new(Vec::new()),
+                max: Point::new(Vec::new()),
+            }
+        };
+        self.search_node(
+            point,
+            &self.root_node,
+            0,
+            &mut region,
+            collector,
+            distance_metric,
+        );
+        for data_pointer in self.unindexed() {
+            self.offer_record(point, data_pointer, collector, distance_metric);
+        }
+    }
+    #[inline(always)]
+    fn offer_record<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        data_pointer: usize,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        if self.is_removed(data_pointer) {
+            return;
+        }
+        let distance = distance_metric.distance(
+            point.as_slice(),
+            self.get_data_point(data_pointer).as_slice(),
+        );
+        collector.offer(distance, data_pointer);
+    }
+    fn search_node<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
         node: &NodeOrDataPointer,
         depth: usize,
-        heap: &mut BinaryHeap<RawNeighbor>,
+        region: &mut BoundingBox,
+        collector: &mut C,
         distance_metric: &D,
     ) {
+        collector.visit_node();
         match node {
             NodeOrDataPointer::Node(n) => {
-                let distance =
-                    distance_metric.distance(&point, self.get_data_point(n.data_pointer));
-                match heap.peek() {
-                    None => heap.push(RawNeighbor::new(distance, n.data_pointer)),
-                    Some(worst_neighbor) => {
-                        if distance < worst_neighbor.distance {
-                            if heap.len() >= k {
-                                heap.pop();
-                            }
-                            heap.push(RawNeighbor::new(distance, n.data_pointer))
-                        }
-                    }
+                if !self.is_removed(n.data_pointer) {
+                    let distance = distance_metric.distance(
+                        point.as_slice(),
+                        self.get_data_point(n.data_pointer).as_slice(),
+                    );
+                    collector.offer(distance, n.data_pointer);
                 }
                 let axis = depth % self.dimension;
-                let diff =
-                    point.coordinates[axis] - self.get_data_point(n.data_pointer).coordinates[axis];
-                let (close, away) = if diff <= 0. {
-                    (n.left.as_ref(), n.right.as_ref())
-                } else {
-                    (n.right.as_ref(), n.left.as_ref())
-                };
-                self.nearest_neighbors(point, k, close, depth + 1, heap, distance_metric);
-                if let Some(worst_neighbor) = heap.peek() {
-                    if diff.powi(2) < worst_neighbor.distance {
-                        self.nearest_neighbors(point, k, away, depth + 1, heap, distance_metric);
-                    }
+                let split_value = *at(&self.get_data_point(n.data_pointer).coordinates, axis);
+                let close_is_left = at(&point.coordinates, axis) - split_value <= 0.;
+                self.search_branch(
+                    point,
+                    n,
+                    depth,
+                    close_is_left,
+                    region,
+                    collector,
+                    distance_metric,
+                );
+                if distance_metric.min_distance_to_plane(point.as_slice(), axis, split_value)
+                    <= collector.bound()
+                {
+                    self.search_branch(
+                        point,
+                        n,
+                        depth,
+                        !close_is_left,
+                        region,
+                        collector,
+                        distance_metric,
+                    );
                 }
             }
             NodeOrDataPointer::Data((start, stop)) => {
-                let mut neighbor_candidates = (*start..*stop)
-                    .map(|data_pointer| {
-                        RawNeighbor::new(
-                            distance_metric.distance(&point, self.get_data_point(data_pointer)),
-                            data_pointer,
-                        )
-                    })
-                    .collect::<Vec<RawNeighbor>>();
-                // Add all candidates if we have enough space.
-                if k.saturating_sub(heap.len()) >= neighbor_candidates.len() {
-                    heap.extend(neighbor_candidates)
-                } else {
-                    // Sort in reverse order.
-                    neighbor_candidates.sort_unstable_by(|a, b| b.cmp(a));
-                    loop {
-                        match neighbor_candidates.pop() {
-                            None => break,
-                            Some(best_candidate) => {
-                                if heap.len() < k {
-                                    heap.push(best_candidate)
-                                } else {
-                                    if let Some(worst_neighbor) = heap.peek() {
-                                        if worst_neighbor > &best_candidate {
-                                            heap.pop();
-                                            heap.push(best_candidate)
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+                for data_pointer in *start..*stop {
+                    self.offer_record(point, data_pointer, collector, distance_metric);
+                }
+                for data_pointer in self.leaf_overflow(*start) {
+                    self.offer_record(point, *data_pointer, collector, distance_metric);
                 }
             }
         }
     }
+    /// Search one side of a split node, narrowing the region to that side
+    /// while doing so.
+    #[allow(clippy::too_many_arguments)]
+    fn search_branch<D: DistanceMetric, C: Collector>(
+        &self,
+        point: &Point,
+        node: &Node,
+        depth: usize,
+        left: bool,
+        region: &mut BoundingBox,
+        collector: &mut C,
+        distance_metric: &D,
+    ) {
+        if !C::USES_REGIONS {
+            let child = if left { &node.left } else { &node.right };
+            self.search_node(point, child, depth + 1, region, collector, distance_metric);
+            return;
+        }
+        let axis = depth % self.dimension;
+        let split_value = *at(&self.get_data_point(node.data_pointer).coordinates, axis);
+        let (child, side) = if left {
+            (node.left.as_ref(), &mut region.max)
+        } else {
+            (node.right.as_ref(), &mut region.min)
+        };
+        let previous = std::mem::replace(&mut side.coordinates[axis], split_value);
+        if collector.accepts_region(region) {
+            self.search_node(point, child, depth + 1, region, collector, distance_metric);
+        }
+        let side = if left {
+            &mut region.max
+        } else {
+            &mut region.min
+        };
+        side.coordinates[axis] = previous;
+    }
+}
+
+impl<T: Clone> FromIterator<Data<T>> for KDTree<T> {
+    /// Build a tree with the default leaf size.
+    fn from_iter<I: IntoIterator<Item = Data<T>>>(iter: I) -> Self {
+        KDTree::from_vec(iter.into_iter().collect(), DEFAULT_MIN_POINTS)
+            .expect("building a tree from a vector does not fail")
+    }
+}
+
+impl KDTree<()> {
+    /// Build a tree from coordinates alone, for when the data belonging to
+    /// each point is kept elsewhere. Records are identified by their position
+    /// in `points`, as returned by [`KDTree::k_nearest`].
+    pub fn from_points<P: Into<Point>, I: IntoIterator<Item = P>>(
+        points: I,
+        min_points: usize,
+    ) -> Result<Self, ClosestError> {
+        let data = points
+            .into_iter()
+            .map(|p| Data {
+                data: (),
+                point: p.into(),
+                id: 0,
+            })
+            .collect();
+        KDTree::from_vec(data, min_points)
+    }
+    /// Positions and distances of the k nearest neighbors to a given point,
+    /// ordered from nearest to farthest.
+    pub fn k_nearest<D: DistanceMetric>(
+        &self,
+        point: &Point,
+        k: usize,
+        distance_metric: &D,
+    ) -> Vec<(usize, f32)> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point, &mut collector, distance_metric);
+        collector
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| (self.data[r.data_pointer].id, r.distance))
+            .collect()
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for KDTree<T> {
+    /// Trees are equal when they store the same records in the same order,
+    /// with the same ones removed and the same ones inserted since they were
+    /// built, and the same leaf size, which means they also have the same
+    /// shape.
+    fn eq(&self, other: &Self) -> bool {
+        self.min_points == other.min_points
+            && self.indexed == other.indexed
+            && self.inserted == other.inserted
+            && self.data == other.data
+            && (0..self.data.len()).all(|i| self.is_removed(i) == other.is_removed(i))
+    }
+}
+
+impl<T: Clone> Default for KDTree<T> {
+    /// An empty tree with the default leaf size, records can be added with
+    /// [`Extend`].
+    fn default() -> Self {
+        KDTree::new(Vec::new())
+    }
+}
+
+impl<T: Clone> Extend<Data<T>> for KDTree<T> {
+    /// Append records, they are found by queries straight away, and the tree
+    /// is rebuilt once enough of them have been added, as set by its
+    /// [`ReindexPolicy`]. A tree built without records takes its dimension
+    /// from the first one. Records without the tree's dimension are skipped,
+    /// [`KDTree::insert`] rejects them with an error instead.
+    fn extend<I: IntoIterator<Item = Data<T>>>(&mut self, iter: I) {
+        for mut record in iter {
+            if self.dimension == 0 {
+                self.dimension = if self.data.is_empty() {
+                    record.point.shape()
+                } else {
+                    self.records_dimension()
+                };
+            }
+            if record.point.shape() != self.dimension {
+                continue;
+            }
+            record.id = self.next_id;
+            self.next_id += 1;
+            self.positions.insert(record.id, self.data.len());
+            match &mut self.extent {
+                Some(extent) => extent.include(&record.point),
+                None => self.extent = BoundingBox::from_points(std::iter::once(&record.point)),
+            }
+            self.data.push(record);
+            if !self.removed.is_empty() {
+                self.removed.push(false);
+            }
+        }
+        self.reindex_if_needed();
+    }
+}
+
+/// Coordinates of test record `i`, scattered over a 101 by 47 grid without
+/// repeating for the first 4747 records.
+#[cfg(test)]
+pub(crate) fn scattered_point(i: usize) -> Vec<f32> {
+    vec![(i * 37 % 101) as f32, (i * 13 % 47) as f32]
+}
+
+/// Coordinates of test record `i` on a grid 10 records wide.
+#[cfg(test)]
+pub(crate) fn grid_point(i: usize) -> Vec<f32> {
+    vec![(i % 10) as f32, (i / 10) as f32]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::distance::SquaredEuclideanDistance;
+    use crate::distance::{MinkowskiDistance, SquaredEuclideanDistance, WeightedEuclideanDistance};
 
+    // Kept as first written, lints included.
+    #[allow(clippy::single_range_in_vec_init, clippy::needless_borrow)]
     #[test]
     fn tree_build() {
         // This is a bad example, because this is lat lng, and so our distance
@@ -357,4 +1674,459 @@ mod tests {
         let nearest = tree.get_nearest_neighbors(&point, 1, &SquaredEuclideanDistance::default());
         assert_eq!(nearest[0].data, "orange");
     }
+
+    #[test]
+    fn point_conversions() {
+        let point = Point::from([237., 139., 69.]);
+        assert_eq!(point, Point::from(vec![237., 139., 69.]));
+        assert_eq!(point, Point::from_slice(&[237., 139., 69.]));
+        assert_eq!(point.as_slice(), &[237., 139., 69.]);
+        assert_eq!(point.coordinates(), point.as_slice());
+    }
+
+    #[test]
+    fn distances_and_indices() {
+        let data = (0..40)
+            .map(|i| Data::new(i, vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::new(vec![6., 2.5]);
+        let nearest = tree.get_nearest_neighbors(&point, 5, &metric);
+        let distances = tree.k_nearest_distances(&point, 5, &metric);
+        let indices = tree.k_nearest_indices(&point, 5, &metric);
+        assert_eq!(
+            nearest.iter().map(|n| n.distance).collect::<Vec<_>>(),
+            distances
+        );
+        // Payloads are the input positions, ties may come in either order.
+        for (index, distance) in indices.iter().zip(&distances) {
+            let i = *index;
+            let original = Point::new(vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32]);
+            assert_eq!(
+                metric.distance(point.as_slice(), original.as_slice()),
+                *distance
+            );
+        }
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let point = |i: usize| vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32];
+        let mut tree = (0..40)
+            .map(|i| Data::new(i, point(i)))
+            .collect::<KDTree<_>>();
+        tree.extend((40..45).map(|i| Data::new(i, point(i))));
+        assert_eq!(tree.indexed, 40);
+        let metric = SquaredEuclideanDistance::default();
+        let nearest = tree.get_nearest_neighbors(&Point::new(point(42)), 1, &metric);
+        assert_eq!(nearest[0].distance, 0.);
+        assert_eq!(
+            tree.k_nearest_indices(&Point::new(point(42)), 1, &metric),
+            vec![42]
+        );
+        tree.extend((45..100).map(|i| Data::new(i, point(i))));
+        assert_eq!(tree.indexed, 100);
+        assert_eq!(tree.root().records().count(), 100);
+
+        assert_eq!(tree.bounds(), tree.root().bounds().as_ref());
+
+        let mut tree = KDTree::default();
+        assert!(tree.bounds().is_none());
+        assert!(tree
+            .get_nearest_neighbors(&Point::new(point(0)), 1, &metric)
+            .is_empty());
+        tree.extend((0..3).map(|i| Data::new(i, point(i))));
+        let nearest = tree.get_nearest_neighbors(&Point::new(point(2)), 1, &metric);
+        assert_eq!(nearest[0].data, 2);
+        assert_eq!(tree.dimension, 2);
+        assert!(tree
+            .try_get_nearest_neighbors(&Point::new(point(2)), 1, &metric)
+            .is_ok());
+        let bounds = tree.bounds().unwrap();
+        assert_eq!(
+            (bounds.min.as_slice(), bounds.max.as_slice()),
+            (&[0., 0.][..], &[7., 6.][..])
+        );
+        // Removing a record only shrinks the bounds once the tree is rebuilt.
+        tree.set_reindex_policy(ReindexPolicy {
+            max_tombstone_fraction: 1.,
+            ..Default::default()
+        });
+        tree.delete(1);
+        assert_eq!(tree.bounds().unwrap().max.as_slice(), &[7., 6.]);
+        tree.compact();
+        assert_eq!(tree.bounds().unwrap().max.as_slice(), &[1., 6.]);
+        assert_eq!(KDTree::new(vec![Data::new(0, point(0))]).min_points(), 16);
+    }
+
+    #[test]
+    fn payload_free() {
+        let points = (0..30).map(|i| [(i * 7 % 13) as f32, (i * 3 % 11) as f32]);
+        let tree = KDTree::from_points(points, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let nearest = tree.k_nearest(&Point::from([(12 * 7 % 13) as f32, 3.]), 2, &metric);
+        assert_eq!(nearest[0], (12, 0.));
+        assert_eq!(nearest.len(), 2);
+        // Low dimensional points are stored inline.
+        assert!(!tree.records()[0].point().coordinates.spilled());
+        assert!(Point::new(vec![0.; 5]).coordinates.spilled());
+    }
+
+    #[test]
+    fn clone_and_compare() {
+        let data = (0..30)
+            .map(|i| Data::new(i, vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let mut copy = tree.clone();
+        assert_eq!(tree, copy);
+        assert_eq!(tree, KDTree::from_vec(data.clone(), 4).unwrap());
+        assert_ne!(tree, KDTree::from_vec(data, 8).unwrap());
+        copy.extend(std::iter::once(Data::new(30, vec![0., 0.])));
+        assert_ne!(tree, copy);
+        // Removed records still stored tell trees apart.
+        let mut removed = tree.clone();
+        removed.delete(3);
+        assert_ne!(tree, removed);
+    }
+
+    #[test]
+    fn permutation_applied_in_place() {
+        let mut data = vec!['a', 'b', 'c', 'd', 'e', 'f'];
+        let mut order = vec![3, 0, 4, 1, 2, 5];
+        apply_order(&mut data, &mut order);
+        assert_eq!(data, vec!['d', 'a', 'e', 'b', 'c', 'f']);
+        assert_eq!(order, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn checked_queries() {
+        let metric = SquaredEuclideanDistance::default();
+        let tree = KDTree::from_vec(vec![Data::new(0, vec![0., 0.])], 4).unwrap();
+        let point = Point::from([1., 1.]);
+        assert_eq!(
+            tree.try_get_nearest_neighbors(&point, 1, &metric)
+                .unwrap()
+                .len(),
+            1
+        );
+        let error = |tree: &KDTree<i32>, point: &Point, k: usize| {
+            tree.try_get_nearest_neighbors(point, k, &metric)
+                .unwrap_err()
+        };
+        assert!(matches!(
+            error(&tree, &point, 0),
+            ClosestError::ZeroNeighbors
+        ));
+        assert!(matches!(
+            error(&tree, &Point::from([1.]), 1),
+            ClosestError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            }
+        ));
+        assert!(matches!(
+            error(&tree, &Point::from([1., f32::NAN]), 1),
+            ClosestError::NonFiniteCoordinate { axis: 1 }
+        ));
+        assert!(matches!(
+            error(&KDTree::default(), &point, 1),
+            ClosestError::EmptyTree
+        ));
+        let weighted = WeightedEuclideanDistance::new(vec![1.; 3]);
+        assert!(matches!(
+            tree.try_get_nearest_neighbors(&point, 1, &weighted),
+            Err(ClosestError::DimensionMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn mismatched_dimensions() {
+        let records = vec![Data::new(0, vec![0., 0.]), Data::new(1, vec![1.])];
+        assert!(matches!(
+            KDTree::from_vec(records, 4),
+            Err(ClosestError::DifferingPositionLength)
+        ));
+        let mut tree = KDTree::from_vec(vec![Data::new(0, vec![0., 0.])], 4).unwrap();
+        tree.extend([Data::new(1, vec![1.]), Data::new(2, vec![2., 2.])]);
+        assert_eq!(tree.data.len(), 2);
+        assert!(tree.insert(Data::new(3, vec![1., 2., 3.])).is_err());
+
+        let short = Point::from([1.]);
+        let metric = SquaredEuclideanDistance::default();
+        let mismatch = |error| matches!(error, ClosestError::DimensionMismatch { found: 1, .. });
+        let query = crate::Query::new(short.clone(), SquaredEuclideanDistance::default()).k(1);
+        assert!(mismatch(tree.try_run(&query).unwrap_err()));
+        assert!(mismatch(tree.try_weighted_centroid(&query).unwrap_err()));
+        let mut out = [(0., 0); 1];
+        assert!(mismatch(
+            tree.try_k_nearest_into(short.as_slice(), &metric, &mut out)
+                .unwrap_err()
+        ));
+        assert!(mismatch(
+            tree.try_get_nearest_neighbors_bbf(&short, 1, 10, 10, &metric)
+                .unwrap_err()
+        ));
+        assert!(mismatch(
+            tree.try_get_nearest_neighbors_with_budget(&short, 1, 10, &metric)
+                .unwrap_err()
+        ));
+        assert!(mismatch(
+            tree.get_nearest_neighbors_from(&short, 1, &vec![0; 3], &metric)
+                .unwrap_err()
+        ));
+        let random = &mut crate::SeededRandom::new(1);
+        assert!(mismatch(
+            tree.try_sample_neighbors(&short, 1, 1., |_| 1., random, &metric)
+                .unwrap_err()
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "query points must have 2 coordinates, found 1")]
+    fn short_query() {
+        let data = (0..100)
+            .map(|i| Data::new(i, vec![i as f32, 0.]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        tree.get_nearest_neighbors(&Point::from([1.]), 3, &SquaredEuclideanDistance::default());
+    }
+
+    #[test]
+    fn box_query() {
+        let data = (0..500)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        let (min, max) = (Point::from([10., 5.]), Point::from([30., 12.]));
+        let mut found = tree.query_box(&min, &max);
+        found.sort_unstable();
+        let expected = data
+            .iter()
+            .filter(|d| {
+                let (x, y) = (d.point.point(0), d.point.point(1));
+                (10. ..=30.).contains(&x) && (5. ..=12.).contains(&y)
+            })
+            .map(|d| d.data)
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+        let mut found = tree.try_query_box(&min, &max).unwrap();
+        found.sort_unstable();
+        assert_eq!(found, expected);
+        assert!(matches!(
+            tree.try_query_box(&Point::from([10.]), &max),
+            Err(ClosestError::DifferingPositionLength)
+        ));
+        assert!(matches!(
+            tree.try_query_box(&min, &Point::from([30., 12., 1.])),
+            Err(ClosestError::DifferingPositionLength)
+        ));
+    }
+
+    #[test]
+    fn radius_search() {
+        let data = (0..500)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50., 20.]);
+        let mut expected = data
+            .iter()
+            .map(|d| metric.distance(point.as_slice(), d.point.as_slice()))
+            .filter(|distance| *distance <= 64.)
+            .collect::<Vec<_>>();
+        expected.sort_by(f32::total_cmp);
+        let found = tree.get_neighbors_within_radius(&point, 64., &metric);
+        assert_eq!(
+            found.iter().map(|n| n.distance).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(tree
+            .try_get_neighbors_within_radius(&Point::from([1.]), 64., &metric)
+            .is_err());
+
+        assert_eq!(
+            tree.count_within_radius(&point, 64., &metric),
+            expected.len()
+        );
+        assert_eq!(
+            tree.try_count_within_radius(&point, 64., &metric).unwrap(),
+            expected.len()
+        );
+        assert!(matches!(
+            tree.try_count_within_radius(&Point::from([1.]), 64., &metric),
+            Err(ClosestError::DimensionMismatch { .. })
+        ));
+        let capped = tree.get_nearest_neighbors_within(&point, 1000, Some(64.), &metric);
+        assert_eq!(
+            capped.iter().map(|n| n.distance).collect::<Vec<_>>(),
+            expected
+        );
+        let capped = tree.get_nearest_neighbors_within(&point, 3, Some(64.), &metric);
+        assert_eq!(
+            capped.iter().map(|n| n.distance).collect::<Vec<_>>(),
+            expected[..3]
+        );
+        assert_eq!(
+            tree.get_nearest_neighbors_within(&point, 3, None, &metric)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn true_distances() {
+        let data = (0..500)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 8).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50., 20.]);
+        let reduced = tree.get_nearest_neighbors(&point, 5, &metric);
+        let true_distance = tree.get_nearest_neighbors_true_distance(&point, 5, &metric);
+        for (r, t) in reduced.iter().zip(&true_distance) {
+            assert_eq!(r.data, t.data);
+            assert_eq!(r.distance.sqrt(), t.distance);
+        }
+        let found = tree.get_neighbors_within_true_radius(&point, 8., &metric);
+        assert_eq!(
+            found.len(),
+            tree.get_neighbors_within_radius(&point, 64., &metric).len()
+        );
+        assert!(found.iter().all(|n| n.distance <= 8.));
+        let checked = tree
+            .try_get_nearest_neighbors_true_distance(&point, 5, &metric)
+            .unwrap();
+        for (c, t) in checked.iter().zip(&true_distance) {
+            assert_eq!((c.data, c.distance), (t.data, t.distance));
+        }
+        let checked = tree
+            .try_get_neighbors_within_true_radius(&point, 8., &metric)
+            .unwrap();
+        assert_eq!(checked.len(), found.len());
+        assert!(matches!(
+            tree.try_get_nearest_neighbors_true_distance(&Point::from([1.]), 5, &metric),
+            Err(ClosestError::DimensionMismatch { .. })
+        ));
+        assert!(matches!(
+            tree.try_get_neighbors_within_true_radius(&Point::from([f32::NAN, 0.]), 8., &metric),
+            Err(ClosestError::NonFiniteCoordinate { axis: 0 })
+        ));
+
+        let metric = MinkowskiDistance::new(3.).unwrap();
+        assert!(
+            (metric.to_true_distance(metric.distance(&[0., 0.], &[1., 2.])) - 9f32.cbrt()).abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn from_parts() {
+        let data = (0..30)
+            .map(|i| Data::new(i, vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32]))
+            .collect::<Vec<_>>();
+        let coordinates = data.iter().flat_map(|d| d.point.coordinates.clone());
+        let tree = KDTree::from_parts((0..30).collect(), coordinates.collect(), 2, 4).unwrap();
+        assert_eq!(tree, KDTree::from_vec(data, 4).unwrap());
+        assert!(matches!(
+            KDTree::from_parts(vec![0, 1], vec![0., 1., 2.], 2, 4),
+            Err(ClosestError::CoordinateCountMismatch {
+                expected: 4,
+                found: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn distinct_neighbors() {
+        let data = vec![
+            Data::new(("cafe", 1), vec![0., 0.]),
+            Data::new(("cafe", 2), vec![0., 1.]),
+            Data::new(("cafe", 3), vec![1., 0.]),
+            Data::new(("grocer", 1), vec![3., 3.]),
+            Data::new(("grocer", 2), vec![1., 1.]),
+            Data::new(("bakery", 1), vec![5., 5.]),
+            Data::new(("bakery", 2), vec![-4., -4.]),
+            Data::new(("florist", 1), vec![9., 9.]),
+        ];
+        let tree = KDTree::from_vec(data, 1).unwrap();
+        let point = Point::new(vec![0., 0.]);
+        let nearest = tree.get_nearest_neighbors_distinct_by(
+            &point,
+            3,
+            &SquaredEuclideanDistance::default(),
+            |(chain, _)| *chain,
+        );
+        let found = nearest.iter().map(|n| n.data).collect::<Vec<_>>();
+        assert_eq!(found, vec![("cafe", 1), ("grocer", 2), ("bakery", 2)]);
+    }
+
+    #[test]
+    fn node_bounds() {
+        let data = (0..50)
+            .map(|i| Data::new(i, vec![(i * 7 % 13) as f32, (i * 3 % 11) as f32]))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data, 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::new(vec![4.5, 20.]);
+        assert_eq!(tree.root().records().count(), 50);
+        for node in tree.nodes() {
+            let bounds = node.bounds().unwrap();
+            let min = bounds.min_distance(&point, &metric);
+            let max = bounds.max_distance(&point, &metric);
+            for record in node.records() {
+                assert!(bounds.contains(&record.point));
+                let distance = metric.distance(point.as_slice(), record.point.as_slice());
+                assert!(min <= distance && distance <= max);
+            }
+        }
+        let leaves = tree.nodes().filter(|n| n.is_leaf());
+        assert_eq!(
+            leaves.map(|n| n.records().count()).sum::<usize>()
+                + tree.nodes().filter(|n| !n.is_leaf()).count(),
+            50
+        );
+        let mut tree = tree;
+        tree.insert(Data::new(50, vec![1000., 0.])).unwrap();
+        assert!(tree.inserted > 0);
+        assert_eq!(tree.root().records().count(), 51);
+        assert_eq!(tree.root().bounds().unwrap().max.point(0), 1000.);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let data = (0..100)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data, 4).unwrap();
+        tree.insert(Data::new(100, vec![50., 20.])).unwrap();
+        tree.delete(3);
+        let json = serde_json::to_string(&tree).unwrap();
+        let loaded: KDTree<usize> = serde_json::from_str(&json).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([50.5, 20.5]);
+        let found = |tree: &KDTree<usize>| {
+            tree.get_nearest_neighbors(&point, 10, &metric)
+                .iter()
+                .map(|n| (n.data, n.distance))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(&loaded), found(&tree));
+        assert_eq!(loaded.tombstones(), 1);
+        // Positions out of range are rejected rather than searched.
+        let corrupted = json.replace(r#""indexed":100"#, r#""indexed":500"#);
+        assert!(serde_json::from_str::<KDTree<usize>>(&corrupted).is_err());
+        let neighbors = tree.get_nearest_neighbors(&point, 1, &metric);
+        assert_eq!(
+            serde_json::to_string(&neighbors).unwrap(),
+            r#"[{"distance":0.5,"data":100}]"#
+        );
+    }
 }
@@ -0,0 +1,234 @@
+//! Leave one out cross validation of k nearest neighbor predictions, to pick
+//! k. Every record is predicted from its nearest other records, found with
+//! one search per record for the largest k, and the error is reported for
+//! every k asked for.
+use std::collections::HashMap;
+
+use crate::distance::DistanceMetric;
+use crate::error::ClosestError;
+use crate::tree::{KDTree, NearestCollector};
+
+impl<T: Clone> KDTree<T> {
+    /// Nearest other records of every live record, enough for the largest
+    /// of `ks`, after checking the values given per record and every k.
+    /// Records, and the neighbors of each, are given by their rank in id
+    /// order, the order values given per record follow.
+    fn validation_neighbors<D: DistanceMetric>(
+        &self,
+        values: usize,
+        ks: &[usize],
+        distance_metric: &D,
+    ) -> Result<Vec<Vec<(usize, f32)>>, ClosestError> {
+        let mut live = (0..self.data.len())
+            .filter(|position| !self.is_removed(*position))
+            .collect::<Vec<_>>();
+        if values != live.len() {
+            return Err(ClosestError::LengthMismatch {
+                expected: live.len(),
+                found: values,
+            });
+        }
+        if ks.contains(&0) {
+            return Err(ClosestError::ZeroNeighbors);
+        }
+        if live.len() < 2 {
+            return Err(ClosestError::NotEnoughSamples {
+                needed: 2,
+                found: live.len(),
+            });
+        }
+        let max_k = ks.iter().copied().max().unwrap_or(0);
+        live.sort_by_key(|position| self.data[*position].id);
+        let mut ranks = vec![0; self.data.len()];
+        for (rank, position) in live.iter().enumerate() {
+            ranks[*position] = rank;
+        }
+        Ok(live
+            .iter()
+            .map(|position| {
+                let mut collector = NearestCollector::new(max_k + 1);
+                self.search(&self.data[*position].point, &mut collector, distance_metric);
+                collector
+                    .into_sorted_vec()
+                    .into_iter()
+                    .filter(|n| n.data_pointer != *position)
+                    .take(max_k)
+                    .map(|n| (ranks[n.data_pointer], n.distance))
+                    .collect()
+            })
+            .collect())
+    }
+    /// Leave one out error rate of k nearest neighbor classification for
+    /// every k in `ks`, as `(k, error rate)`. `labels` holds the class of
+    /// every record not removed, ordered by id: the order of the data the
+    /// tree was built from without the removed records, followed by the
+    /// records added since in the order they were added. Every record is predicted by majority vote among its k nearest other
+    /// records, with ties going to the class of the nearest of the tied
+    /// classes' records. Records with fewer than k other records are
+    /// predicted from all of them.
+    pub fn loo_classification_error<D: DistanceMetric, I: IntoIterator<Item = usize>>(
+        &self,
+        labels: &[usize],
+        ks: I,
+        distance_metric: &D,
+    ) -> Result<Vec<(usize, f64)>, ClosestError> {
+        let ks = ks.into_iter().collect::<Vec<_>>();
+        let neighbors = self.validation_neighbors(labels.len(), &ks, distance_metric)?;
+        let mut errors = vec![0usize; ks.len()];
+        for (position, nearest) in neighbors.iter().enumerate() {
+            for (k, error) in ks.iter().zip(&mut errors) {
+                // Votes of every class, with the rank of its nearest record.
+                let mut votes = HashMap::new();
+                for (rank, (neighbor, _)) in nearest.iter().take(*k).enumerate() {
+                    votes.entry(labels[*neighbor]).or_insert((0, rank)).0 += 1;
+                }
+                let predicted = votes
+                    .into_iter()
+                    .max_by(|a, b| (a.1 .0, b.1 .1).cmp(&(b.1 .0, a.1 .1)))
+                    .map(|(class, _)| class);
+                if predicted != Some(labels[position]) {
+                    *error += 1;
+                }
+            }
+        }
+        Ok(ks
+            .into_iter()
+            .zip(errors)
+            .map(|(k, error)| (k, error as f64 / neighbors.len() as f64))
+            .collect())
+    }
+    /// Leave one out mean squared error of k nearest neighbor regression for
+    /// every k in `ks`, as `(k, mean squared error)`. `targets` holds the
+    /// value of every record not removed, in the order of the labels of
+    /// [`KDTree::loo_classification_error`], and every record is predicted by the mean value of its k
+    /// nearest other records.
+    pub fn loo_regression_error<D: DistanceMetric, I: IntoIterator<Item = usize>>(
+        &self,
+        targets: &[f32],
+        ks: I,
+        distance_metric: &D,
+    ) -> Result<Vec<(usize, f64)>, ClosestError> {
+        let ks = ks.into_iter().collect::<Vec<_>>();
+        let neighbors = self.validation_neighbors(targets.len(), &ks, distance_metric)?;
+        let mut errors = vec![0f64; ks.len()];
+        for (position, nearest) in neighbors.iter().enumerate() {
+            // Running sums of the nearest values, so every k costs one step.
+            let sums = nearest
+                .iter()
+                .scan(0f64, |sum, (neighbor, _)| {
+                    *sum += targets[*neighbor] as f64;
+                    Some(*sum)
+                })
+                .collect::<Vec<_>>();
+            for (k, error) in ks.iter().zip(&mut errors) {
+                let used = (*k).min(sums.len());
+                let predicted = sums[used - 1] / used as f64;
+                *error += (predicted - targets[position] as f64).powi(2);
+            }
+        }
+        Ok(ks
+            .into_iter()
+            .zip(errors)
+            .map(|(k, error)| (k, error / neighbors.len() as f64))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{scattered_point, Data};
+
+    #[test]
+    fn error_curves() {
+        // Two bands of classes along x, with every fifth record mislabeled.
+        let data = (0..100)
+            .map(|i| Data::new(i, scattered_point(i)))
+            .collect::<Vec<_>>();
+        let labels = data
+            .iter()
+            .enumerate()
+            .map(|(i, d)| usize::from(d.point.point(0) > 50.) ^ usize::from(i % 5 == 0))
+            .collect::<Vec<_>>();
+        let tree = KDTree::from_vec(data.clone(), 4).unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let curve = tree
+            .loo_classification_error(&labels, 1..=15, &metric)
+            .unwrap();
+        assert_eq!(curve.len(), 15);
+        assert_eq!(curve[0].0, 1);
+        // Voting over more neighbors outvotes the noise.
+        assert!(curve[14].1 < curve[0].1);
+        assert!(curve[14].1 < 0.25);
+
+        // A target that is a linear function of the coordinates.
+        let targets = data
+            .iter()
+            .map(|d| d.point.point(0) + 2. * d.point.point(1))
+            .collect::<Vec<_>>();
+        let curve = tree
+            .loo_regression_error(&targets, [1, 3, 99, 500], &metric)
+            .unwrap();
+        assert!(curve[1].1 < curve[2].1);
+        // Past the number of other records every prediction uses them all.
+        assert_eq!(curve[2].1, curve[3].1);
+
+        assert!(matches!(
+            tree.loo_regression_error(&targets[1..], [1], &metric),
+            Err(ClosestError::LengthMismatch { .. })
+        ));
+        assert!(matches!(
+            tree.loo_classification_error(&labels, 0..3, &metric),
+            Err(ClosestError::ZeroNeighbors)
+        ));
+    }
+
+    #[test]
+    fn after_removing_and_inserting() {
+        // Coordinates without ties, so neighbors come in the same order
+        // whatever order the records are stored in.
+        let data = (0..120)
+            .map(|i| {
+                let i = i as f32;
+                Data::new(
+                    i as usize,
+                    vec![(i * 0.731).sin() * 50., (i * 1.37).cos() * 20.],
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut tree = KDTree::from_vec(data[..100].to_vec(), 4).unwrap();
+        for id in [0, 17, 58, 99] {
+            tree.remove(id).unwrap();
+        }
+        for record in &data[100..] {
+            tree.insert(record.clone()).unwrap();
+        }
+        // The same records, built fresh in id order.
+        let live = data
+            .iter()
+            .filter(|d| ![0, 17, 58, 99].contains(&d.data))
+            .cloned()
+            .collect::<Vec<_>>();
+        let rebuilt = KDTree::from_vec(live.clone(), 4).unwrap();
+        let labels = live
+            .iter()
+            .map(|d| usize::from(d.point.point(0) > 0.) ^ usize::from(d.data % 7 == 0))
+            .collect::<Vec<_>>();
+        let metric = SquaredEuclideanDistance::default();
+        assert_eq!(
+            tree.loo_classification_error(&labels, 1..=9, &metric)
+                .unwrap(),
+            rebuilt
+                .loo_classification_error(&labels, 1..=9, &metric)
+                .unwrap()
+        );
+        assert!(matches!(
+            tree.loo_classification_error(&labels[1..], [1], &metric),
+            Err(ClosestError::LengthMismatch {
+                expected: 116,
+                found: 115
+            })
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::distance::DistanceMetric;
+use crate::tree::{Collector, Data, NearestCollector, Neighbor, Point, RadiusCollector};
+
+/// Vantage point tree, an index that only relies on distances between
+/// records, never on their coordinates. Records need not have the same
+/// number of coordinates, as long as the metric can compare them, such as
+/// sequences compared with [`DynamicTimeWarping`](crate::DynamicTimeWarping).
+///
+/// Each node splits its records into those within the median distance of a
+/// vantage record, and those beyond it. Pruning uses the triangle inequality,
+/// so results are exact for true metrics, such as euclidean distance (not
+/// squared), and approximate for other distances.
+#[derive(Debug)]
+pub struct VPTree<T: Clone, D: DistanceMetric> {
+    data: Vec<Data<T>>,
+    /// Median distance to the vantage record, for each record that is the
+    /// vantage record of a node.
+    thresholds: Vec<f32>,
+    leaf_size: usize,
+    distance_metric: D,
+}
+
+impl<T: Clone, D: DistanceMetric> VPTree<T, D> {
+    /// Build the tree. The first record of each range is its vantage record,
+    /// ranges of at most `leaf_size` records are scanned.
+    pub fn from_vec(mut data: Vec<Data<T>>, leaf_size: usize, distance_metric: D) -> Self {
+        let mut thresholds = vec![0.; data.len()];
+        build(
+            &mut data,
+            &mut thresholds,
+            leaf_size.max(1),
+            &distance_metric,
+        );
+        VPTree {
+            data,
+            thresholds,
+            leaf_size: leaf_size.max(1),
+            distance_metric,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// Get k nearest neighbors to a given point, ordered from nearest to
+    /// farthest.
+    pub fn get_nearest_neighbors(&self, point: &Point, k: usize) -> Vec<Neighbor<T>> {
+        let mut collector = NearestCollector::new(k);
+        self.search(point.as_slice(), 0, self.data.len(), &mut collector);
+        collector.into_neighbors(&self.data)
+    }
+    /// Get every record within `radius` of a given point, ordered from
+    /// nearest to farthest.
+    pub fn get_neighbors_within_radius(&self, point: &Point, radius: f32) -> Vec<Neighbor<T>> {
+        let mut collector = RadiusCollector::new(radius);
+        self.search(point.as_slice(), 0, self.data.len(), &mut collector);
+        collector.into_neighbors(&self.data)
+    }
+    fn search<C: Collector>(&self, point: &[f32], start: usize, stop: usize, collector: &mut C) {
+        let distance = |i: usize| {
+            self.distance_metric
+                .distance(point, self.data[i].point.as_slice())
+        };
+        if stop - start <= self.leaf_size {
+            for data_pointer in start..stop {
+                collector.offer(distance(data_pointer), data_pointer);
+            }
+            return;
+        }
+        let to_vantage = distance(start);
+        collector.offer(to_vantage, start);
+        let threshold = self.thresholds[start];
+        let (inside, outside) = split(start, stop);
+        if to_vantage <= threshold {
+            self.search(point, inside.0, inside.1, collector);
+            if to_vantage + collector.bound() >= threshold {
+                self.search(point, outside.0, outside.1, collector);
+            }
+        } else {
+            self.search(point, outside.0, outside.1, collector);
+            if to_vantage - collector.bound() <= threshold {
+                self.search(point, inside.0, inside.1, collector);
+            }
+        }
+    }
+}
+
+/// Ranges of the records within, and beyond, the threshold of the vantage
+/// record at `start`.
+fn split(start: usize, stop: usize) -> ((usize, usize), (usize, usize)) {
+    let middle = start + 1 + ((stop - start - 1) >> 1);
+    ((start + 1, middle), (middle, stop))
+}
+
+fn build<T: Clone, D: DistanceMetric>(
+    data: &mut [Data<T>],
+    thresholds: &mut [f32],
+    leaf_size: usize,
+    distance_metric: &D,
+) {
+    if data.len() <= leaf_size {
+        return;
+    }
+    let ((_, middle), _) = split(0, data.len());
+    let (vantage, rest) = data.split_first_mut().expect("range is not empty");
+    let distance =
+        |d: &Data<T>| distance_metric.distance(vantage.point.as_slice(), d.point.as_slice());
+    // Distances are never negative, so their bits sort in the same order.
+    rest.sort_by_cached_key(|d| distance(d).to_bits());
+    // Records up to the middle are within the threshold, the rest beyond it.
+    thresholds[0] = distance(&rest[middle - 2]);
+    let (inside, outside) = data[1..].split_at_mut(middle - 1);
+    let (inside_thresholds, outside_thresholds) = thresholds[1..].split_at_mut(middle - 1);
+    build(inside, inside_thresholds, leaf_size, distance_metric);
+    build(outside, outside_thresholds, leaf_size, distance_metric);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brute_force::BruteForce;
+    use crate::dtw::DynamicTimeWarping;
+    use crate::tree::scattered_point;
+
+    struct EuclideanDistance {}
+
+    impl DistanceMetric for EuclideanDistance {
+        fn distance(&self, p1: &[f32], p2: &[f32]) -> f32 {
+            p1.iter()
+                .zip(p2)
+                .map(|(a, b)| (a - b).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        }
+        fn min_distance_to_plane(&self, point: &[f32], axis: usize, value: f32) -> f32 {
+            (point[axis] - value).abs()
+        }
+    }
+
+    #[test]
+    fn vp_tree_matches_brute_force() {
+        let data = || {
+            (0..300)
+                .map(|i| Data::new(i, scattered_point(i)))
+                .collect::<Vec<_>>()
+        };
+        let tree = VPTree::from_vec(data(), 4, EuclideanDistance {});
+        let brute_force = BruteForce::from_vec(data());
+        for i in 0..30 {
+            let point = Point::new(vec![i as f32 * 3.3, i as f32 * 1.7]);
+            let distances =
+                |n: Vec<Neighbor<usize>>| n.iter().map(|n| n.distance).collect::<Vec<_>>();
+            assert_eq!(
+                distances(tree.get_nearest_neighbors(&point, 5)),
+                distances(brute_force.get_nearest_neighbors(&point, 5, &EuclideanDistance {}))
+            );
+            assert_eq!(
+                distances(tree.get_neighbors_within_radius(&point, 9.)),
+                distances(brute_force.get_neighbors_within_radius(
+                    &point,
+                    9.,
+                    &EuclideanDistance {}
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn sequences_of_any_length() {
+        let sequences = (1..40)
+            .map(|n| {
+                Data::new(
+                    n,
+                    (0..n).map(|i| (i as f32 / n as f32 * 6.).sin()).collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let tree = VPTree::from_vec(sequences, 4, DynamicTimeWarping::default());
+        let query = Point::new((0..25).map(|i| (i as f32 / 25. * 6.).sin()).collect());
+        let nearest = tree.get_nearest_neighbors(&query, 1);
+        assert_eq!(nearest[0].data, 25);
+    }
+}
@@ -0,0 +1,76 @@
+//! Warming up indexes after loading, so the first queries do not pay for
+//! page faults, as they do when the index is read lazily from a memory
+//! mapped file or has been swapped out.
+use std::hint::black_box;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::frozen::FrozenKDTree;
+use crate::tree::KDTree;
+
+/// Smallest page size in common use, reading a value this often touches
+/// every page.
+const PAGE_SIZE: usize = 4096;
+
+/// Read a value from every page `values` spans.
+pub(crate) fn touch_pages<V: Copy>(values: &[V]) {
+    let stride = (PAGE_SIZE / std::mem::size_of::<V>().max(1)).max(1);
+    for value in values.iter().step_by(stride) {
+        black_box(*value);
+    }
+    if let Some(last) = values.last() {
+        black_box(*last);
+    }
+}
+
+impl<T: Clone> KDTree<T> {
+    /// Touch every node and the coordinates of every record, so they are in
+    /// memory before the first query.
+    pub fn warm(&self) {
+        black_box(self.nodes().count());
+        for record in &self.data {
+            touch_pages(record.point.as_slice());
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> KDTree<T> {
+    /// [`KDTree::warm`] on another thread, so the caller can start serving
+    /// queries right away. Join the handle to wait until the tree is warm.
+    pub fn warm_in_background(self: Arc<Self>) -> JoinHandle<()> {
+        thread::spawn(move || self.warm())
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> FrozenKDTree<T> {
+    /// [`FrozenKDTree::warm`] on another thread, so the caller can start
+    /// serving queries right away. Join the handle to wait until the tree is
+    /// warm.
+    pub fn warm_in_background(self: Arc<Self>) -> JoinHandle<()> {
+        thread::spawn(move || self.warm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance::SquaredEuclideanDistance;
+    use crate::tree::{Data, Point};
+
+    #[test]
+    fn warm_up() {
+        let data = (0..5000)
+            .map(|i| Data::new(i, vec![(i % 71) as f32, (i % 67) as f32]))
+            .collect::<Vec<_>>();
+        let tree = Arc::new(KDTree::from_vec(data, 8).unwrap());
+        tree.clone().warm_in_background().join().unwrap();
+        let frozen = Arc::new(KDTree::clone(&tree).freeze());
+        frozen.clone().warm_in_background().join().unwrap();
+        let metric = SquaredEuclideanDistance::default();
+        let point = Point::from([3., 4.]);
+        assert_eq!(
+            tree.get_nearest_neighbors(&point, 1, &metric)[0].distance,
+            frozen.get_nearest_neighbors(&point, 1, &metric)[0].distance
+        );
+    }
+}